@@ -0,0 +1,62 @@
+//! Benchmarks looking up entries by id with [`Maildir::find`].
+//!
+//! `find` only builds entries whose raw file name starts with the
+//! searched id. This is compared to a naive lookup, which builds and
+//! parses every entry. Run with `cargo bench --bench find`.
+
+use std::{
+    hint::black_box,
+    time::{Duration, Instant},
+};
+
+use maildirs::{Flag, Maildir, MaildirBuilder};
+use tempfile::tempdir;
+
+/// The number of entries of the benchmarked Maildir.
+static ENTRIES: usize = 20_000;
+
+/// The number of lookups per benchmark.
+static ITERATIONS: u32 = 50;
+
+fn main() {
+    let root = tempdir().unwrap();
+    let mdir = MaildirBuilder::new()
+        .with_size_field(true)
+        .build(root.path());
+    mdir.create_all().unwrap();
+
+    for i in 0..ENTRIES {
+        let flags = if i % 2 == 0 { vec![Flag::Seen] } else { vec![] };
+        mdir.write_cur(b"Subject: bench\r\n\r\nbody", flags)
+            .unwrap();
+    }
+
+    let last = mdir.read().unwrap().last().unwrap().unwrap();
+    let last = last.id().unwrap().to_owned();
+
+    bench("find missing id", || {
+        mdir.find("missing").unwrap().is_some()
+    });
+    bench("find existing id", || mdir.find(&last).unwrap().is_some());
+    bench("naive missing id", || naive_find(&mdir, "missing"));
+    bench("naive existing id", || naive_find(&mdir, &last));
+}
+
+/// Finds the entry matching the given id by parsing every entry.
+fn naive_find(mdir: &Maildir, id: &str) -> bool {
+    mdir.read()
+        .unwrap()
+        .filter_map(Result::ok)
+        .any(|entry| entry.id().ok() == Some(id))
+}
+
+fn bench(name: &str, f: impl Fn() -> bool) {
+    let start = Instant::now();
+
+    for _ in 0..ITERATIONS {
+        black_box(f());
+    }
+
+    let elapsed: Duration = start.elapsed() / ITERATIONS;
+    println!("{name:<20} {elapsed:>10.2?} per lookup ({ENTRIES} entries)");
+}
@@ -25,6 +25,26 @@ pub enum Error {
     RemoveMaildirByNameNotFoundError(String),
     #[error("cannot find maildir entry matching {0}")]
     GetMaildirEntryNotFoundError(String),
+    #[error("invalid maildir info separator {0:?}")]
+    InvalidInfoSeparatorError(String),
+    #[error("cannot find unique file name for delivery in {0}")]
+    DeliveryRetryExhaustedError(PathBuf),
+    #[error("cannot change flags of maildir entry concurrently modified at {0}")]
+    ConcurrentModificationError(PathBuf),
+    #[error("cannot change owner of {1}, the process may lack privileges")]
+    ChangeOwnerError(#[source] io::Error, PathBuf),
+    #[error("cannot remove maildir directory {1}")]
+    RemoveMaildirError(#[source] io::Error, PathBuf),
+    #[error("cannot create maildir {1}")]
+    CreateMaildirError(#[source] Box<Error>, String),
+    #[error("cannot parse maildir uid list at {0}")]
+    ParseUidlistError(PathBuf),
+    #[error("cannot parse maildir quota at {0}")]
+    ParseQuotaError(PathBuf),
+    #[error("cannot parse maildir keywords at {0}")]
+    ParseKeywordsError(PathBuf),
+    #[error("cannot allocate keyword, all keywords are used at {0}")]
+    KeywordsExhaustedError(PathBuf),
 
     #[error("cannot find email {0}")]
     FindEmailError(String),
@@ -34,10 +54,20 @@ pub enum Error {
     NoParentError(PathBuf),
     #[error("invalid id {0}")]
     InvalidIdError(String),
-    #[error("invalid folder {0}")]
-    InvalidFolderError(String),
+    #[error("invalid folder {0}: {1}")]
+    InvalidFolderError(String, &'static str),
+    #[error("invalid envelope sender {0:?}")]
+    InvalidEnvelopeSenderError(String),
+    #[error("invalid maildir entry info at {0}")]
+    InvalidInfoError(PathBuf),
     #[error("invalid flag {0}")]
     InvalidFlagError(char),
+    #[error("invalid flag {0:?}: expected a single letter")]
+    InvalidFlagsError(String),
+    #[error("invalid keyword {0:?}")]
+    InvalidKeywordError(String),
+    #[error("invalid flag {0}: lowercase letters are reserved for keywords, did you mean {1}?")]
+    LowercaseFlagError(char, char),
     #[error("{0} already exists")]
     AlreadyExistsError(PathBuf),
     #[error(transparent)]
@@ -45,5 +75,10 @@ pub enum Error {
     #[error(transparent)]
     IoError(#[from] io::Error),
     #[error(transparent)]
+    WalkDirError(#[from] walkdir::Error),
+    #[error(transparent)]
     SystemTimeError(#[from] SystemTimeError),
+    #[cfg(feature = "watch")]
+    #[error(transparent)]
+    WatchError(#[from] notify::Error),
 }
@@ -1,14 +1,105 @@
+use std::{collections::HashSet, fmt, str::FromStr};
+
 use crate::{Error, Result};
 
 /// Represents a maildir flag.
-#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+///
+/// Flags are ordered in the canonical Maildir order, which is the
+/// ASCII order of their letters: `D`, `F`, `P`, `R`, `S` then `T`.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
 pub enum Flag {
+    /// `D`: the message is a draft.
+    Draft,
+    /// `F`: the message is flagged for urgent or special attention.
+    Flagged,
+    /// `P`: the message has been passed on, which means forwarded,
+    /// resent or bounced. This is not the same as replied.
     Passed,
+    /// `R`: the message has been replied to.
     Replied,
+    /// `S`: the message has been seen.
     Seen,
+    /// `T`: the message is marked for deletion.
     Trashed,
-    Draft,
-    Flagged,
+}
+
+impl Flag {
+    /// Returns all the flags, in the canonical Maildir order.
+    pub fn all() -> [Flag; 6] {
+        [
+            Flag::Draft,
+            Flag::Flagged,
+            Flag::Passed,
+            Flag::Replied,
+            Flag::Seen,
+            Flag::Trashed,
+        ]
+    }
+
+    /// Returns a human readable label of the flag, for user
+    /// interfaces.
+    pub fn description(&self) -> &'static str {
+        match self {
+            Flag::Draft => "Draft",
+            Flag::Flagged => "Flagged",
+            Flag::Passed => "Forwarded",
+            Flag::Replied => "Replied",
+            Flag::Seen => "Seen",
+            Flag::Trashed => "Trashed",
+        }
+    }
+
+    /// Returns the IMAP name of the flag, like `\Seen`.
+    ///
+    /// [`Flag::Replied`] maps to `\Answered` and [`Flag::Trashed`] to
+    /// `\Deleted`. IMAP has no system flag for [`Flag::Passed`], which
+    /// maps to the `$Forwarded` keyword instead.
+    pub fn to_imap(&self) -> &'static str {
+        match self {
+            Flag::Draft => "\\Draft",
+            Flag::Flagged => "\\Flagged",
+            Flag::Passed => "$Forwarded",
+            Flag::Replied => "\\Answered",
+            Flag::Seen => "\\Seen",
+            Flag::Trashed => "\\Deleted",
+        }
+    }
+
+    /// Parses a flag from its IMAP name, case-insensitively.
+    ///
+    /// See [`Flag::to_imap`]. Returns `None` for names that do not
+    /// match any flag, like `\Recent` or custom keywords.
+    pub fn from_imap(name: &str) -> Option<Flag> {
+        Flag::all()
+            .into_iter()
+            .find(|flag| flag.to_imap().eq_ignore_ascii_case(name))
+    }
+
+    /// Returns the bit of the flag, for compact storage of flag sets.
+    ///
+    /// Bits follow the canonical Maildir order, and are a stable part
+    /// of the API:
+    ///
+    /// | Flag               | Bit    |
+    /// |--------------------|--------|
+    /// | [`Flag::Draft`]    | `0x01` |
+    /// | [`Flag::Flagged`]  | `0x02` |
+    /// | [`Flag::Passed`]   | `0x04` |
+    /// | [`Flag::Replied`]  | `0x08` |
+    /// | [`Flag::Seen`]     | `0x10` |
+    /// | [`Flag::Trashed`]  | `0x20` |
+    ///
+    /// See [`flags_to_bits`] and [`flags_from_bits`].
+    pub fn bit(&self) -> u8 {
+        match self {
+            Flag::Draft => 0x01,
+            Flag::Flagged => 0x02,
+            Flag::Passed => 0x04,
+            Flag::Replied => 0x08,
+            Flag::Seen => 0x10,
+            Flag::Trashed => 0x20,
+        }
+    }
 }
 
 impl AsRef<str> for Flag {
@@ -24,6 +115,27 @@ impl AsRef<str> for Flag {
     }
 }
 
+/// Displays the letter of the flag.
+impl fmt::Display for Flag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_ref())
+    }
+}
+
+/// Parses a flag from its letter.
+impl FromStr for Flag {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut chars = s.chars();
+
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => Flag::try_from(c),
+            _ => Err(Error::InvalidFlagsError(s.to_owned())),
+        }
+    }
+}
+
 impl TryFrom<char> for Flag {
     type Error = Error;
 
@@ -35,7 +147,43 @@ impl TryFrom<char> for Flag {
             'T' => Ok(Flag::Trashed),
             'D' => Ok(Flag::Draft),
             'F' => Ok(Flag::Flagged),
+            // lowercase letters are keywords, but the lowercase form
+            // of a standard flag is most likely a typo
+            'p' | 'r' | 's' | 't' | 'd' | 'f' => {
+                Err(Error::LowercaseFlagError(c, c.to_ascii_uppercase()))
+            }
             _ => Err(Error::InvalidFlagError(c)),
         }
     }
 }
+
+/// Formats the given flags as a string of letters, in the canonical
+/// Maildir order, like `FRS`.
+pub fn flags_to_string(flags: &HashSet<Flag>) -> String {
+    let mut flags: Vec<&Flag> = flags.iter().collect();
+    flags.sort();
+    flags.into_iter().map(Flag::as_ref).collect()
+}
+
+/// Parses flags from a string of letters, like `FRS`.
+///
+/// Characters that do not match any flag, like keywords, are
+/// ignored.
+pub fn flags_from_str(s: &str) -> HashSet<Flag> {
+    s.chars().filter_map(|c| Flag::try_from(c).ok()).collect()
+}
+
+/// Packs the given flags into bits, see [`Flag::bit`].
+pub fn flags_to_bits(flags: &HashSet<Flag>) -> u8 {
+    flags.iter().fold(0, |bits, flag| bits | flag.bit())
+}
+
+/// Unpacks flags from bits, see [`Flag::bit`].
+///
+/// Bits that do not match any flag are ignored.
+pub fn flags_from_bits(bits: u8) -> HashSet<Flag> {
+    Flag::all()
+        .into_iter()
+        .filter(|flag| bits & flag.bit() != 0)
+        .collect()
+}
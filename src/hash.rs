@@ -0,0 +1,63 @@
+use std::{
+    collections::HashMap,
+    io::{self, Read},
+};
+
+use sha2::{Digest, Sha256};
+
+use crate::{Maildir, MaildirEntry, Result};
+
+impl MaildirEntry {
+    /// Computes the SHA-256 hash of the contents of the current
+    /// entry.
+    ///
+    /// The file is streamed in chunks, so that large entries are
+    /// never loaded into memory. Two entries with the same hash have
+    /// the same contents, whatever their file names.
+    pub fn content_hash(&self) -> Result<[u8; 32]> {
+        let mut file = self.open()?;
+        let mut hasher = Sha256::new();
+        let mut buffer = [0; 8192];
+
+        loop {
+            match file.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(len) => hasher.update(&buffer[..len]),
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err.into()),
+            }
+        }
+
+        Ok(hasher.finalize().into())
+    }
+}
+
+impl Maildir {
+    /// Groups entries of the current Maildir having identical
+    /// contents, see [`MaildirEntry::content_hash`].
+    ///
+    /// Only groups of at least two entries are returned. Entries of
+    /// a group are sorted by path, and groups by the path of their
+    /// first entry.
+    pub fn find_duplicates(&self) -> Result<Vec<Vec<MaildirEntry>>> {
+        let mut groups: HashMap<[u8; 32], Vec<MaildirEntry>> = HashMap::new();
+
+        for entry in self.read()? {
+            let entry = entry?;
+            groups.entry(entry.content_hash()?).or_default().push(entry);
+        }
+
+        let mut duplicates: Vec<Vec<MaildirEntry>> = groups
+            .into_values()
+            .filter(|group| group.len() > 1)
+            .map(|mut group| {
+                group.sort_by(|a, b| a.path().cmp(b.path()));
+                group
+            })
+            .collect();
+
+        duplicates.sort_by(|a, b| a[0].path().cmp(b[0].path()));
+
+        Ok(duplicates)
+    }
+}
@@ -0,0 +1,110 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::{lock::lock_sibling, maildir::write_atomically, Error, Maildir, Result};
+
+/// The name of the keywords file, located at the root of the Maildir.
+static DOVECOT_KEYWORDS: &str = "dovecot-keywords";
+
+/// The maximum number of keywords, one per lowercase letter.
+static MAX_KEYWORDS: u8 = 26;
+
+impl Maildir {
+    /// Reads the keywords of the current Maildir from its
+    /// `dovecot-keywords` file.
+    ///
+    /// Keywords are custom flags stored as lowercase letters in entry
+    /// file names: the letter `a` stands for the keyword of index 0,
+    /// `b` for the keyword of index 1, and so on. Returns an empty
+    /// list when the Maildir has no `dovecot-keywords` file.
+    pub fn keywords(&self) -> Result<Vec<(u8, String)>> {
+        read_keywords(&keywords_path(self.path()))
+    }
+}
+
+/// Returns the path of the keywords file of the Maildir located at
+/// the given root.
+pub(crate) fn keywords_path(root: &Path) -> PathBuf {
+    root.join(DOVECOT_KEYWORDS)
+}
+
+/// Reads the keywords file at the given path.
+pub(crate) fn read_keywords(path: &Path) -> Result<Vec<(u8, String)>> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err.into()),
+    };
+
+    contents
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            let (index, name) = line
+                .split_once(' ')
+                .ok_or_else(|| Error::ParseKeywordsError(path.to_owned()))?;
+
+            match index.parse() {
+                Ok(index) if index < MAX_KEYWORDS => Ok((index, name.to_owned())),
+                _ => Err(Error::ParseKeywordsError(path.to_owned())),
+            }
+        })
+        .collect()
+}
+
+/// Returns the letter of the given keyword, allocating the next free
+/// index in the keywords file at the given path if needed.
+///
+/// Allocations hold an exclusive advisory lock on a sibling `.lock`
+/// file from the moment they read the keywords file until it is
+/// renamed, so that concurrent allocations never lose keywords.
+pub(crate) fn allocate_keyword(path: &Path, name: &str) -> Result<char> {
+    if name.is_empty() || name.contains(char::is_whitespace) {
+        return Err(Error::InvalidKeywordError(name.to_owned()));
+    }
+
+    if let Some(letter) = find_keyword(&read_keywords(path)?, name) {
+        return Ok(letter);
+    }
+
+    let _lock = lock_sibling(path)?;
+    let mut keywords = read_keywords(path)?;
+
+    // another writer may have allocated the keyword in the meantime
+    if let Some(letter) = find_keyword(&keywords, name) {
+        return Ok(letter);
+    }
+
+    let Some(index) = (0..MAX_KEYWORDS).find(|i| keywords.iter().all(|(index, _)| index != i))
+    else {
+        return Err(Error::KeywordsExhaustedError(path.to_owned()));
+    };
+
+    keywords.push((index, name.to_owned()));
+    keywords.sort();
+
+    let mut contents = String::new();
+
+    for (index, name) in keywords {
+        contents.push_str(&format!("{index} {name}\n"));
+    }
+
+    write_atomically(path, contents)?;
+
+    Ok(keyword_letter(index))
+}
+
+/// Returns the letter of the given keyword, if any.
+fn find_keyword(keywords: &[(u8, String)], name: &str) -> Option<char> {
+    keywords
+        .iter()
+        .find(|(_, n)| n == name)
+        .map(|(index, _)| keyword_letter(*index))
+}
+
+/// Returns the letter standing for the keyword of the given index.
+pub(crate) fn keyword_letter(index: u8) -> char {
+    (b'a' + index) as char
+}
@@ -0,0 +1,96 @@
+use std::{
+    ffi::OsString,
+    fs::{File, OpenOptions},
+    path::{Path, PathBuf},
+};
+
+use fs2::FileExt;
+
+use crate::{Maildir, Result};
+
+/// The name of the lock file, located at the root of the Maildir.
+static LOCK: &str = ".lock";
+
+/// An exclusive lock on a Maildir, released when dropped.
+///
+/// The lock is an advisory lock held on a `.lock` file at the root of
+/// the Maildir: it only protects against cooperating processes that
+/// also lock the Maildir before accessing it. It does not prevent
+/// other processes from reading or delivering entries, but it can be
+/// used to coordinate operations like UID list updates or
+/// subscription edits.
+#[derive(Debug)]
+pub struct MaildirLock {
+    path: PathBuf,
+    file: File,
+}
+
+impl MaildirLock {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for MaildirLock {
+    fn drop(&mut self) {
+        let _ = self.file.unlock();
+    }
+}
+
+impl Maildir {
+    /// Locks the current Maildir, blocking until the lock is
+    /// available.
+    ///
+    /// See [`MaildirLock`].
+    pub fn lock(&self) -> Result<MaildirLock> {
+        let (path, file) = self.open_lock_file()?;
+        file.lock_exclusive()?;
+        Ok(MaildirLock { path, file })
+    }
+
+    /// Locks the current Maildir without blocking.
+    ///
+    /// Returns `None` if the lock is already held, by another process
+    /// or by another [`MaildirLock`] of the current process.
+    pub fn try_lock(&self) -> Result<Option<MaildirLock>> {
+        let (path, file) = self.open_lock_file()?;
+
+        match file.try_lock_exclusive() {
+            Ok(()) => Ok(Some(MaildirLock { path, file })),
+            Err(err) if err.kind() == fs2::lock_contended_error().kind() => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    fn open_lock_file(&self) -> Result<(PathBuf, File)> {
+        let path = self.path().join(LOCK);
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)?;
+
+        Ok((path, file))
+    }
+}
+
+/// Locks the file at the given path for writing, blocking until the
+/// lock is available. The lock is released when the returned file is
+/// dropped.
+///
+/// The lock is held on a sibling `.lock` file, since files replaced
+/// on every write cannot be locked themselves.
+pub(crate) fn lock_sibling(path: &Path) -> Result<File> {
+    let mut path = OsString::from(path);
+    path.push(".lock");
+
+    let file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)?;
+
+    file.lock_exclusive()?;
+    Ok(file)
+}
@@ -3,22 +3,30 @@ use std::os::unix::fs::MetadataExt;
 #[cfg(windows)]
 use std::os::windows::fs::MetadataExt;
 use std::{
-    collections::HashSet,
+    borrow::Cow,
+    cmp,
+    collections::{HashMap, HashSet, VecDeque},
     ffi::OsStr,
     fs::{self, File, OpenOptions},
     hash::{Hash, Hasher},
-    io::{self, BufRead, BufReader, Write},
+    io::{self, BufRead, BufReader, Read, Write},
     path::{Component, Path, PathBuf},
     process,
-    sync::atomic::{AtomicUsize, Ordering},
-    thread,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
     time::{Duration, SystemTime, UNIX_EPOCH},
+    vec,
 };
 
 use gethostname::gethostname;
 use walkdir::WalkDir;
 
-use crate::{Error, Flag, Result};
+use crate::{
+    flags_from_str, flags_to_string, keywords, quota::append_quota_delta, validate, Error, Flag,
+    Result,
+};
 
 static NEW: &str = "new";
 static CUR: &str = "cur";
@@ -29,13 +37,54 @@ static DEFAULT_INFO_SEPARATOR: &str = ":";
 #[cfg(windows)]
 static DEFAULT_INFO_SEPARATOR: &str = ";";
 
-static H_36: u64 = 36 * 60 * 60;
+/// The info separators permitted by the Maildir specification,
+/// recognized by [`MaildirEntry::normalize`] whatever the configured
+/// separator.
+static SPEC_INFO_SEPARATORS: [&str; 2] = [":", ";"];
+
+static SUBSCRIPTIONS: &str = "subscriptions";
+
+/// The name of the marker file of Maildir++ folders.
+static MAILDIRFOLDER: &str = "maildirfolder";
+
+/// The default separator of nested names of [`Maildirs`] folders.
+static DEFAULT_HIERARCHY_SEPARATOR: char = '/';
+
+/// The name of the inbox, matched case-insensitively as IMAP does.
+static INBOX: &str = "INBOX";
+
+/// The folders created by [`Maildirs::create_standard`].
+static STANDARD_FOLDERS: [&str; 6] = [INBOX, "Sent", "Drafts", "Trash", "Archive", "Junk"];
+
+/// The default age after which tmp files are cleaned up, as
+/// recommended by <https://cr.yp.to/proto/maildir.html>.
+static DEFAULT_TMP_MAX_AGE: Duration = Duration::from_secs(36 * 60 * 60);
+
+/// The maximum number of attempts at finding a unique tmp file name
+/// before giving up the delivery.
+static MAX_DELIVERY_ATTEMPTS: usize = 10;
+
+/// The maximum number of attempts at changing flags of an entry
+/// concurrently renamed by another process.
+static MAX_FLAGS_CHANGE_ATTEMPTS: usize = 5;
 
 static COUNTER: AtomicUsize = AtomicUsize::new(0);
 
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct MaildirBuilder {
-    pub info_separator: &'static str,
+    info_separator: Cow<'static, str>,
+    maildirpp: bool,
+    size_field: bool,
+    stable_order: bool,
+    quota_tracking: bool,
+    durable: bool,
+    duplicate_policy: DuplicatePolicy,
+    tmp_cleanup: bool,
+    tmp_max_age: Duration,
+    file_mode: Option<u32>,
+    dir_mode: Option<u32>,
+    owner: Option<(u32, u32)>,
+    preserve_mtime: bool,
 }
 
 impl MaildirBuilder {
@@ -43,24 +92,255 @@ impl MaildirBuilder {
         Self::default()
     }
 
-    pub fn set_info_separator(&mut self, sep: &'static str) {
+    /// Sets the Maildir entry id ←→ info separator.
+    ///
+    /// The separator must be a single character, and cannot be `/`,
+    /// `.` nor NUL. The Maildir specification only permits `:`, or
+    /// `;` on filesystems that do not support colons (like FAT or
+    /// Windows).
+    pub fn set_info_separator(&mut self, sep: impl Into<Cow<'static, str>>) -> Result<()> {
+        let sep = sep.into();
+        validate_info_separator(&sep)?;
         self.info_separator = sep;
+        Ok(())
+    }
+
+    pub fn with_info_separator(mut self, sep: impl Into<Cow<'static, str>>) -> Result<Self> {
+        self.set_info_separator(sep)?;
+        Ok(self)
+    }
+
+    /// Makes the Maildir follow the Maildir++ layout.
+    ///
+    /// This only affects [`Maildir::create_child`], which creates
+    /// Maildir++ folders instead of nested directories. Disabled by
+    /// default.
+    pub fn set_maildirpp(&mut self, maildirpp: bool) {
+        self.maildirpp = maildirpp;
+    }
+
+    pub fn with_maildirpp(mut self, maildirpp: bool) -> Self {
+        self.set_maildirpp(maildirpp);
+        self
+    }
+
+    /// Enables the `,S=<size>` file name field on delivery.
+    ///
+    /// The size field, as understood by Dovecot, is appended to the
+    /// unique name of delivered entries so that readers do not need
+    /// to stat them to know their size. Disabled by default.
+    pub fn set_size_field(&mut self, enabled: bool) {
+        self.size_field = enabled;
+    }
+
+    pub fn with_size_field(mut self, enabled: bool) -> Self {
+        self.set_size_field(enabled);
+        self
+    }
+
+    /// Makes [`Maildir::read`] yield entries sorted by id.
+    ///
+    /// By default, entries are yielded in directory order, which is
+    /// not guaranteed to be the same between two reads. With a stable
+    /// order, two reads of an unchanged Maildir yield the exact same
+    /// sequence, which allows comparing snapshots with a simple
+    /// merge-join. This is distinct from
+    /// [`Maildir::read_sorted_by_date`], which sorts by arrival
+    /// order.
+    ///
+    /// Sorting requires all entries to be collected in memory, and
+    /// costs `O(n log n)` on every read. Disabled by default.
+    pub fn set_stable_order(&mut self, enabled: bool) {
+        self.stable_order = enabled;
+    }
+
+    pub fn with_stable_order(mut self, enabled: bool) -> Self {
+        self.set_stable_order(enabled);
+        self
+    }
+
+    /// Enables the automatic update of the `maildirsize` quota file
+    /// on delivery.
+    ///
+    /// When enabled and a `maildirsize` file exists at the root of
+    /// the Maildir, a delta line is appended to it for every
+    /// delivered entry. Failures are ignored, since the entry is
+    /// delivered anyway: see [`Maildir::recalculate_quota`] to repair
+    /// the file. Disabled by default.
+    pub fn set_quota_tracking(&mut self, enabled: bool) {
+        self.quota_tracking = enabled;
+    }
+
+    pub fn with_quota_tracking(mut self, enabled: bool) -> Self {
+        self.set_quota_tracking(enabled);
+        self
+    }
+
+    /// Makes deliveries durable.
+    ///
+    /// Delivered files are always synced before being moved from
+    /// `tmp` to `new` or `cur`, but the rename itself may be lost on
+    /// crash until the destination directory is synced as well. When
+    /// enabled, the destination directory is synced after the
+    /// rename, so that a delivery is never lost once it succeeds, at
+    /// the cost of latency. This is a no-op on non-Unix systems.
+    /// Disabled by default.
+    pub fn set_durable(&mut self, enabled: bool) {
+        self.durable = enabled;
+    }
+
+    pub fn with_durable(mut self, enabled: bool) -> Self {
+        self.set_durable(enabled);
+        self
+    }
+
+    /// Sets what happens when an entry is moved or copied to a file
+    /// name already taken in the Maildir.
+    ///
+    /// This applies to [`MaildirEntry::copy`], [`MaildirEntry::move`],
+    /// [`Maildir::move_all_to`] and [`Maildir::move_all_new_to_cur`],
+    /// using the policy of the destination Maildir. Defaults to
+    /// [`DuplicatePolicy::Rename`], so that no entry is lost.
+    pub fn set_duplicate_policy(&mut self, policy: DuplicatePolicy) {
+        self.duplicate_policy = policy;
+    }
+
+    pub fn with_duplicate_policy(mut self, policy: DuplicatePolicy) -> Self {
+        self.set_duplicate_policy(policy);
+        self
+    }
+
+    /// Enables the cleanup of old tmp files when building the
+    /// Maildir.
+    ///
+    /// When enabled, [`MaildirBuilder::build`] reads the whole `tmp`
+    /// directory and removes files older than the maximum age (see
+    /// [`MaildirBuilder::set_tmp_max_age`]). This costs one directory
+    /// read per built Maildir, which can be disabled when many
+    /// Maildirs are built in a loop, or when tmp files are cleaned up
+    /// by other means. Enabled by default.
+    pub fn set_tmp_cleanup(&mut self, enabled: bool) {
+        self.tmp_cleanup = enabled;
+    }
+
+    pub fn with_tmp_cleanup(mut self, enabled: bool) -> Self {
+        self.set_tmp_cleanup(enabled);
+        self
+    }
+
+    /// Sets the age after which tmp files are cleaned up. Defaults to
+    /// 36 hours.
+    pub fn set_tmp_max_age(&mut self, age: Duration) {
+        self.tmp_max_age = age;
     }
 
-    pub fn with_info_separator(mut self, sep: &'static str) -> Self {
-        self.set_info_separator(sep);
+    pub fn with_tmp_max_age(mut self, age: Duration) -> Self {
+        self.set_tmp_max_age(age);
+        self
+    }
+
+    /// Sets the permissions of delivered files, like `0o660`.
+    ///
+    /// Permissions are applied to tmp files before they are moved to
+    /// `new` or `cur`, regardless of the process umask. Files given
+    /// to [`Maildir::deliver_file`] are then copied instead of being
+    /// hard linked, so that their permissions are applied as well
+    /// without changing the source. This is a no-op on non-Unix
+    /// systems.
+    pub fn set_file_mode(&mut self, mode: u32) {
+        self.file_mode = Some(mode);
+    }
+
+    pub fn with_file_mode(mut self, mode: u32) -> Self {
+        self.set_file_mode(mode);
+        self
+    }
+
+    /// Sets the permissions of the root, `cur`, `new` and `tmp`
+    /// directories created by [`Maildir::create`] and
+    /// [`Maildir::create_all`], like `0o770`.
+    ///
+    /// This is a no-op on non-Unix systems.
+    pub fn set_dir_mode(&mut self, mode: u32) {
+        self.dir_mode = Some(mode);
+    }
+
+    pub fn with_dir_mode(mut self, mode: u32) -> Self {
+        self.set_dir_mode(mode);
+        self
+    }
+
+    /// Sets the owner of delivered files and created directories.
+    ///
+    /// This is meant for privileged delivery agents delivering into
+    /// per-user Maildirs: tmp files are changed to the given user and
+    /// group ids before they are moved to `new` or `cur`, as well as
+    /// directories created by [`Maildir::create`] and
+    /// [`Maildir::create_all`]. Operations fail with
+    /// [`Error::ChangeOwnerError`] when the process lacks the
+    /// privilege to change owners. This is a no-op on non-Unix
+    /// systems.
+    ///
+    /// ```no_run
+    /// use maildirs::MaildirBuilder;
+    ///
+    /// let mdir = MaildirBuilder::new()
+    ///     .with_owner(1000, 1000)
+    ///     .with_file_mode(0o600)
+    ///     .build("/home/user/Maildir");
+    ///
+    /// mdir.create_all().unwrap();
+    /// mdir.write_new(b"From: alice@localhost\r\n\r\nHello!").unwrap();
+    /// ```
+    pub fn set_owner(&mut self, uid: u32, gid: u32) {
+        self.owner = Some((uid, gid));
+    }
+
+    pub fn with_owner(mut self, uid: u32, gid: u32) -> Self {
+        self.set_owner(uid, gid);
+        self
+    }
+
+    /// Preserves the modification time of files delivered with
+    /// [`Maildir::deliver_file`].
+    ///
+    /// When disabled, delivered files are always copied, so that
+    /// their modification time is the time of delivery. Enabled by
+    /// default.
+    pub fn set_preserve_mtime(&mut self, enabled: bool) {
+        self.preserve_mtime = enabled;
+    }
+
+    pub fn with_preserve_mtime(mut self, enabled: bool) -> Self {
+        self.set_preserve_mtime(enabled);
         self
     }
 
     pub fn build(self, path: impl Into<PathBuf>) -> Maildir {
-        let mdir = Maildir::from(path.into()).with_info_separator(self.info_separator);
+        let mut mdir = Maildir::from(path.into());
+        let inner = mdir.inner_mut();
+        inner.info_separator = self.info_separator;
+        inner.maildirpp = self.maildirpp;
+        inner.size_field = self.size_field;
+        inner.stable_order = self.stable_order;
+        inner.quota_tracking = self.quota_tracking;
+        inner.durable = self.durable;
+        inner.duplicate_policy = self.duplicate_policy;
+        inner.file_mode = self.file_mode;
+        inner.dir_mode = self.dir_mode;
+        inner.owner = self.owner;
+        inner.preserve_mtime = self.preserve_mtime;
+
+        if !self.tmp_cleanup {
+            return mdir;
+        }
 
-        if let Ok(mut entries) = fs::read_dir(&mdir.tmp()) {
+        if let Ok(mut entries) = fs::read_dir(mdir.tmp()) {
             let _ = entries.try_for_each(|entry| {
                 let path = entry?.path();
                 let metadata = path.metadata()?;
 
-                if metadata.is_file() && metadata.modified()?.elapsed()?.as_secs() > H_36 {
+                if metadata.is_file() && metadata.modified()?.elapsed()? > self.tmp_max_age {
                     fs::remove_file(path)?;
                 }
 
@@ -75,7 +355,19 @@ impl MaildirBuilder {
 impl Default for MaildirBuilder {
     fn default() -> Self {
         Self {
-            info_separator: DEFAULT_INFO_SEPARATOR,
+            info_separator: Cow::Borrowed(DEFAULT_INFO_SEPARATOR),
+            maildirpp: false,
+            size_field: false,
+            stable_order: false,
+            quota_tracking: false,
+            durable: false,
+            duplicate_policy: DuplicatePolicy::default(),
+            tmp_cleanup: true,
+            tmp_max_age: DEFAULT_TMP_MAX_AGE,
+            file_mode: None,
+            dir_mode: None,
+            owner: None,
+            preserve_mtime: true,
         }
     }
 }
@@ -84,8 +376,19 @@ impl Default for MaildirBuilder {
 ///
 /// A Maildir is a mail directory composed of a `new`, `cur` and `tmp`
 /// subdirectories.
+///
+/// The Maildir is a cheap handle: its state is shared behind an
+/// [`Arc`], so that cloning only bumps a reference count. Setters
+/// copy the state when it is shared, so that clones are never
+/// affected.
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct Maildir {
+    inner: Arc<MaildirInner>,
+}
+
+/// The state of a [`Maildir`], shared between its clones.
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct MaildirInner {
     /// The root path of the mail directory.
     root: PathBuf,
 
@@ -94,29 +397,194 @@ pub struct Maildir {
     tmp: PathBuf,
 
     /// The Maildir entry id ←→ info separator.
-    info_separator: &'static str,
+    info_separator: Cow<'static, str>,
+
+    /// Whether the Maildir follows the Maildir++ layout.
+    maildirpp: bool,
+
+    /// Whether the `,S=<size>` field is appended to delivered
+    /// entries.
+    size_field: bool,
+
+    /// Whether entries are read sorted by id.
+    stable_order: bool,
+
+    /// Whether the `maildirsize` file is updated on delivery.
+    quota_tracking: bool,
+
+    /// Whether destination directories are synced on delivery.
+    durable: bool,
+
+    /// What happens when moving or copying to a taken file name.
+    duplicate_policy: DuplicatePolicy,
+
+    /// The permissions of delivered files.
+    file_mode: Option<u32>,
+
+    /// The permissions of created directories.
+    dir_mode: Option<u32>,
+
+    /// The user and group ids owning delivered files and created
+    /// directories.
+    owner: Option<(u32, u32)>,
+
+    /// Whether files delivered with [`Maildir::deliver_file`] keep
+    /// their modification time.
+    preserve_mtime: bool,
 }
 
 impl Maildir {
-    pub fn set_info_separator(&mut self, sep: &'static str) {
-        self.info_separator = sep;
+    /// Opens the existing Maildir at the given path.
+    ///
+    /// Unlike [`Maildir::from`], which does no I/O, this function
+    /// fails with [`Error::ReadMaildirError`] if the `cur`, `new` or
+    /// `tmp` directory is missing. Like [`MaildirBuilder::build`], old
+    /// tmp files are cleaned up.
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self> {
+        let mdir = MaildirBuilder::new().build(path);
+
+        if !mdir.exists() {
+            return Err(Error::ReadMaildirError(mdir.path().to_owned()));
+        }
+
+        Ok(mdir)
+    }
+
+    /// Returns the existing Maildir at the given path.
+    ///
+    /// Like [`Maildir::open`], this function fails with
+    /// [`Error::ReadMaildirError`] if the `cur`, `new` or `tmp`
+    /// directory is missing, but old tmp files are not cleaned up.
+    /// See [`Maildir::from`] for Maildirs created later on.
+    pub fn from_existing(path: impl Into<PathBuf>) -> Result<Self> {
+        let mdir = Self::from(path);
+
+        if !mdir.exists() {
+            return Err(Error::ReadMaildirError(mdir.path().to_owned()));
+        }
+
+        Ok(mdir)
+    }
+
+    /// Opens the Maildir at the given path, creating it first if
+    /// needed.
+    ///
+    /// Unlike [`Maildir::open`], missing directories are created
+    /// instead of failing. Use [`MaildirBuilder`] in order to
+    /// customize the Maildir.
+    pub fn open_or_create(path: impl Into<PathBuf>) -> Result<Self> {
+        let mdir = MaildirBuilder::new().build(path);
+        mdir.create_all()?;
+        Ok(mdir)
+    }
+
+    /// Sets the Maildir entry id ←→ info separator.
+    ///
+    /// See [`MaildirBuilder::set_info_separator`] for the allowed
+    /// values.
+    pub fn set_info_separator(&mut self, sep: impl Into<Cow<'static, str>>) -> Result<()> {
+        let sep = sep.into();
+        validate_info_separator(&sep)?;
+        self.inner_mut().info_separator = sep;
+        Ok(())
     }
 
-    pub fn with_info_separator(mut self, sep: &'static str) -> Self {
-        self.set_info_separator(sep);
+    pub fn with_info_separator(mut self, sep: impl Into<Cow<'static, str>>) -> Result<Self> {
+        self.set_info_separator(sep)?;
+        Ok(self)
+    }
+
+    /// Makes the Maildir follow the Maildir++ layout.
+    ///
+    /// See [`MaildirBuilder::set_maildirpp`].
+    pub fn set_maildirpp(&mut self, maildirpp: bool) {
+        self.inner_mut().maildirpp = maildirpp;
+    }
+
+    pub fn with_maildirpp(mut self, maildirpp: bool) -> Self {
+        self.set_maildirpp(maildirpp);
+        self
+    }
+
+    /// Enables the `,S=<size>` file name field on delivery.
+    ///
+    /// See [`MaildirBuilder::set_size_field`].
+    pub fn set_size_field(&mut self, enabled: bool) {
+        self.inner_mut().size_field = enabled;
+    }
+
+    pub fn with_size_field(mut self, enabled: bool) -> Self {
+        self.set_size_field(enabled);
+        self
+    }
+
+    /// Makes [`Maildir::read`] yield entries sorted by id.
+    ///
+    /// See [`MaildirBuilder::set_stable_order`].
+    pub fn set_stable_order(&mut self, enabled: bool) {
+        self.inner_mut().stable_order = enabled;
+    }
+
+    pub fn with_stable_order(mut self, enabled: bool) -> Self {
+        self.set_stable_order(enabled);
+        self
+    }
+
+    /// Enables the automatic update of the `maildirsize` quota file
+    /// on delivery.
+    ///
+    /// See [`MaildirBuilder::set_quota_tracking`].
+    pub fn set_quota_tracking(&mut self, enabled: bool) {
+        self.inner_mut().quota_tracking = enabled;
+    }
+
+    pub fn with_quota_tracking(mut self, enabled: bool) -> Self {
+        self.set_quota_tracking(enabled);
+        self
+    }
+
+    /// Makes deliveries durable.
+    ///
+    /// See [`MaildirBuilder::set_durable`].
+    pub fn set_durable(&mut self, enabled: bool) {
+        self.inner_mut().durable = enabled;
+    }
+
+    pub fn with_durable(mut self, enabled: bool) -> Self {
+        self.set_durable(enabled);
         self
     }
 
+    /// Sets what happens when an entry is moved or copied to a file
+    /// name already taken in the Maildir.
+    ///
+    /// See [`MaildirBuilder::set_duplicate_policy`].
+    pub fn set_duplicate_policy(&mut self, policy: DuplicatePolicy) {
+        self.inner_mut().duplicate_policy = policy;
+    }
+
+    pub fn with_duplicate_policy(mut self, policy: DuplicatePolicy) -> Self {
+        self.set_duplicate_policy(policy);
+        self
+    }
+
+    /// Returns the state of the current Maildir, copied first if it
+    /// is shared with clones.
+    fn inner_mut(&mut self) -> &mut MaildirInner {
+        Arc::make_mut(&mut self.inner)
+    }
+
     pub fn path(&self) -> &Path {
-        &self.root
+        &self.inner.root
     }
 
     pub fn name(&self) -> Result<&str> {
         let file_name = self
+            .inner
             .root
             .file_name()
             .and_then(OsStr::to_str)
-            .ok_or_else(|| Error::GetMaildirNameError(self.root.clone()))?;
+            .ok_or_else(|| Error::GetMaildirNameError(self.inner.root.clone()))?;
 
         // remove potential periods in front of the name (this happens
         // when using Maildir++ format).
@@ -124,37 +592,109 @@ impl Maildir {
     }
 
     pub fn cur(&self) -> &Path {
-        &self.cur
+        &self.inner.cur
     }
 
+    #[allow(clippy::new_ret_no_self)]
     pub fn new(&self) -> &Path {
-        &self.new
+        &self.inner.new
     }
 
     pub fn tmp(&self) -> &Path {
-        &self.tmp
+        &self.inner.tmp
+    }
+
+    /// Returns the path of the given directory of the current
+    /// Maildir.
+    pub fn dir_path(&self, dir: MaildirDir) -> &Path {
+        match dir {
+            MaildirDir::New => &self.inner.new,
+            MaildirDir::Cur => &self.inner.cur,
+            MaildirDir::Tmp => &self.inner.tmp,
+        }
     }
 
     pub fn exists(&self) -> bool {
-        self.root.is_dir() && self.cur.is_dir() && self.new.is_dir() && self.tmp.is_dir()
+        self.inner.root.is_dir()
+            && self.inner.cur.is_dir()
+            && self.inner.new.is_dir()
+            && self.inner.tmp.is_dir()
     }
 
     pub fn create(&self) -> Result<()> {
-        fs::create_dir(&self.root)?;
+        fs::create_dir(&self.inner.root)?;
 
-        fs::create_dir(&self.cur)?;
-        fs::create_dir(&self.new)?;
-        fs::create_dir(&self.tmp)?;
+        fs::create_dir(&self.inner.cur)?;
+        fs::create_dir(&self.inner.new)?;
+        fs::create_dir(&self.inner.tmp)?;
 
-        Ok(())
+        self.apply_dir_mode()?;
+        self.apply_dir_owner()
     }
 
     pub fn create_all(&self) -> Result<()> {
-        fs::create_dir_all(&self.root)?;
+        fs::create_dir_all(&self.inner.root)?;
+
+        fs::create_dir_all(&self.inner.cur)?;
+        fs::create_dir_all(&self.inner.new)?;
+        fs::create_dir_all(&self.inner.tmp)?;
+
+        self.apply_dir_mode()?;
+        self.apply_dir_owner()
+    }
+
+    /// Applies the configured directory permissions, if any.
+    fn apply_dir_mode(&self) -> Result<()> {
+        #[cfg(unix)]
+        if let Some(mode) = self.inner.dir_mode {
+            use std::os::unix::fs::PermissionsExt;
+
+            for dir in [
+                &self.inner.root,
+                &self.inner.cur,
+                &self.inner.new,
+                &self.inner.tmp,
+            ] {
+                fs::set_permissions(dir, fs::Permissions::from_mode(mode))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Applies the configured directory owner, if any.
+    fn apply_dir_owner(&self) -> Result<()> {
+        for dir in [
+            &self.inner.root,
+            &self.inner.cur,
+            &self.inner.new,
+            &self.inner.tmp,
+        ] {
+            self.apply_owner(dir)?;
+        }
+
+        Ok(())
+    }
+
+    /// Applies the configured owner to the given path, if any.
+    fn apply_owner(&self, #[allow(unused)] path: &Path) -> Result<()> {
+        #[cfg(unix)]
+        if let Some((uid, gid)) = self.inner.owner {
+            if let Err(err) = std::os::unix::fs::chown(path, Some(uid), Some(gid)) {
+                return Err(Error::ChangeOwnerError(err, path.to_owned()));
+            }
+        }
 
-        fs::create_dir_all(&self.cur)?;
-        fs::create_dir_all(&self.new)?;
-        fs::create_dir_all(&self.tmp)?;
+        Ok(())
+    }
+
+    /// Applies the configured file permissions, if any.
+    fn apply_file_mode(&self, #[allow(unused)] file: &File) -> Result<()> {
+        #[cfg(unix)]
+        if let Some(mode) = self.inner.file_mode {
+            use std::os::unix::fs::PermissionsExt;
+            file.set_permissions(fs::Permissions::from_mode(mode))?;
+        }
 
         Ok(())
     }
@@ -163,11 +703,16 @@ impl Maildir {
     /// Maildir.
     ///
     /// This function does not remove the root directory. If you want
-    /// to do so, see [`Maildir::remove_all`].
+    /// to do so, see [`Maildir::remove_all`]. Missing directories are
+    /// skipped, so that partially created Maildirs can be removed.
     pub fn remove(&self) -> Result<()> {
-        fs::remove_dir_all(&self.cur)?;
-        fs::remove_dir_all(&self.new)?;
-        fs::remove_dir_all(&self.tmp)?;
+        for dir in [&self.inner.cur, &self.inner.new, &self.inner.tmp] {
+            match fs::remove_dir_all(dir) {
+                Ok(()) => (),
+                Err(err) if err.kind() == io::ErrorKind::NotFound => (),
+                Err(err) => return Err(Error::RemoveMaildirError(err, dir.clone())),
+            }
+        }
 
         Ok(())
     }
@@ -178,570 +723,3822 @@ impl Maildir {
     /// as the root directory of the current Maildir. See also
     /// [`Maildir::remove`].
     pub fn remove_all(&self) -> Result<()> {
-        fs::remove_dir_all(&self.root)?;
+        fs::remove_dir_all(&self.inner.root)?;
 
         Ok(())
     }
 
-    pub fn read(&self) -> Result<impl Iterator<Item = MaildirEntry> + '_> {
-        Ok(fs::read_dir(&self.new)?
-            .chain(fs::read_dir(&self.cur)?)
-            .filter_map(|entry| entry.ok())
-            .filter(|entry| entry.path().is_file())
-            .filter(|entry| {
-                entry
-                    .file_name()
-                    .to_str()
-                    .map(|s| !s.starts_with("."))
-                    .unwrap_or(false)
-            })
-            .map(|entry| MaildirEntry::new(entry.path()).with_info_separator(self.info_separator)))
+    /// Returns the Maildir++ subfolders of the current Maildir, as a
+    /// [`Maildirs`] rooted at the current Maildir path.
+    pub fn subfolders(&self) -> Maildirs {
+        let mut mdirs = Maildirs::new(&self.inner.root).with_maildirpp(true);
+        mdirs.info_separator = self.inner.info_separator.clone();
+        mdirs
     }
 
-    pub fn find(&self, id: impl AsRef<str>) -> Result<Option<MaildirEntry>> {
-        let id = id.as_ref();
+    /// Creates the child folder of the given name, then returns it.
+    ///
+    /// In Maildir++ layouts (see [`Maildir::set_maildirpp`]), the
+    /// child is created as a `.child` folder at the root of the
+    /// current Maildir, or as a `.parent.child` sibling folder if the
+    /// current Maildir is itself a folder, as indicated by its
+    /// `maildirfolder` marker file. The marker file is written to
+    /// the child. Otherwise, the child is created as a nested
+    /// `child` directory. The child inherits the settings of the
+    /// current Maildir.
+    pub fn create_child(&self, name: impl AsRef<str>) -> Result<Maildir> {
+        let name = name.as_ref();
+        validate::validate_folder(name)?;
 
-        let mdir = fs::read_dir(&self.new)?
-            .chain(fs::read_dir(&self.cur)?)
-            .filter_map(|entry| entry.ok())
-            .find_map(|entry| {
-                if !entry.path().is_file() {
-                    return None;
-                }
+        let path = if !self.inner.maildirpp {
+            if name == CUR || name == NEW || name == TMP {
+                let reason = "cur, new and tmp are reserved Maildir directory names";
+                return Err(Error::InvalidFolderError(name.to_owned(), reason));
+            }
 
-                let entry =
-                    MaildirEntry::new(entry.path()).with_info_separator(self.info_separator);
+            self.inner.root.join(name)
+        } else if self.inner.root.join(MAILDIRFOLDER).is_file() {
+            let parent = self
+                .inner
+                .root
+                .parent()
+                .ok_or_else(|| Error::NoParentError(self.inner.root.clone()))?;
+            let file_name = self
+                .inner
+                .root
+                .file_name()
+                .and_then(OsStr::to_str)
+                .ok_or_else(|| Error::GetMaildirNameError(self.inner.root.clone()))?;
+            parent.join(format!("{file_name}.{name}"))
+        } else {
+            self.inner.root.join(format!(".{name}"))
+        };
 
-                if id != entry.id().ok()? {
-                    return None;
-                }
+        let mut child = self.clone();
+        let inner = child.inner_mut();
+        inner.cur = path.join(CUR);
+        inner.new = path.join(NEW);
+        inner.tmp = path.join(TMP);
+        inner.root = path;
+        child.create_all()?;
+
+        if self.inner.maildirpp {
+            let marker = child.inner.root.join(MAILDIRFOLDER);
+            File::create(&marker)?;
+            child.apply_owner(&marker)?;
+        }
 
-                Some(entry)
-            });
+        Ok(child)
+    }
 
-        Ok(mdir)
+    /// Reads entries from `new`, then from `cur`.
+    ///
+    /// Only `new` is opened eagerly, `cur` is opened once `new` is
+    /// exhausted. Errors occurring while opening `cur` or iterating
+    /// over entries are yielded by the returned [`MaildirEntries`],
+    /// so that consumers stopping early never touch `cur`.
+    ///
+    /// When the stable order is enabled (see
+    /// [`Maildir::set_stable_order`]), entries are collected and
+    /// sorted by id first, and errors are returned directly.
+    pub fn read(&self) -> Result<MaildirEntries> {
+        self.read_dirs(&[MaildirDir::New, MaildirDir::Cur])
     }
 
-    pub fn get(&self, id: impl AsRef<str>) -> Result<MaildirEntry> {
-        let id = id.as_ref();
+    /// Reads entries from `new`, `cur`, then from `tmp`.
+    ///
+    /// Entries of `tmp` are deliveries in progress, or stuck ones.
+    /// They have no info section, hence no flag. See
+    /// [`Maildir::read_dirs`].
+    pub fn read_all(&self) -> Result<MaildirEntries> {
+        self.read_dirs(&[MaildirDir::New, MaildirDir::Cur, MaildirDir::Tmp])
+    }
 
-        match self.find(id)? {
-            Some(mdir) => Ok(mdir),
-            None => Err(Error::GetMaildirEntryNotFoundError(id.to_owned())),
+    /// Reads entries from the given directories, in order.
+    ///
+    /// Like [`Maildir::read`], only the first directory is opened
+    /// eagerly.
+    pub fn read_dirs(&self, dirs: &[MaildirDir]) -> Result<MaildirEntries> {
+        let mut pending_dirs: VecDeque<PathBuf> = dirs
+            .iter()
+            .map(|dir| self.dir_path(*dir).to_owned())
+            .collect();
+
+        let mut entries = MaildirEntries {
+            info_separator: self.inner.info_separator.clone(),
+            dirs: VecDeque::new(),
+            pending_dirs: VecDeque::new(),
+            sorted: None,
+            error: None,
+        };
+
+        if let Some(dir) = pending_dirs.pop_front() {
+            entries.dirs.push_back(fs::read_dir(dir)?);
+        }
+
+        entries.pending_dirs = pending_dirs;
+
+        if self.inner.stable_order {
+            let mut sorted = entries.by_ref().collect::<Result<Vec<_>>>()?;
+            sorted.sort_by(|a, b| {
+                let (a_id, b_id) = (a.id().ok(), b.id().ok());
+                a_id.cmp(&b_id).then_with(|| a.path.cmp(&b.path))
+            });
+            entries.sorted = Some(sorted.into_iter());
         }
+
+        Ok(entries)
     }
 
-    pub fn write_new(&self, contents: impl AsRef<[u8]>) -> Result<MaildirEntry> {
-        self.write(contents, None, true, None)
+    /// Counts entries in `new`, without moving them to `cur`.
+    ///
+    /// Dotfiles are skipped, like [`Maildir::read`] does.
+    pub fn count_new(&self) -> Result<usize> {
+        self.count_dirs(&[MaildirDir::New])
     }
 
-    pub fn write_cur(
-        &self,
-        contents: impl AsRef<[u8]>,
-        flags: impl IntoIterator<Item = Flag>,
-    ) -> Result<MaildirEntry> {
-        self.write(contents, flags, false, None)
+    /// Counts entries in `cur`.
+    ///
+    /// Dotfiles are skipped, like [`Maildir::read`] does.
+    pub fn count_cur(&self) -> Result<usize> {
+        self.count_dirs(&[MaildirDir::Cur])
     }
 
-    fn write(
-        &self,
-        contents: impl AsRef<[u8]>,
-        flags: impl IntoIterator<Item = Flag>,
-        new: bool,
-        id: Option<String>,
-    ) -> Result<MaildirEntry> {
-        // loop when conflicting filenames occur, as described at
-        // <http://www.courier-mta.org/maildir.html> this assumes that
-        // pid and hostname don't change.
-        let (tmp_path, mut tmp_file) = loop {
-            let path = self.tmp.join(generate_tmp_id());
-            let open = OpenOptions::new().write(true).create_new(true).open(&path);
+    /// Counts entries in both `new` and `cur`.
+    pub fn count(&self) -> Result<usize> {
+        self.count_dirs(&[MaildirDir::New, MaildirDir::Cur])
+    }
 
-            match open {
-                Ok(file) => {
-                    break Result::Ok((path, file));
-                }
-                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {
-                    thread::sleep(Duration::from_secs(2));
-                    continue;
-                }
-                Err(err) => {
-                    break Err(err.into());
+    /// Counts entries in the given directories.
+    fn count_dirs(&self, dirs: &[MaildirDir]) -> Result<usize> {
+        dirs.iter().try_fold(0, |count, dir| {
+            Ok(count + read_entries(self.dir_path(*dir))?.count())
+        })
+    }
+
+    /// Sums the sizes of entries in both `new` and `cur`, in bytes.
+    ///
+    /// See [`Maildir::size_on_disk_dirs`].
+    pub fn size_on_disk(&self) -> Result<u64> {
+        self.size_on_disk_dirs(&[MaildirDir::New, MaildirDir::Cur])
+    }
+
+    /// Sums the sizes of entries in the given directories, in bytes.
+    ///
+    /// The size of an entry is taken from its `,S=<size>` field when
+    /// present (see [`MaildirEntry::size_hint`]), otherwise from its
+    /// metadata. This is O(n) in the number of entries, each entry
+    /// without size field costing a `stat` call. Entries removed
+    /// concurrently by another process are skipped.
+    pub fn size_on_disk_dirs(&self, dirs: &[MaildirDir]) -> Result<u64> {
+        self.read_dirs(dirs)?.try_fold(0, |total, entry| {
+            let entry = entry?;
+
+            if let Some(size) = entry.size_hint()? {
+                return Ok(total + size);
+            }
+
+            match fs::metadata(entry.path()) {
+                Ok(meta) => Ok(total + meta.len()),
+                Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(total),
+                Err(err) => Err(err.into()),
+            }
+        })
+    }
+
+    /// Removes entries of `new` and `cur` having the
+    /// [`Flag::Trashed`] flag, and returns the number of removed
+    /// entries, like an IMAP `EXPUNGE`.
+    ///
+    /// Entries removed concurrently by another process are skipped.
+    pub fn purge_trashed(&self) -> Result<usize> {
+        let mut count = 0;
+
+        for dir in [&self.inner.new, &self.inner.cur] {
+            for entry in read_entries(dir)? {
+                if !self.entry(entry.path()).has_trash_flag() {
+                    continue;
+                }
+
+                match fs::remove_file(entry.path()) {
+                    Ok(()) => count += 1,
+                    Err(err) if err.kind() == io::ErrorKind::NotFound => (),
+                    Err(err) => return Err(err.into()),
+                }
+            }
+        }
+
+        Ok(count)
+    }
+
+    /// Removes all entries of `new` and `cur`.
+    ///
+    /// Unlike [`Maildir::remove`], the directory structure is kept.
+    /// Files of `tmp`, which are deliveries in progress, are kept as
+    /// well.
+    pub fn empty(&self) -> Result<()> {
+        for dir in [&self.inner.new, &self.inner.cur] {
+            for entry in read_entries(dir)? {
+                match fs::remove_file(entry.path()) {
+                    Ok(()) => (),
+                    Err(err) if err.kind() == io::ErrorKind::NotFound => (),
+                    Err(err) => return Err(err.into()),
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Renames entries using another info separator than the one of
+    /// the current Maildir, and returns the number of renamed entries.
+    ///
+    /// Maildirs synchronized between Unix and Windows systems may end
+    /// up mixing `:` and `;` separators, which breaks flags parsing of
+    /// entries not matching the configured separator.
+    pub fn normalize_separators(&self) -> Result<usize> {
+        let mut count = 0;
+
+        for entry in self.read()? {
+            let entry = entry?;
+            let file_name = entry.file_name()?;
+
+            if file_name.contains(&*self.inner.info_separator) {
+                continue;
+            }
+
+            let info = SPEC_INFO_SEPARATORS
+                .into_iter()
+                .filter(|sep| *sep != self.inner.info_separator)
+                .find_map(|sep| file_name.rsplit_once(sep));
+
+            if let Some((name, info)) = info {
+                let sep = &self.inner.info_separator;
+                let next_path = entry.path().with_file_name(format!("{name}{sep}{info}"));
+                fs::rename(entry.path(), next_path)?;
+                count += 1;
+            }
+        }
+
+        Ok(count)
+    }
+
+    /// Moves all entries of the current Maildir to the given one, and
+    /// returns the number of moved entries from `new` and from `cur`.
+    ///
+    /// Entries keep their partition: entries from `new` are moved to
+    /// `new`, and entries from `cur` are moved to `cur` with their
+    /// flags. Entries whose file name is already taken in the given
+    /// Maildir are handled according to its duplicate policy (see
+    /// [`Maildir::set_duplicate_policy`]): skipped entries are not
+    /// counted. Info sections are separated with the info separator
+    /// of the given Maildir.
+    pub fn move_all_to(&self, mdir: &Maildir) -> Result<(usize, usize)> {
+        if self.inner.root == mdir.inner.root {
+            return Err(Error::CopyEmailSamePathError(self.inner.root.clone()));
+        }
+
+        let new = mdir.move_entries(self, &self.inner.new, &mdir.inner.new)?;
+        let cur = mdir.move_entries(self, &self.inner.cur, &mdir.inner.cur)?;
+
+        Ok((new, cur))
+    }
+
+    /// Moves all entries of `new` to `cur`, and returns the moved
+    /// entries.
+    ///
+    /// Entries get an info section without flags, like `:2,`. When
+    /// the file name is already taken in `cur`, the duplicate policy
+    /// applies (see [`Maildir::set_duplicate_policy`]): skipped
+    /// entries stay in `new` and are not returned. See
+    /// [`MaildirEntry::move_to_cur`] to move a single entry.
+    pub fn move_all_new_to_cur(&self) -> Result<Vec<MaildirEntry>> {
+        let mut entries = Vec::new();
+
+        for entry in read_entries(&self.inner.new)? {
+            let entry = self.entry(entry.path());
+            let file_name = entry.format_file_name(entry.flags()?)?;
+            let next_path = self.transfer(
+                entry.path(),
+                &self.inner.cur,
+                &file_name,
+                &self.inner.info_separator,
+                false,
+            )?;
+
+            if let Some(next_path) = next_path {
+                entries.push(self.entry(next_path));
+            }
+        }
+
+        Ok(entries)
+    }
+
+    /// Repairs entries of `new` having an info section, which is
+    /// reserved to entries of `cur`, and returns the changes made.
+    ///
+    /// Such entries are dropped by buggy delivery agents. Entries
+    /// with flags are moved to `cur` with their flags preserved,
+    /// other entries get their info section stripped. When the
+    /// repaired file name is already taken, a new id is generated.
+    pub fn repair(&self) -> Result<RepairReport> {
+        let mut report = RepairReport::default();
+
+        for entry in read_entries(&self.inner.new)? {
+            let entry = self.entry(entry.path());
+
+            if entry.info_section()?.is_none() {
+                continue;
+            }
+
+            if entry.flags_raw()?.is_empty() {
+                let file_name = entry.base_name()?;
+                let next_path = transfer_free(
+                    entry.path(),
+                    &self.inner.new,
+                    file_name,
+                    &self.inner.info_separator,
+                    false,
+                )?;
+                report.stripped.push((entry.path, next_path));
+            } else {
+                let file_name = entry.format_file_name(entry.flags()?)?;
+                let next_path = transfer_free(
+                    entry.path(),
+                    &self.inner.cur,
+                    &file_name,
+                    &self.inner.info_separator,
+                    false,
+                )?;
+                report.moved.push((entry.path, next_path));
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// Moves or copies the given source file to the given file name
+    /// in the given directory of the current Maildir, according to
+    /// its duplicate policy, and returns its next path, or `None` if
+    /// the entry is skipped.
+    ///
+    /// Unless the policy is [`DuplicatePolicy::Overwrite`], existing
+    /// files are never replaced, even when they appear concurrently.
+    /// The info separator is the one of the file name.
+    fn transfer(
+        &self,
+        src: &Path,
+        dir: &Path,
+        file_name: &str,
+        sep: &str,
+        copy: bool,
+    ) -> Result<Option<PathBuf>> {
+        let path = dir.join(file_name);
+
+        match self.inner.duplicate_policy {
+            DuplicatePolicy::Overwrite => {
+                if copy {
+                    fs::copy(src, &path)?;
+                } else {
+                    fs::rename(src, &path)?;
+                }
+
+                Ok(Some(path))
+            }
+            DuplicatePolicy::Skip => match transfer_new(src, &path, copy) {
+                Ok(()) => Ok(Some(path)),
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => Ok(None),
+                Err(err) => Err(err.into()),
+            },
+            DuplicatePolicy::Rename => Ok(Some(transfer_free(src, dir, file_name, sep, copy)?)),
+        }
+    }
+
+    /// Moves the entries of the given source directory of the given
+    /// Maildir to the given directory of the current Maildir, and
+    /// returns the number of moved entries.
+    ///
+    /// File names are parsed with the info separator of the given
+    /// Maildir, then formatted with the one of the current Maildir.
+    fn move_entries(&self, from: &Maildir, src: &Path, dest: &Path) -> Result<usize> {
+        let mut count = 0;
+
+        for entry in read_entries(src)? {
+            let entry = from.entry(entry.path());
+            let file_name = match entry.info_section()? {
+                Some(info) => format!("{}{}{info}", entry.base_name()?, self.inner.info_separator),
+                None => entry.file_name()?.to_owned(),
+            };
+
+            let next_path = self.transfer(
+                entry.path(),
+                dest,
+                &file_name,
+                &self.inner.info_separator,
+                false,
+            )?;
+
+            if next_path.is_some() {
+                count += 1;
+            }
+        }
+
+        Ok(count)
+    }
+
+    /// Reads entries from `new` and `cur`, setting aside malformed
+    /// ones instead of failing.
+    ///
+    /// Returns the valid entries, followed by the paths of malformed
+    /// entries along with the reason why they cannot be used: a file
+    /// name that is not valid UTF-8, an empty id or an info section
+    /// not starting with `2,`. Errors occurring while reading the
+    /// directories themselves are still returned directly.
+    ///
+    /// Unlike [`Maildir::read`], which skips them, entries whose
+    /// file name is not valid UTF-8 are reported.
+    #[allow(clippy::type_complexity)]
+    pub fn read_lenient(&self) -> Result<(Vec<MaildirEntry>, Vec<(PathBuf, Error)>)> {
+        let mut entries = Vec::new();
+        let mut failures = Vec::new();
+
+        for dir in [&self.inner.new, &self.inner.cur] {
+            for entry in fs::read_dir(dir)? {
+                let path = entry?.path();
+                let is_dotfile = path
+                    .file_name()
+                    .is_some_and(|name| name.as_encoded_bytes().starts_with(b"."));
+
+                if is_dotfile || !path.is_file() {
+                    continue;
+                }
+
+                let entry = self.entry(path);
+
+                match entry.validate() {
+                    Ok(()) => entries.push(entry),
+                    Err(err) => failures.push((entry.path, err)),
+                }
+            }
+        }
+
+        Ok((entries, failures))
+    }
+
+    /// Reads entries from `new` and `cur`, sorted by ascending
+    /// received date (see [`MaildirEntry::received_date`]).
+    ///
+    /// Unlike [`Maildir::read`], all entries are collected in memory
+    /// in order to be sorted.
+    pub fn read_sorted_by_date(&self) -> Result<Vec<MaildirEntry>> {
+        let mut entries = self
+            .read()?
+            .map(|entry| {
+                let entry = entry?;
+                Ok((entry.received_date()?, entry))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        Ok(entries.into_iter().map(|(_, entry)| entry).collect())
+    }
+
+    /// Same as [`Maildir::read_sorted_by_date`], but sorted by
+    /// descending received date (newest first).
+    pub fn read_sorted_by_date_desc(&self) -> Result<Vec<MaildirEntry>> {
+        let mut entries = self.read_sorted_by_date()?;
+        entries.reverse();
+        Ok(entries)
+    }
+
+    pub fn find(&self, id: impl AsRef<str>) -> Result<Option<MaildirEntry>> {
+        self.find_in(&[MaildirDir::New, MaildirDir::Cur], id.as_ref())
+    }
+
+    /// Finds the entry matching the given id in the given
+    /// directories, in order.
+    ///
+    /// A directory is only read when no entry of the previous ones
+    /// matches.
+    fn find_in(&self, dirs: &[MaildirDir], id: &str) -> Result<Option<MaildirEntry>> {
+        for dir in dirs {
+            let entry = fs::read_dir(self.dir_path(*dir))?
+                .filter_map(|entry| entry.ok())
+                .find_map(|entry| {
+                    // cheap check on the raw file name first, to avoid
+                    // building and parsing entries that cannot match
+                    if !self.may_have_id(entry.file_name().to_str()?, id) {
+                        return None;
+                    }
+
+                    if !entry.path().is_file() {
+                        return None;
+                    }
+
+                    let entry = self.entry(entry.path());
+
+                    if id != entry.id().ok()? {
+                        return None;
+                    }
+
+                    Some(entry)
+                });
+
+            if entry.is_some() {
+                return Ok(entry);
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Returns `true` if an entry of `new` or `cur` matches the given
+    /// id.
+    ///
+    /// Like [`Maildir::find`], reading stops at the first match, but
+    /// file names are checked in place: no entry is built. `cur` is
+    /// only read when no entry of `new` matches.
+    pub fn contains(&self, id: impl AsRef<str>) -> Result<bool> {
+        let id = id.as_ref();
+
+        for dir in [MaildirDir::New, MaildirDir::Cur] {
+            for entry in fs::read_dir(self.dir_path(dir))? {
+                let Ok(entry) = entry else {
+                    continue;
+                };
+
+                let file_name = entry.file_name();
+
+                let Some(file_name) = file_name.to_str() else {
+                    continue;
+                };
+
+                if !self.may_have_id(file_name, id) {
+                    continue;
+                }
+
+                if ParsedName::new(file_name, &self.inner.info_separator).id != id.len() {
+                    continue;
+                }
+
+                // symlinks are followed, like with find
+                let is_file = match entry.file_type() {
+                    Ok(file_type) if file_type.is_file() => true,
+                    Ok(file_type) if file_type.is_symlink() => entry.path().is_file(),
+                    _ => false,
+                };
+
+                if is_file {
+                    return Ok(true);
+                }
+            }
+        }
+
+        Ok(false)
+    }
+
+    /// Returns `true` if the given file name starts with the given id,
+    /// followed by either nothing, a `,X=` field or the info
+    /// separator.
+    fn may_have_id(&self, file_name: &str, id: &str) -> bool {
+        match file_name.strip_prefix(id) {
+            Some(rest) => {
+                rest.is_empty()
+                    || rest.starts_with(',')
+                    || rest.starts_with(&*self.inner.info_separator)
+            }
+            None => false,
+        }
+    }
+
+    pub fn get(&self, id: impl AsRef<str>) -> Result<MaildirEntry> {
+        let id = id.as_ref();
+
+        match self.find(id)? {
+            Some(mdir) => Ok(mdir),
+            None => Err(Error::GetMaildirEntryNotFoundError(id.to_owned())),
+        }
+    }
+
+    /// Returns the flags of the entry matching the given id.
+    ///
+    /// Fails with [`Error::GetMaildirEntryNotFoundError`] if no entry
+    /// matches the id.
+    pub fn flags_of(&self, id: impl AsRef<str>) -> Result<HashSet<Flag>> {
+        self.get(id)?.flags()
+    }
+
+    /// Replaces the flags of the entry matching the given id.
+    ///
+    /// The entry is renamed atomically, like with
+    /// [`MaildirEntry::update_flags`]. Fails with
+    /// [`Error::GetMaildirEntryNotFoundError`] if no entry matches
+    /// the id.
+    pub fn set_flags(
+        &self,
+        id: impl AsRef<str>,
+        flags: impl IntoIterator<Item = Flag>,
+    ) -> Result<()> {
+        self.get(id)?.update_flags(flags)
+    }
+
+    /// Replaces the flags of many entries at once, by id.
+    ///
+    /// Matching entries are renamed once per change, like with
+    /// [`Maildir::set_flags`]. Changes are applied in order, so the
+    /// last change of an id wins. Returns the ids that did not match
+    /// any entry.
+    pub fn update_flags_bulk(&self, changes: &[(String, HashSet<Flag>)]) -> Result<Vec<String>> {
+        let mut entries = self.index_by_id(changes.iter().map(|(id, _)| id.as_str()))?;
+        let mut misses = Vec::new();
+
+        for (id, flags) in changes {
+            match entries.get_mut(id.as_str()) {
+                Some(entry) => entry.update_flags(flags.iter().copied())?,
+                None => misses.push(id.clone()),
+            }
+        }
+
+        Ok(misses)
+    }
+
+    /// Finds the entries matching the given ids at once.
+    ///
+    /// Unlike calling [`Maildir::find`] for every id, `new` and `cur`
+    /// are read only once, which makes the lookup O(n + m) instead of
+    /// O(n × m). Results are returned in the order of the given ids,
+    /// with `None` for ids that did not match any entry.
+    pub fn get_many(&self, ids: &[&str]) -> Result<Vec<(String, Option<MaildirEntry>)>> {
+        let entries = self.index_by_id(ids.iter().copied())?;
+
+        Ok(ids
+            .iter()
+            .map(|id| (id.to_string(), entries.get(id).cloned()))
+            .collect())
+    }
+
+    /// Compares entries of `new` and `cur` of the current Maildir
+    /// with the ones of the given Maildir, by id.
+    ///
+    /// Each Maildir is read once. Only ids and flags are compared,
+    /// not contents.
+    pub fn diff(&self, other: &Maildir) -> Result<MaildirDiff> {
+        let flags_by_id = |mdir: &Maildir| -> Result<HashMap<String, HashSet<Flag>>> {
+            mdir.read()?
+                .map(|entry| {
+                    let entry = entry?;
+                    Ok((entry.id()?.to_owned(), entry.flags()?))
+                })
+                .collect()
+        };
+
+        let ours = flags_by_id(self)?;
+        let mut theirs = flags_by_id(other)?;
+        let mut diff = MaildirDiff::default();
+
+        for (id, flags) in ours {
+            match theirs.remove(&id) {
+                None => diff.only_in_self.push(id),
+                Some(other_flags) if other_flags != flags => diff.in_both_flags_differ.push(id),
+                Some(_) => (),
+            }
+        }
+
+        diff.only_in_other = theirs.into_keys().collect();
+
+        diff.only_in_self.sort();
+        diff.only_in_other.sort();
+        diff.in_both_flags_differ.sort();
+
+        Ok(diff)
+    }
+
+    /// Reads `new` and `cur` once, and indexes the entries matching
+    /// the given ids by id.
+    fn index_by_id<'a>(
+        &self,
+        ids: impl IntoIterator<Item = &'a str>,
+    ) -> Result<HashMap<&'a str, MaildirEntry>> {
+        let ids: HashSet<&str> = ids.into_iter().collect();
+        let mut entries = HashMap::new();
+
+        for entry in fs::read_dir(&self.inner.new)?.chain(fs::read_dir(&self.inner.cur)?) {
+            let path = entry?.path();
+
+            if !path.is_file() {
+                continue;
+            }
+
+            let entry = self.entry(path);
+
+            if let Some(id) = entry.id().ok().and_then(|id| ids.get(id)) {
+                entries.insert(*id, entry);
+            }
+        }
+
+        Ok(entries)
+    }
+
+    pub fn write_new(&self, contents: impl AsRef<[u8]>) -> Result<MaildirEntry> {
+        Ok(self.write(contents, None, true, None, None, None)?.0)
+    }
+
+    pub fn write_cur(
+        &self,
+        contents: impl AsRef<[u8]>,
+        flags: impl IntoIterator<Item = Flag>,
+    ) -> Result<MaildirEntry> {
+        Ok(self.write(contents, flags, false, None, None, None)?.0)
+    }
+
+    /// Writes the given contents to `cur` with the given flags, using
+    /// the given date as the time of the generated id.
+    ///
+    /// This is meant for imports and migrations: the leading
+    /// `<secs>.` part of the id reflects the original date of the
+    /// message, like its `Date` header, so that sorting by received
+    /// date (see [`MaildirEntry::received_date`]) matches the
+    /// original order. The other parts of the id still make it
+    /// unique.
+    pub fn write_cur_with_date(
+        &self,
+        contents: impl AsRef<[u8]>,
+        flags: impl IntoIterator<Item = Flag>,
+        date: SystemTime,
+    ) -> Result<MaildirEntry> {
+        Ok(self
+            .write(contents, flags, false, None, None, Some(date))?
+            .0)
+    }
+
+    /// Writes the given contents to either `new` or `cur`, and
+    /// returns the written entry along with its generated id.
+    ///
+    /// Unlike [`MaildirEntry::id`], getting the id this way cannot
+    /// fail, since it does not need to be parsed back from the file
+    /// name. Flags are ignored when writing to `new`.
+    pub fn store_and_get_id(
+        &self,
+        contents: impl AsRef<[u8]>,
+        flags: impl IntoIterator<Item = Flag>,
+        new: bool,
+    ) -> Result<(MaildirEntry, String)> {
+        self.write(contents, flags, new, None, None, None)
+    }
+
+    /// Writes the given contents to `new`, using the given id instead
+    /// of generating one.
+    ///
+    /// This allows idempotent redeliveries: if an entry with the same
+    /// id already exists in `new` or `cur`, an
+    /// [`Error::AlreadyExistsError`] is returned instead of
+    /// overwriting it. Concurrent deliveries of the same id never
+    /// overwrite each other, but they are only rejected when they
+    /// target the same file name: deliveries to different directories,
+    /// with different flags or sizes, can both succeed. The id cannot
+    /// be empty, start with a dot, nor contain a slash or the info
+    /// separator.
+    pub fn write_new_with_id(
+        &self,
+        contents: impl AsRef<[u8]>,
+        id: impl ToString,
+    ) -> Result<MaildirEntry> {
+        let id = self.validate_new_id(id.to_string())?;
+        Ok(self.write(contents, None, true, Some(id), None, None)?.0)
+    }
+
+    /// Writes the given contents to `cur` with the given flags, using
+    /// the given id instead of generating one.
+    ///
+    /// See [`Maildir::write_new_with_id`].
+    pub fn write_cur_with_id(
+        &self,
+        contents: impl AsRef<[u8]>,
+        flags: impl IntoIterator<Item = Flag>,
+        id: impl ToString,
+    ) -> Result<MaildirEntry> {
+        let id = self.validate_new_id(id.to_string())?;
+        Ok(self.write(contents, flags, false, Some(id), None, None)?.0)
+    }
+
+    /// Writes the given contents to `cur` with the given flags,
+    /// storing the given SMTP envelope sender along with the entry.
+    ///
+    /// The envelope sender is stored in a `,E=<sender>` file name
+    /// field, like the `,S=<size>` field: it does not belong to the
+    /// id, and it is used by [`MaildirEntry::to_mbox`] to build an
+    /// accurate `From ` line. An empty sender stands for the null
+    /// sender `<>` of bounces. The sender cannot contain whitespaces,
+    /// commas, slashes, NUL nor the info separator.
+    pub fn deliver_with_envelope(
+        &self,
+        contents: impl AsRef<[u8]>,
+        flags: impl IntoIterator<Item = Flag>,
+        sender: &str,
+    ) -> Result<MaildirEntry> {
+        let invalid = sender.contains(&*self.inner.info_separator)
+            || sender.contains(|c: char| c.is_whitespace() || matches!(c, ',' | '/' | '\0'));
+
+        if invalid {
+            return Err(Error::InvalidEnvelopeSenderError(sender.to_owned()));
+        }
+
+        Ok(self
+            .write(contents, flags, false, None, Some(sender), None)?
+            .0)
+    }
+
+    /// Same as [`Maildir::write_new`], but using [`tokio::fs`] so
+    /// that the executor is not blocked.
+    #[cfg(feature = "tokio")]
+    pub async fn write_new_async(&self, contents: impl AsRef<[u8]>) -> Result<MaildirEntry> {
+        self.write_async(contents, None, true).await
+    }
+
+    /// Same as [`Maildir::write_cur`], but using [`tokio::fs`] so
+    /// that the executor is not blocked.
+    #[cfg(feature = "tokio")]
+    pub async fn write_cur_async(
+        &self,
+        contents: impl AsRef<[u8]>,
+        flags: impl IntoIterator<Item = Flag>,
+    ) -> Result<MaildirEntry> {
+        self.write_async(contents, flags, false).await
+    }
+
+    /// Validates a caller-supplied id, and ensures no entry uses it
+    /// yet.
+    fn validate_new_id(&self, id: String) -> Result<String> {
+        validate::validate_id(&id)?;
+
+        if id.contains(&*self.inner.info_separator) {
+            return Err(Error::InvalidIdError(id));
+        }
+
+        if let Some(entry) = self.find(&id)? {
+            return Err(Error::AlreadyExistsError(entry.path));
+        }
+
+        Ok(id)
+    }
+
+    /// Delivers an existing file to the current Maildir.
+    ///
+    /// The file is hard linked into place when it lives on the same
+    /// filesystem as the Maildir, otherwise it is copied. Either way,
+    /// the file contents are never loaded into memory and the source
+    /// file is left untouched. The modification time of the source
+    /// file is preserved, unless disabled with
+    /// [`MaildirBuilder::set_preserve_mtime`].
+    ///
+    /// A hard link shares the permissions, owner and modification
+    /// time of its source, so the file is always copied when any of
+    /// them has to change: when the modification time is not
+    /// preserved, or when [`MaildirBuilder::set_file_mode`] or
+    /// [`MaildirBuilder::set_owner`] is configured.
+    pub fn deliver_file(
+        &self,
+        path: impl AsRef<Path>,
+        flags: impl IntoIterator<Item = Flag>,
+        new: bool,
+    ) -> Result<MaildirEntry> {
+        let src = path.as_ref();
+
+        let linkable = self.inner.preserve_mtime
+            && self.inner.file_mode.is_none()
+            && self.inner.owner.is_none();
+
+        let tmp = if linkable {
+            self.link_tmp_file(src)?
+        } else {
+            None
+        };
+
+        let tmp = match tmp {
+            Some(tmp) => tmp,
+            None => self.copy_tmp_file(src)?,
+        };
+
+        let meta = fs::metadata(tmp.path())?;
+        let id = generate_id(&meta);
+        let size = meta.len();
+        let entry = self.commit_tmp_file(
+            tmp.path(),
+            self.unique_name(id, size, None),
+            flags,
+            new,
+            false,
+        )?;
+        tmp.disarm();
+        self.track_quota(size);
+
+        Ok(entry)
+    }
+
+    /// Writes the given contents to `tmp`, without making them
+    /// visible yet.
+    ///
+    /// This allows two-phase deliveries, like SMTP transactions that
+    /// are accepted only once all the data has been received: the
+    /// returned [`StagedMessage`] is either committed to `new` or
+    /// `cur`, or aborted. The given flags are used when committing to
+    /// `cur`. The tmp file is removed when the handle is dropped
+    /// without being committed.
+    pub fn stage(
+        &self,
+        contents: impl AsRef<[u8]>,
+        flags: impl IntoIterator<Item = Flag>,
+    ) -> Result<StagedMessage> {
+        let (tmp_path, mut tmp_file) = self.create_tmp_file()?;
+        let tmp = RemoveOnDrop::new(tmp_path);
+
+        tmp_file.write_all(contents.as_ref())?;
+        tmp_file.sync_all()?;
+
+        let size = contents.as_ref().len() as u64;
+        let id = generate_id(&tmp_file.metadata()?);
+
+        Ok(StagedMessage {
+            mdir: self.clone(),
+            tmp,
+            unique_name: self.unique_name(id, size, None),
+            flags: flags.into_iter().collect(),
+            size,
+        })
+    }
+
+    fn write(
+        &self,
+        contents: impl AsRef<[u8]>,
+        flags: impl IntoIterator<Item = Flag>,
+        new: bool,
+        id: Option<String>,
+        sender: Option<&str>,
+        date: Option<SystemTime>,
+    ) -> Result<(MaildirEntry, String)> {
+        let (tmp_path, mut tmp_file) = self.create_tmp_file()?;
+
+        // ensure that the tmp file is removed if anything fails
+        // before it is moved to either new or cur
+        let tmp = RemoveOnDrop::new(tmp_path);
+
+        tmp_file.write_all(contents.as_ref())?;
+        tmp_file.sync_all()?;
+
+        let size = contents.as_ref().len() as u64;
+
+        // caller-supplied ids may be delivered concurrently, so they
+        // must not replace an existing entry
+        let no_replace = id.is_some();
+        let id = match id {
+            Some(id) => id,
+            None => generate_id_at(&tmp_file.metadata()?, date.unwrap_or_else(SystemTime::now)),
+        };
+        let unique_name = self.unique_name(id.clone(), size, sender);
+        let entry = self.commit_tmp_file(tmp.path(), unique_name, flags, new, no_replace)?;
+        tmp.disarm();
+        self.track_quota(size);
+
+        Ok((entry, id))
+    }
+
+    /// Hard links the given file into `tmp`.
+    ///
+    /// Returns `None` when the file cannot be hard linked, like when
+    /// it lives on another filesystem.
+    fn link_tmp_file(&self, src: &Path) -> Result<Option<RemoveOnDrop>> {
+        for _ in 0..MAX_DELIVERY_ATTEMPTS {
+            let path = self.inner.tmp.join(generate_tmp_name());
+
+            match fs::hard_link(src, &path) {
+                Ok(()) => return Ok(Some(RemoveOnDrop::new(path))),
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(_) => return Ok(None),
+            }
+        }
+
+        Err(Error::DeliveryRetryExhaustedError(self.inner.tmp.clone()))
+    }
+
+    /// Copies the given file into `tmp`, preserving its modification
+    /// time if configured.
+    fn copy_tmp_file(&self, src: &Path) -> Result<RemoveOnDrop> {
+        let (path, mut tmp_file) = self.create_tmp_file()?;
+        let tmp = RemoveOnDrop::new(path);
+
+        io::copy(&mut File::open(src)?, &mut tmp_file)?;
+
+        if self.inner.preserve_mtime {
+            tmp_file.set_modified(fs::metadata(src)?.modified()?)?;
+        }
+
+        tmp_file.sync_all()?;
+
+        Ok(tmp)
+    }
+
+    fn create_tmp_file(&self) -> Result<(PathBuf, File)> {
+        // retry when conflicting filenames occur, as described at
+        // <http://www.courier-mta.org/maildir.html>. There is no need
+        // to wait before retrying: each name generated by default
+        // bumps a process-wide counter, so it cannot conflict twice.
+        for _ in 0..MAX_DELIVERY_ATTEMPTS {
+            let path = self.inner.tmp.join(generate_tmp_name());
+            let open = OpenOptions::new().write(true).create_new(true).open(&path);
+
+            match open {
+                Ok(file) => {
+                    let prepared = (|| {
+                        self.apply_file_mode(&file)?;
+                        self.apply_owner(&path)
+                    })();
+
+                    if let Err(err) = prepared {
+                        let _ = fs::remove_file(&path);
+                        return Err(err);
+                    }
+
+                    return Ok((path, file));
+                }
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {
+                    continue;
+                }
+                Err(err) => {
+                    return Err(err.into());
+                }
+            }
+        }
+
+        Err(Error::DeliveryRetryExhaustedError(self.inner.tmp.clone()))
+    }
+
+    /// Async counterpart of [`Maildir::write`].
+    ///
+    /// The tmp file is removed if anything fails before it is renamed
+    /// to either `new` or `cur`.
+    #[cfg(feature = "tokio")]
+    async fn write_async(
+        &self,
+        contents: impl AsRef<[u8]>,
+        flags: impl IntoIterator<Item = Flag>,
+        new: bool,
+    ) -> Result<MaildirEntry> {
+        use tokio::io::AsyncWriteExt;
+
+        let (tmp_path, mut tmp_file) = self.create_tmp_file_async().await?;
+
+        let written = async {
+            tmp_file.write_all(contents.as_ref()).await?;
+            tmp_file.sync_all().await?;
+
+            let meta = tmp_file.metadata().await?;
+            let id = self.unique_name(generate_id(&meta), meta.len(), None);
+            let next_path = self.next_path(id, flags, new);
+            tokio::fs::rename(&tmp_path, &next_path).await?;
+
+            #[cfg(unix)]
+            if self.inner.durable {
+                let dir = if new {
+                    &self.inner.new
+                } else {
+                    &self.inner.cur
+                };
+                tokio::fs::File::open(dir).await?.sync_all().await?;
+            }
+
+            Ok((next_path, meta.len()))
+        };
+
+        match written.await {
+            Ok((path, size)) => {
+                if self.inner.quota_tracking {
+                    let _ = crate::quota::append_quota_delta_async(&self.inner.root, size).await;
+                }
+
+                Ok(self.entry(path))
+            }
+            Err(err) => {
+                let _ = tokio::fs::remove_file(&tmp_path).await;
+                Err(err)
+            }
+        }
+    }
+
+    /// Async counterpart of [`Maildir::create_tmp_file`].
+    #[cfg(feature = "tokio")]
+    async fn create_tmp_file_async(&self) -> Result<(PathBuf, tokio::fs::File)> {
+        for _ in 0..MAX_DELIVERY_ATTEMPTS {
+            let path = self.inner.tmp.join(generate_tmp_name());
+            let open = tokio::fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&path)
+                .await;
+
+            match open {
+                Ok(file) => {
+                    let prepared = async {
+                        #[cfg(unix)]
+                        if let Some(mode) = self.inner.file_mode {
+                            use std::os::unix::fs::PermissionsExt;
+                            file.set_permissions(fs::Permissions::from_mode(mode))
+                                .await?;
+                        }
+
+                        self.apply_owner(&path)
+                    };
+
+                    if let Err(err) = prepared.await {
+                        let _ = tokio::fs::remove_file(&path).await;
+                        return Err(err);
+                    }
+
+                    return Ok((path, file));
+                }
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {
+                    continue;
+                }
+                Err(err) => {
+                    return Err(err.into());
+                }
+            }
+        }
+
+        Err(Error::DeliveryRetryExhaustedError(self.inner.tmp.clone()))
+    }
+
+    /// Moves the given tmp file to either `new` or `cur`.
+    ///
+    /// When `no_replace` is set, an entry already using the next path
+    /// is never replaced: the tmp file is hard linked into place then
+    /// removed, so that the check and the move are atomic, and an
+    /// [`Error::AlreadyExistsError`] is returned when the path is
+    /// taken. Filesystems without hard links fall back to a regular
+    /// rename, checked beforehand.
+    fn commit_tmp_file(
+        &self,
+        tmp_path: &Path,
+        id: String,
+        flags: impl IntoIterator<Item = Flag>,
+        new: bool,
+        no_replace: bool,
+    ) -> Result<MaildirEntry> {
+        let next_parent_path = if new {
+            &self.inner.new
+        } else {
+            &self.inner.cur
+        };
+        let next_path = self.next_path(id, flags, new);
+
+        if !no_replace {
+            fs::rename(tmp_path, &next_path)?;
+        } else {
+            match fs::hard_link(tmp_path, &next_path) {
+                Ok(()) => fs::remove_file(tmp_path)?,
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {
+                    return Err(Error::AlreadyExistsError(next_path));
+                }
+                Err(_) if next_path.exists() => {
+                    return Err(Error::AlreadyExistsError(next_path));
+                }
+                Err(_) => fs::rename(tmp_path, &next_path)?,
+            }
+        }
+
+        self.sync_dir(next_parent_path)?;
+
+        let entry = fs::read_dir(next_parent_path)?
+            .filter_map(|entry| entry.ok())
+            .find(|entry| entry.path() == next_path);
+
+        match entry {
+            Some(entry) => Ok(self.entry(entry.path())),
+            None => Err(Error::FindNewMaildirEntryError(next_path)),
+        }
+    }
+
+    /// Syncs the given directory, if deliveries are durable.
+    fn sync_dir(&self, #[allow(unused)] dir: &Path) -> Result<()> {
+        #[cfg(unix)]
+        if self.inner.durable {
+            File::open(dir)?.sync_all()?;
+        }
+
+        Ok(())
+    }
+
+    /// Appends a delta line for a delivered entry of the given size to
+    /// the `maildirsize` file, if quota tracking is enabled.
+    fn track_quota(&self, size: u64) {
+        if self.inner.quota_tracking {
+            let _ = append_quota_delta(&self.inner.root, size);
+        }
+    }
+
+    /// Builds the path of a new entry, in either `new` or `cur`.
+    fn next_path(&self, id: String, flags: impl IntoIterator<Item = Flag>, new: bool) -> PathBuf {
+        if new {
+            self.inner.new.join(id)
+        } else {
+            self.inner
+                .cur
+                .join(self.format_file_name(id, flags.into_iter().collect()))
+        }
+    }
+
+    /// Builds the unique name of a new entry from its id, appending
+    /// the size field if enabled and the envelope sender field if
+    /// any.
+    fn unique_name(&self, id: String, size: u64, sender: Option<&str>) -> String {
+        let mut name = id;
+
+        if self.inner.size_field {
+            name.push_str(&format!(",S={size}"));
+        }
+
+        if let Some(sender) = sender {
+            name.push_str(&format!(",E={sender}"));
+        }
+
+        name
+    }
+
+    pub(crate) fn entry(&self, path: impl Into<PathBuf>) -> MaildirEntry {
+        MaildirEntry::parse(path.into(), self.inner.info_separator.clone())
+    }
+
+    fn format_file_name(&self, id: String, flags: HashSet<Flag>) -> String {
+        format_file_name(&self.inner.info_separator, id, flags, "")
+    }
+}
+
+impl Hash for Maildir {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.inner.root.hash(state);
+    }
+}
+
+/// Iterates over the entries of the Maildir, see [`Maildir::read`].
+///
+/// When the Maildir cannot be read, the error is yielded as the only
+/// item of the iterator.
+impl IntoIterator for &Maildir {
+    type Item = Result<MaildirEntry>;
+    type IntoIter = MaildirEntries;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.read().unwrap_or_else(|err| MaildirEntries {
+            info_separator: self.inner.info_separator.clone(),
+            dirs: VecDeque::new(),
+            pending_dirs: VecDeque::new(),
+            sorted: None,
+            error: Some(err),
+        })
+    }
+}
+
+impl<P: Into<PathBuf>> From<P> for Maildir {
+    fn from(root: P) -> Self {
+        let root = root.into();
+
+        let new = root.join(NEW);
+        let cur = root.join(CUR);
+        let tmp = root.join(TMP);
+
+        let inner = MaildirInner {
+            root,
+            new,
+            cur,
+            tmp,
+            info_separator: Cow::Borrowed(DEFAULT_INFO_SEPARATOR),
+            maildirpp: false,
+            size_field: false,
+            stable_order: false,
+            quota_tracking: false,
+            durable: false,
+            duplicate_policy: DuplicatePolicy::default(),
+            file_mode: None,
+            dir_mode: None,
+            owner: None,
+            preserve_mtime: true,
+        };
+
+        Self {
+            inner: Arc::new(inner),
+        }
+    }
+}
+
+fn validate_info_separator(sep: &str) -> Result<()> {
+    let mut chars = sep.chars();
+
+    match (chars.next(), chars.next()) {
+        (Some('/' | '.' | '\0'), _) | (None, _) | (_, Some(_)) => {
+            Err(Error::InvalidInfoSeparatorError(sep.to_owned()))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Reads the entry files of the given directory, skipping
+/// directories and dotfiles.
+fn read_entries(dir: &Path) -> Result<impl Iterator<Item = fs::DirEntry>> {
+    Ok(fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok())
+        .filter(is_entry))
+}
+
+/// Moves or copies the given source file to the given destination
+/// path, failing with [`io::ErrorKind::AlreadyExists`] if the path is
+/// already taken.
+///
+/// Moves hard link the file then remove the source, since a rename
+/// would silently replace an existing file. Copies create the
+/// destination file exclusively.
+fn transfer_new(src: &Path, dest: &Path, copy: bool) -> io::Result<()> {
+    if copy {
+        let mut file = OpenOptions::new().write(true).create_new(true).open(dest)?;
+        let guard = RemoveOnDrop::new(dest.to_owned());
+        io::copy(&mut File::open(src)?, &mut file)?;
+        file.set_permissions(fs::metadata(src)?.permissions())?;
+        guard.disarm();
+        return Ok(());
+    }
+
+    match fs::hard_link(src, dest) {
+        Ok(()) => fs::remove_file(src),
+        Err(err) if err.kind() == io::ErrorKind::AlreadyExists => Err(err),
+        // hard links are not supported everywhere
+        Err(_) if dest.exists() => Err(io::ErrorKind::AlreadyExists.into()),
+        Err(_) => fs::rename(src, dest),
+    }
+}
+
+/// Moves or copies the given source file to the given file name in
+/// the given directory, and returns its next path.
+///
+/// If the file name is already taken, the id of the file name is
+/// replaced by a newly generated one, computed from the metadata of
+/// the given source file. Fields and the info section are preserved.
+fn transfer_free(
+    src: &Path,
+    dir: &Path,
+    file_name: &str,
+    sep: &str,
+    copy: bool,
+) -> Result<PathBuf> {
+    let mut path = dir.join(file_name);
+
+    for _ in 0..MAX_DELIVERY_ATTEMPTS {
+        match transfer_new(src, &path, copy) {
+            Ok(()) => return Ok(path),
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {
+                path = dir.join(free_file_name(src, file_name, sep)?);
+            }
+            Err(err) => return Err(err.into()),
+        }
+    }
+
+    Err(Error::DeliveryRetryExhaustedError(dir.to_owned()))
+}
+
+/// Returns the given file name with a newly generated id, computed
+/// from the metadata of the given source file.
+fn free_file_name(src: &Path, file_name: &str, sep: &str) -> Result<String> {
+    let (unique_name, info) = match file_name.rsplit_once(sep) {
+        Some((name, info)) => (name, Some(info)),
+        None => (file_name, None),
+    };
+
+    let mut file_name = generate_id(&fs::metadata(src)?);
+
+    match split_unique_name(unique_name) {
+        (_, "") => (),
+        (_, fields) => file_name.push_str(&format!(",{fields}")),
+    }
+
+    if let Some(info) = info {
+        file_name.push_str(&format!("{sep}{info}"));
+    }
+
+    Ok(file_name)
+}
+
+/// Returns `true` if the given walk error is caused by a missing
+/// file or directory.
+fn is_not_found(err: &walkdir::Error) -> bool {
+    err.io_error().map(io::Error::kind) == Some(io::ErrorKind::NotFound)
+}
+
+/// Returns `true` if the given directory entry is a message file,
+/// which excludes directories, dotfiles and file names that are not
+/// valid UTF-8.
+fn is_entry(entry: &fs::DirEntry) -> bool {
+    entry.path().is_file()
+        && entry
+            .file_name()
+            .to_str()
+            .map(|s| !s.starts_with('.'))
+            .unwrap_or(false)
+}
+
+/// Reads headers from the given reader, up to and including the
+/// first empty line.
+fn read_headers(reader: &mut impl BufRead) -> Result<Vec<u8>> {
+    let mut buffer = Vec::<u8>::new();
+    let mut headers = Vec::<u8>::new();
+
+    loop {
+        match reader.read_until(b'\n', &mut buffer)? {
+            0 => {
+                break;
+            }
+            1 if buffer[0] == b'\n' => {
+                headers.push(b'\n');
+                break;
+            }
+            2 if buffer[0] == b'\r' && buffer[1] == b'\n' => {
+                headers.extend([b'\r', b'\n']);
+                break;
+            }
+            _ => {
+                headers.extend(&buffer);
+                buffer.clear();
+            }
+        }
+    }
+
+    Ok(headers)
+}
+
+/// Writes the given contents to a temporary sibling file, then
+/// renames it to the given path so that readers never see a partial
+/// write.
+pub(crate) fn write_atomically(path: impl AsRef<Path>, contents: impl AsRef<[u8]>) -> Result<()> {
+    let path = path.as_ref();
+    let file_name = path.file_name().and_then(OsStr::to_str).unwrap_or_default();
+    let tmp_path = path.with_file_name(format!(".{file_name}.{}", generate_tmp_id()));
+
+    let mut tmp_file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&tmp_path)?;
+
+    if let Err(err) = tmp_file
+        .write_all(contents.as_ref())
+        .and_then(|()| tmp_file.sync_all())
+        .and_then(|()| fs::rename(&tmp_path, path))
+    {
+        let _ = fs::remove_file(&tmp_path);
+        return Err(err.into());
+    }
+
+    Ok(())
+}
+
+/// The changes made by [`Maildir::repair`], as pairs of previous and
+/// next paths.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct RepairReport {
+    /// The entries of `new` whose info section has been stripped.
+    pub stripped: Vec<(PathBuf, PathBuf)>,
+
+    /// The entries of `new` moved to `cur`, along with their flags.
+    pub moved: Vec<(PathBuf, PathBuf)>,
+}
+
+impl RepairReport {
+    /// Returns `true` if no change has been made.
+    pub fn is_empty(&self) -> bool {
+        self.stripped.is_empty() && self.moved.is_empty()
+    }
+}
+
+/// The differences between two Maildirs, returned by
+/// [`Maildir::diff`].
+///
+/// Ids are sorted.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct MaildirDiff {
+    /// The ids of entries of the current Maildir only.
+    pub only_in_self: Vec<String>,
+
+    /// The ids of entries of the other Maildir only.
+    pub only_in_other: Vec<String>,
+
+    /// The ids of entries of both Maildirs, with different flags.
+    pub in_both_flags_differ: Vec<String>,
+}
+
+impl MaildirDiff {
+    /// Returns `true` if both Maildirs have the same entries, with
+    /// the same flags.
+    pub fn is_empty(&self) -> bool {
+        self.only_in_self.is_empty()
+            && self.only_in_other.is_empty()
+            && self.in_both_flags_differ.is_empty()
+    }
+}
+
+/// The parts of a unique name generated by this crate, as
+/// `<secs>.#<counter>M<nanos>P<pid>V<dev>I<ino>.<host>`.
+///
+/// Names generated by other tools following the same pattern, like
+/// `<secs>.M<nanos>P<pid>.<host>`, are parsed as well: parts they do
+/// not contain are `None`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct UniqueName {
+    /// The seconds since the Unix epoch the name was generated at.
+    pub secs: u64,
+
+    /// The nanoseconds part of the time the name was generated at.
+    pub nanos: Option<u32>,
+
+    /// The delivery counter of the process, stored in hexadecimal.
+    pub counter: Option<u64>,
+
+    /// The identifier of the process that generated the name.
+    pub pid: Option<u32>,
+
+    /// The device number of the delivered file.
+    pub dev: Option<u64>,
+
+    /// The inode number of the delivered file.
+    pub ino: Option<u64>,
+
+    /// The hostname, as stored: `/`, `:` and `;` characters remain
+    /// escaped.
+    pub host: String,
+}
+
+impl UniqueName {
+    /// Parses the given unique name, without fields nor info
+    /// section.
+    ///
+    /// Returns `None` when the name does not match the pattern, like
+    /// the `<secs>.<pid>_<counter>.<host>` names of qmail.
+    pub fn parse(name: &str) -> Option<UniqueName> {
+        let (secs, rest) = name.split_once('.')?;
+        let (mut parts, host) = rest.split_once('.')?;
+
+        if secs.is_empty() || !secs.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+
+        if parts.is_empty() || host.is_empty() {
+            return None;
+        }
+
+        let mut name = UniqueName {
+            secs: secs.parse().ok()?,
+            nanos: None,
+            counter: None,
+            pid: None,
+            dev: None,
+            ino: None,
+            host: host.to_owned(),
+        };
+
+        while let Some(prefix) = parts.chars().next() {
+            let radix = if prefix == '#' { 16 } else { 10 };
+            let rest = &parts[prefix.len_utf8()..];
+            let len = rest
+                .find(|c: char| !c.is_digit(radix) || c.is_ascii_uppercase())
+                .unwrap_or(rest.len());
+            let (value, rest) = rest.split_at(len);
+
+            if value.is_empty() {
+                return None;
+            }
+
+            let value = u64::from_str_radix(value, radix).ok()?;
+
+            let part = match prefix {
+                '#' => name.counter.replace(value),
+                'M' => name.nanos.replace(value.try_into().ok()?).map(u64::from),
+                'P' => name.pid.replace(value.try_into().ok()?).map(u64::from),
+                'V' => name.dev.replace(value),
+                'I' => name.ino.replace(value),
+                _ => return None,
+            };
+
+            // each part can only appear once
+            if part.is_some() {
+                return None;
+            }
+
+            parts = rest;
+        }
+
+        Some(name)
+    }
+}
+
+/// A message written to `tmp` but not delivered yet, returned by
+/// [`Maildir::stage`].
+///
+/// The tmp file is removed when the message is dropped without being
+/// committed.
+#[derive(Debug)]
+pub struct StagedMessage {
+    mdir: Maildir,
+    tmp: RemoveOnDrop,
+    unique_name: String,
+    flags: HashSet<Flag>,
+    size: u64,
+}
+
+impl StagedMessage {
+    /// Returns the path of the tmp file of the staged message.
+    pub fn path(&self) -> &Path {
+        self.tmp.path()
+    }
+
+    /// Delivers the staged message to `new`.
+    pub fn commit_new(self) -> Result<MaildirEntry> {
+        self.commit(true)
+    }
+
+    /// Delivers the staged message to `cur`, with the flags given
+    /// when staging.
+    pub fn commit_cur(self) -> Result<MaildirEntry> {
+        self.commit(false)
+    }
+
+    /// Removes the tmp file of the staged message.
+    pub fn abort(self) -> Result<()> {
+        fs::remove_file(self.tmp.path())?;
+        self.tmp.disarm();
+        Ok(())
+    }
+
+    fn commit(self, new: bool) -> Result<MaildirEntry> {
+        let entry =
+            self.mdir
+                .commit_tmp_file(self.tmp.path(), self.unique_name, self.flags, new, false)?;
+        self.tmp.disarm();
+        self.mdir.track_quota(self.size);
+
+        Ok(entry)
+    }
+}
+
+/// Guard removing a tmp file when dropped, unless it is disarmed
+/// once the tmp file has been moved to its final location.
+#[derive(Debug)]
+struct RemoveOnDrop {
+    path: PathBuf,
+    armed: bool,
+}
+
+impl RemoveOnDrop {
+    fn new(path: PathBuf) -> Self {
+        Self { path, armed: true }
+    }
+
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn disarm(mut self) {
+        self.armed = false;
+    }
+}
+
+impl Drop for RemoveOnDrop {
+    fn drop(&mut self) {
+        if self.armed {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+fn generate_tmp_id() -> String {
+    generate_tmp_id_at(SystemTime::now())
+}
+
+#[cfg(test)]
+thread_local! {
+    /// Overrides the names of tmp files generated by the current
+    /// thread, in order to reproduce conflicts.
+    static TMP_NAME_GENERATOR: std::cell::Cell<Option<fn() -> String>> =
+        const { std::cell::Cell::new(None) };
+}
+
+/// Generates the name of a new tmp file.
+fn generate_tmp_name() -> String {
+    #[cfg(test)]
+    if let Some(generator) = TMP_NAME_GENERATOR.with(|generator| generator.get()) {
+        return generator();
+    }
+
+    generate_tmp_id()
+}
+
+/// Generates a tmp id whose time parts come from the given date.
+///
+/// Dates before the Unix epoch are considered at the epoch.
+fn generate_tmp_id_at(date: SystemTime) -> String {
+    let ts = date.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = ts.as_secs();
+    let nanos = ts.subsec_nanos();
+    let counter = COUNTER.fetch_add(1, Ordering::SeqCst);
+
+    format!(
+        "{secs}.#{counter:x}M{nanos}P{pid}",
+        secs = secs,
+        counter = counter,
+        nanos = nanos,
+        pid = process::id()
+    )
+}
+
+fn generate_id(meta: &fs::Metadata) -> String {
+    generate_id_at(meta, SystemTime::now())
+}
+
+/// Generates an id whose time parts come from the given date, see
+/// [`generate_tmp_id_at`].
+fn generate_id_at(meta: &fs::Metadata, date: SystemTime) -> String {
+    #[cfg(unix)]
+    let dev = meta.dev();
+    #[cfg(windows)]
+    let dev: u64 = 0;
+
+    #[cfg(unix)]
+    let ino = meta.ino();
+    #[cfg(windows)]
+    let ino: u64 = 0;
+
+    let hostname = sanitize_hostname(&gethostname().to_string_lossy());
+
+    format!("{}V{dev}I{ino}.{hostname}", generate_tmp_id_at(date))
+}
+
+/// Sanitizes the given hostname so that it can be used in entry
+/// file names.
+///
+/// As recommended by <http://www.courier-mta.org/maildir.html>,
+/// `/` and `:` are replaced by their octal escapes, and so is `;`,
+/// which is the info separator on Windows.
+fn sanitize_hostname(hostname: &str) -> String {
+    hostname
+        .replace('/', r"\057")
+        .replace(':', r"\072")
+        .replace(';', r"\073")
+}
+
+// =============================== LIST ================================
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Maildirs {
+    root: PathBuf,
+    maildirpp: bool,
+    info_separator: Cow<'static, str>,
+    hierarchy_separator: char,
+    max_depth: Option<usize>,
+    follow_links: bool,
+    inbox: Option<String>,
+    root_name: Option<String>,
+}
+
+impl Maildirs {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            root: path.into(),
+            maildirpp: false,
+            info_separator: Cow::Borrowed(DEFAULT_INFO_SEPARATOR),
+            hierarchy_separator: DEFAULT_HIERARCHY_SEPARATOR,
+            max_depth: None,
+            follow_links: false,
+            inbox: None,
+            root_name: None,
+        }
+    }
+
+    pub fn set_maildirpp(&mut self, maildirpp: bool) {
+        self.maildirpp = maildirpp;
+    }
+
+    pub fn with_maildirpp(mut self, maildirpp: bool) -> Self {
+        self.set_maildirpp(maildirpp);
+        self
+    }
+
+    /// Sets the Maildir entry id ←→ info separator.
+    ///
+    /// See [`MaildirBuilder::set_info_separator`] for the allowed
+    /// values.
+    pub fn set_info_separator(&mut self, sep: impl Into<Cow<'static, str>>) -> Result<()> {
+        let sep = sep.into();
+        validate_info_separator(&sep)?;
+        self.info_separator = sep;
+        Ok(())
+    }
+
+    pub fn with_info_separator(mut self, sep: impl Into<Cow<'static, str>>) -> Result<Self> {
+        self.set_info_separator(sep)?;
+        Ok(self)
+    }
+
+    /// Sets the separator of nested folder names, like `.` for
+    /// IMAP servers using dots as hierarchy delimiter.
+    ///
+    /// Names given to [`Maildirs`] functions are split with this
+    /// separator, and names listed by [`Maildirs::iter`] are joined
+    /// with it. With the default `/` separator, nested Maildir++
+    /// folders live inside their parent directory, like `.A/.B`.
+    /// With any other separator, they are stored flat at the root,
+    /// as the Maildir++ specification describes, like `.A.B`: dots
+    /// are then reserved as on-disk delimiters, so folder names
+    /// cannot contain them unless the separator is `.` itself.
+    pub fn set_hierarchy_separator(&mut self, sep: char) {
+        self.hierarchy_separator = sep;
+    }
+
+    pub fn with_hierarchy_separator(mut self, sep: char) -> Self {
+        self.set_hierarchy_separator(sep);
+        self
+    }
+
+    /// Sets the maximum nesting depth of listed Maildirs.
+    ///
+    /// Top-level Maildirs have a depth of 1, their nested Maildirs a
+    /// depth of 2, and so on. Deeper directories are not traversed,
+    /// which speeds up [`Maildirs::iter`] and [`Maildirs::entries`]
+    /// on large trees. The depth is unlimited by default.
+    pub fn set_max_depth(&mut self, depth: Option<usize>) {
+        self.max_depth = depth;
+    }
+
+    pub fn with_max_depth(mut self, depth: Option<usize>) -> Self {
+        self.set_max_depth(depth);
+        self
+    }
+
+    /// Makes [`Maildirs::iter`] follow symbolic links.
+    ///
+    /// Symbolic links can point outside of the root directory, or
+    /// form cycles. When enabled, directories already visited through
+    /// another path are skipped, so that listing always terminates.
+    /// When disabled, symbolic links are ignored. Disabled by
+    /// default.
+    pub fn set_follow_links(&mut self, follow: bool) {
+        self.follow_links = follow;
+    }
+
+    pub fn with_follow_links(mut self, follow: bool) -> Self {
+        self.set_follow_links(follow);
+        self
+    }
+
+    /// Sets the name of the folder holding the inbox, in non
+    /// Maildir++ layouts.
+    ///
+    /// IMAP treats `INBOX` case-insensitively, unlike other folder
+    /// names: when set, any case variant of `INBOX` resolves to the
+    /// given folder. In Maildir++ layouts, the inbox is always the
+    /// root Maildir.
+    pub fn set_inbox(&mut self, name: Option<String>) {
+        self.inbox = name;
+    }
+
+    pub fn with_inbox(mut self, name: Option<String>) -> Self {
+        self.set_inbox(name);
+        self
+    }
+
+    /// Sets the name of the root Maildir, in non Maildir++ layouts.
+    ///
+    /// When the root directory is itself a Maildir, like when clients
+    /// treat the account root as the inbox, [`Maildirs::iter`] lists
+    /// it under the given name, and the given name resolves to it.
+    /// The root Maildir is listed under an empty name by default. In
+    /// Maildir++ layouts, the root Maildir is always the inbox.
+    pub fn set_root_name(&mut self, name: Option<String>) {
+        self.root_name = name;
+    }
+
+    pub fn with_root_name(mut self, name: Option<String>) -> Self {
+        self.set_root_name(name);
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.root
+    }
+
+    /// Returns `true` if the root directory exists.
+    ///
+    /// This allows to distinguish a missing root from an empty one,
+    /// since [`Maildirs::iter`] yields nothing in both cases.
+    pub fn exists(&self) -> bool {
+        self.root.is_dir()
+    }
+
+    /// Creates the root directory and its parents, if they do not
+    /// exist yet.
+    pub fn create_root(&self) -> Result<()> {
+        fs::create_dir_all(&self.root)?;
+        Ok(())
+    }
+
+    fn maildir(&self, name: impl AsRef<str>) -> Result<Maildir> {
+        let is_inbox = name.as_ref().eq_ignore_ascii_case(INBOX);
+
+        let is_root = !self.maildirpp && self.root_name.as_deref() == Some(name.as_ref());
+
+        let path = if (self.maildirpp && is_inbox) || is_root {
+            self.root.clone()
+        } else if let (Some(inbox), true) = (&self.inbox, is_inbox) {
+            self.root.join(inbox)
+        } else if self.is_flat() {
+            let components = self.split_name(name.as_ref())?;
+            self.root.join(format!(".{}", components.join(".")))
+        } else if self.maildirpp {
+            let mut path = self.root.clone();
+
+            for component in PathBuf::from(name.as_ref()).components() {
+                if let Component::Normal(component) = component {
+                    let component = component.to_string_lossy();
+                    path.push(format!(".{}", component.trim_start_matches('.')))
+                }
+            }
+
+            path
+        } else {
+            let name = name.as_ref();
+
+            let path = if self.hierarchy_separator == DEFAULT_HIERARCHY_SEPARATOR {
+                PathBuf::from(name)
+            } else {
+                self.split_name(name)?.into_iter().collect()
+            };
+
+            // nested folders live next to the cur, new and tmp
+            // directories of their parent, so they cannot use the
+            // same names
+            let reserved = path.components().any(|c| match c {
+                Component::Normal(c) => c == CUR || c == NEW || c == TMP,
+                _ => false,
+            });
+
+            if reserved {
+                let reason = "cur, new and tmp are reserved Maildir directory names";
+                return Err(Error::InvalidFolderError(name.to_owned(), reason));
+            }
+
+            self.root.join(path)
+        };
+
+        let builder = MaildirBuilder {
+            info_separator: self.info_separator.clone(),
+            maildirpp: self.maildirpp,
+            ..Default::default()
+        };
+
+        Ok(builder.build(path))
+    }
+
+    /// Returns `true` if nested Maildir++ folders are stored flat at
+    /// the root, see [`Maildirs::set_hierarchy_separator`].
+    fn is_flat(&self) -> bool {
+        self.maildirpp && self.hierarchy_separator != DEFAULT_HIERARCHY_SEPARATOR
+    }
+
+    /// Splits the given name with the hierarchy separator, when it is
+    /// not the default one.
+    ///
+    /// Components cannot be empty nor contain slashes, and cannot
+    /// contain dots when nested folders are stored flat.
+    fn split_name<'a>(&self, name: &'a str) -> Result<Vec<&'a str>> {
+        let mut components = Vec::new();
+
+        for component in name.split(self.hierarchy_separator) {
+            let reason = if component.is_empty() {
+                "folder cannot contain empty levels"
+            } else if component.contains('/') {
+                "folder cannot contain slashes"
+            } else if self.is_flat() && component.contains('.') {
+                "folder cannot contain dots, used as Maildir++ hierarchy delimiters"
+            } else {
+                components.push(component);
+                continue;
+            };
+
+            return Err(Error::InvalidFolderError(name.to_owned(), reason));
+        }
+
+        Ok(components)
+    }
+
+    /// Joins the components of the given path, relative to the root,
+    /// into a listed name.
+    fn join_name(&self, subpath: &Path) -> Option<String> {
+        let mut components = Vec::new();
+
+        for component in subpath.components() {
+            if let Component::Normal(component) = component {
+                let component = component.to_str()?;
+
+                if self.is_flat() {
+                    components.extend(component.trim_start_matches('.').split('.'));
+                } else if self.maildirpp {
+                    components.push(component.trim_start_matches('.'));
+                } else {
+                    components.push(component);
+                }
+            }
+        }
+
+        Some(components.join(&self.hierarchy_separator.to_string()))
+    }
+
+    pub fn create(&self, name: impl ToString) -> Result<Maildir> {
+        let mdir = self.maildir(name.to_string())?;
+        mdir.create_all()?;
+        Ok(mdir)
+    }
+
+    /// Creates the Maildirs matching the given names, in order, then
+    /// returns them.
+    ///
+    /// Existing Maildirs are skipped. The first failure aborts the
+    /// creation with [`Error::CreateMaildirError`], which names the
+    /// Maildir that cannot be created.
+    pub fn create_all(&self, names: &[&str]) -> Result<Vec<Maildir>> {
+        names
+            .iter()
+            .map(|name| {
+                let create = || {
+                    let mdir = self.maildir(name)?;
+
+                    if !mdir.exists() {
+                        mdir.create_all()?;
+                    }
+
+                    Ok(mdir)
+                };
+
+                create().map_err(|err| Error::CreateMaildirError(Box::new(err), name.to_string()))
+            })
+            .collect()
+    }
+
+    /// Creates the standard set of Maildirs, then returns them.
+    ///
+    /// The standard set is made of `INBOX`, `Sent`, `Drafts`,
+    /// `Trash`, `Archive` and `Junk`. See [`Maildirs::create_all`].
+    pub fn create_standard(&self) -> Result<Vec<Maildir>> {
+        self.create_all(&STANDARD_FOLDERS)
+    }
+
+    /// Finds the Maildir matching the given name.
+    ///
+    /// Names are case-sensitive, except `INBOX` which resolves to the
+    /// root Maildir in Maildir++ layouts, or to the folder set with
+    /// [`Maildirs::set_inbox`] otherwise.
+    pub fn find(&self, name: impl AsRef<str>) -> Option<Maildir> {
+        self.maildir(name).ok().filter(|mdir| mdir.exists())
+    }
+
+    pub fn get(&self, name: impl AsRef<str>) -> Result<Maildir> {
+        let name = name.as_ref();
+
+        match self.find(name) {
+            Some(mdir) => Ok(mdir),
+            None => Err(Error::GetMaildirByNameNotFoundError(name.to_owned())),
+        }
+    }
+
+    /// Lists the Maildirs and the containers of nested Maildirs.
+    ///
+    /// Directories that are neither Maildirs nor containers are
+    /// skipped, but errors occurring while walking the tree, like
+    /// unreadable directories, are yielded. A missing root directory
+    /// yields nothing, see [`Maildirs::exists`]. A root directory
+    /// being itself a Maildir is listed too, see
+    /// [`Maildirs::set_root_name`]. Symbolic links are
+    /// ignored unless [`Maildirs::set_follow_links`] is enabled.
+    pub fn iter(&self) -> impl Iterator<Item = Result<MaildirsEntry>> + '_ {
+        let mut visited = HashSet::new();
+
+        // directories that are not Maildirs, waiting for a nested
+        // Maildir to be found in order to be listed as containers
+        let mut pending: Vec<MaildirsEntry> = Vec::new();
+
+        WalkDir::new(&self.root)
+            .follow_links(self.follow_links)
+            .max_depth(self.max_depth.unwrap_or(usize::MAX))
+            .into_iter()
+            .filter_entry(move |entry| {
+                if !self.follow_links || !entry.file_type().is_dir() {
+                    return true;
+                }
+
+                // skips directories reachable through many paths,
+                // which prevents cycles of links
+                match fs::canonicalize(entry.path()) {
+                    Ok(path) => visited.insert(path),
+                    Err(_) => true,
+                }
+            })
+            .flat_map(move |entry| match entry {
+                Ok(entry) => self.list_entries(entry, &mut pending),
+                Err(err) if err.depth() == 0 && is_not_found(&err) => vec![],
+                Err(err) if err.loop_ancestor().is_some() => vec![],
+                Err(err) => vec![Err(err.into())],
+            })
+    }
+
+    /// Lists the Maildirs and the containers of nested Maildirs,
+    /// sorted by name.
+    ///
+    /// Names are compared level by level, as split by the hierarchy
+    /// separator (see [`Maildirs::set_hierarchy_separator`]), so
+    /// that parents come right before their children. Unlike
+    /// [`Maildirs::iter`], the whole tree is collected in memory, and
+    /// the first error is returned.
+    pub fn iter_sorted(&self) -> Result<Vec<MaildirsEntry>> {
+        let mut entries = self.iter().collect::<Result<Vec<_>>>()?;
+
+        entries.sort_by(|a, b| {
+            let sep = self.hierarchy_separator;
+            a.name.split(sep).cmp(b.name.split(sep))
+        });
+
+        Ok(entries)
+    }
+
+    /// Lists the entries found when walking the given directory
+    /// entry.
+    ///
+    /// The walk yields parents before their children, so containers
+    /// are only known once a nested Maildir is found: until then,
+    /// they wait in the given pending list, which is emptied when
+    /// the walk leaves their subtree. This way, every directory is
+    /// visited once. Directories at the maximum depth (see
+    /// [`Maildirs::set_max_depth`]) are probed instead, since their
+    /// children are not walked.
+    fn list_entries(
+        &self,
+        entry: walkdir::DirEntry,
+        pending: &mut Vec<MaildirsEntry>,
+    ) -> Vec<Result<MaildirsEntry>> {
+        let depth = entry.depth();
+
+        let Some(mut entry) = self.list_entry(entry) else {
+            return vec![];
+        };
+
+        pending.retain(|parent| entry.maildir.path().starts_with(parent.maildir.path()));
+
+        // lists the entry along with its pending parents
+        let list = |pending: &mut Vec<MaildirsEntry>, entry| {
+            let mut entries: Vec<_> = pending.drain(..).map(Ok).collect();
+            entries.push(Ok(entry));
+            entries
+        };
+
+        if entry.maildir.exists() {
+            return list(pending, entry);
+        }
+
+        // cur, new and tmp directories never hold folders
+        let reserved = !self.maildirpp
+            && [CUR, NEW, TMP]
+                .iter()
+                .any(|name| entry.maildir.path().file_name() == Some(OsStr::new(name)));
+
+        if entry.maildir.path() == self.root || reserved {
+            return vec![];
+        }
+
+        entry.selectable = false;
+
+        if Some(depth) == self.max_depth {
+            if self.is_container(entry.maildir.path()) {
+                return list(pending, entry);
+            }
+        } else {
+            pending.push(entry);
+        }
+
+        vec![]
+    }
+
+    /// Builds the listed entry matching the given directory entry,
+    /// if it can be a Maildir or a container.
+    fn list_entry(&self, entry: walkdir::DirEntry) -> Option<MaildirsEntry> {
+        if !entry.file_type().is_dir() {
+            return None;
+        }
+
+        let file_name = entry.file_name().to_str()?;
+
+        if self.maildirpp && !file_name.starts_with('.') {
+            return None;
+        }
+
+        if self.maildirpp && entry.path() == self.root {
+            return Some(MaildirsEntry {
+                maildirpp: self.maildirpp,
+                hierarchy_separator: self.hierarchy_separator,
+                maildir: Maildir::from(&self.root),
+                name: self.root.file_name()?.to_str()?.to_owned(),
+                selectable: true,
+            })
+            .filter(|entry| entry.maildir.exists());
+        }
+
+        let name = match &self.root_name {
+            Some(name) if entry.path() == self.root => name.clone(),
+            _ => self.join_name(entry.path().strip_prefix(&self.root).ok()?)?,
+        };
+
+        Some(MaildirsEntry {
+            maildirpp: self.maildirpp,
+            hierarchy_separator: self.hierarchy_separator,
+            maildir: Maildir::from(entry.into_path()),
+            name,
+            selectable: true,
+        })
+    }
+
+    /// Returns `true` if the given directory is not a Maildir but
+    /// contains nested Maildirs, like an IMAP `\Noselect` folder.
+    ///
+    /// This walks the whole subtree, see [`Maildirs::list_entries`].
+    fn is_container(&self, path: &Path) -> bool {
+        WalkDir::new(path)
+            .min_depth(1)
+            .follow_links(self.follow_links)
+            .into_iter()
+            .filter_entry(|entry| {
+                let Some(name) = entry.file_name().to_str() else {
+                    return false;
+                };
+
+                // do not descend into cur, new and tmp directories,
+                // which can hold many entries
+                let is_folder = if self.maildirpp {
+                    name.starts_with('.')
+                } else {
+                    ![CUR, NEW, TMP].contains(&name)
+                };
+
+                entry.file_type().is_dir() && is_folder
+            })
+            .filter_map(|entry| entry.ok())
+            .any(|entry| Maildir::from(entry.path()).exists())
+    }
+
+    /// Reads entries of all the Maildirs listed by
+    /// [`Maildirs::iter`], along with the name of their Maildir.
+    ///
+    /// Containers, which are not selectable, are skipped. Maildirs
+    /// are read one after the other, so that entries are never all
+    /// collected in memory. See [`Maildir::read`].
+    pub fn entries(&self) -> impl Iterator<Item = Result<(String, MaildirEntry)>> + '_ {
+        self.iter()
+            .filter(|entry| entry.as_ref().map_or(true, |entry| entry.selectable))
+            .flat_map(|entry| {
+                let entries = entry.and_then(|entry| Ok((entry.name, entry.maildir.read()?)));
+
+                let (entries, err) = match entries {
+                    Ok(entries) => (Some(entries), None),
+                    Err(err) => (None, Some(Err(err))),
+                };
+
+                entries
+                    .into_iter()
+                    .flat_map(|(name, entries)| {
+                        entries.map(move |entry| Ok((name.clone(), entry?)))
+                    })
+                    .chain(err)
+            })
+    }
+
+    /// Counts entries of all the Maildirs listed by
+    /// [`Maildirs::iter`].
+    ///
+    /// Containers, which are not selectable, are skipped. See
+    /// [`Maildir::count`]. Listed Maildirs whose entries cannot
+    /// be counted make this function fail instead of being skipped,
+    /// so that the total is not silently undercounted.
+    pub fn total_count(&self) -> Result<usize> {
+        self.iter().try_fold(0, |total, entry| {
+            let entry = entry?;
+
+            if !entry.selectable {
+                return Ok(total);
+            }
+
+            Ok(total + entry.maildir.count()?)
+        })
+    }
+
+    /// Sums the sizes of entries of all the Maildirs listed by
+    /// [`Maildirs::iter`], in bytes.
+    ///
+    /// Containers are skipped and failures are returned, like
+    /// [`Maildirs::total_count`] does. See [`Maildir::size_on_disk`].
+    pub fn total_size_on_disk(&self) -> Result<u64> {
+        self.iter().try_fold(0, |total, entry| {
+            let entry = entry?;
+
+            if !entry.selectable {
+                return Ok(total);
+            }
+
+            Ok(total + entry.maildir.size_on_disk()?)
+        })
+    }
+
+    /// Counts entries of all the Maildirs listed by
+    /// [`Maildirs::iter`], by Maildir name.
+    ///
+    /// See [`Maildirs::total_count`].
+    pub fn counts(&self) -> Result<HashMap<String, usize>> {
+        self.iter()
+            .filter(|entry| entry.as_ref().map_or(true, |entry| entry.selectable))
+            .map(|entry| {
+                let entry = entry?;
+                Ok((entry.name, entry.maildir.count()?))
+            })
+            .collect()
+    }
+
+    /// Removes the cur, new and tmp directories from the Maildir
+    /// matching the given name.
+    ///
+    /// This function does not remove the root directory, nor its
+    /// nested folders. If you want to do so, see
+    /// [`Maildirs::remove_all`].
+    pub fn remove(&self, name: impl AsRef<str>) -> Result<()> {
+        let mdir = self.maildir(name)?;
+        mdir.remove()?;
+        Ok(())
+    }
+
+    /// Removes the Maildir matching the given name, including nested
+    /// Maildirs.
+    ///
+    /// This function removes the cur, new and tmp directories as well
+    /// as the root directory of the matching Maildir and its nested
+    /// Maildirs. See also [`Maildirs::remove`].
+    pub fn remove_all(&self, name: impl AsRef<str>) -> Result<()> {
+        let mdir = self.maildir(name)?;
+        mdir.remove_all()?;
+        Ok(())
+    }
+
+    /// Renames the Maildir matching the given name, including nested
+    /// Maildirs.
+    ///
+    /// Nested Maildirs live inside their parent directory in both
+    /// layouts, so they are moved along, and so are flat Maildir++
+    /// folders (see [`Maildirs::set_hierarchy_separator`]).
+    /// Subscriptions to the renamed Maildir and its nested Maildirs
+    /// are renamed too, so that they stay subscribed. Renaming to an
+    /// existing folder fails with [`Error::AlreadyExistsError`].
+    ///
+    /// The root Maildir, like the Maildir++ `INBOX`, holds all other
+    /// folders: renaming from or to it fails with
+    /// [`Error::InvalidFolderError`].
+    pub fn rename(&self, from: impl AsRef<str>, to: impl AsRef<str>) -> Result<Maildir> {
+        let (from, to) = (from.as_ref(), to.as_ref());
+        let src = self.maildir(from)?;
+        let dest = self.maildir(to)?;
+
+        for (name, mdir) in [(from, &src), (to, &dest)] {
+            if mdir.path() == self.root {
+                let reason = "root Maildir cannot be renamed";
+                return Err(Error::InvalidFolderError(name.to_owned(), reason));
+            }
+        }
+
+        if dest.path().exists() {
+            return Err(Error::AlreadyExistsError(dest.path().to_owned()));
+        }
+
+        if let Some(parent) = dest.path().parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        fs::rename(src.path(), dest.path())?;
+
+        if self.is_flat() {
+            self.rename_flat_children(&src, &dest)?;
+        }
+
+        self.rename_subscriptions(from, to)?;
+
+        Ok(dest)
+    }
+
+    /// Renames the flat Maildir++ folders nested in the given source
+    /// folder, which do not live inside their parent directory.
+    fn rename_flat_children(&self, src: &Maildir, dest: &Maildir) -> Result<()> {
+        let (Some(src_name), Some(dest_name)) = (
+            src.path().file_name().and_then(OsStr::to_str),
+            dest.path().file_name().and_then(OsStr::to_str),
+        ) else {
+            return Ok(());
+        };
+
+        let children: Vec<(PathBuf, String)> = fs::read_dir(&self.root)?
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| {
+                let file_name = entry.file_name();
+                let child = file_name
+                    .to_str()?
+                    .strip_prefix(src_name)?
+                    .strip_prefix('.')?;
+                Some((entry.path(), child.to_owned()))
+            })
+            .collect();
+
+        for (path, child) in children {
+            fs::rename(path, self.root.join(format!("{dest_name}.{child}")))?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the names of subscribed Maildirs, as stored in the
+    /// `subscriptions` file at the root of the Maildirs.
+    pub fn subscriptions(&self) -> Result<Vec<String>> {
+        self.read_subscriptions()
+    }
+
+    /// Subscribes to the Maildir matching the given name.
+    ///
+    /// Names are stored in their logical form, without the leading
+    /// dots of Maildir++ folders. Subscribing to an already
+    /// subscribed name does nothing.
+    pub fn subscribe(&self, name: impl AsRef<str>) -> Result<()> {
+        let name = self.logical_name(name.as_ref());
+        let mut names = self.read_subscriptions()?;
+
+        if !names.contains(&name) {
+            names.push(name);
+            self.write_subscriptions(&names)?;
+        }
+
+        Ok(())
+    }
+
+    /// Unsubscribes from the Maildir matching the given name.
+    ///
+    /// Unsubscribing from a name that is not subscribed does nothing.
+    pub fn unsubscribe(&self, name: impl AsRef<str>) -> Result<()> {
+        let name = self.logical_name(name.as_ref());
+        let mut names = self.read_subscriptions()?;
+        let len = names.len();
+
+        names.retain(|n| *n != name);
+
+        if names.len() != len {
+            self.write_subscriptions(&names)?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the logical form of the given name, without the
+    /// leading dots of Maildir++ folders.
+    fn logical_name(&self, name: &str) -> String {
+        if !self.maildirpp || self.is_flat() {
+            return name.to_owned();
+        }
+
+        let components: Vec<&str> = Path::new(name)
+            .components()
+            .filter_map(|c| match c {
+                Component::Normal(c) => c.to_str(),
+                _ => None,
+            })
+            .map(|c| c.trim_start_matches('.'))
+            .collect();
+
+        components.join("/")
+    }
+
+    fn subscriptions_path(&self) -> PathBuf {
+        self.root.join(SUBSCRIPTIONS)
+    }
+
+    fn read_subscriptions(&self) -> Result<Vec<String>> {
+        match fs::read_to_string(self.subscriptions_path()) {
+            Ok(contents) => Ok(contents
+                .lines()
+                .filter(|name| !name.is_empty())
+                .map(ToOwned::to_owned)
+                .collect()),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    fn write_subscriptions(&self, names: &[String]) -> Result<()> {
+        let mut contents = String::new();
+
+        for name in names {
+            contents.push_str(name);
+            contents.push('\n');
+        }
+
+        write_atomically(self.subscriptions_path(), contents)
+    }
+
+    fn rename_subscriptions(&self, from: &str, to: &str) -> Result<()> {
+        let (from, to) = (&self.logical_name(from), &self.logical_name(to));
+        let mut names = self.read_subscriptions()?;
+        let mut changed = false;
+
+        for name in &mut names {
+            if name == from {
+                *name = to.to_owned();
+                changed = true;
+            } else if let Some(child) = name
+                .strip_prefix(from)
+                .and_then(|n| n.strip_prefix(self.hierarchy_separator))
+            {
+                *name = format!("{to}{}{child}", self.hierarchy_separator);
+                changed = true;
+            }
+        }
+
+        if changed {
+            self.write_subscriptions(&names)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Hash for Maildirs {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.root.hash(state);
+        self.maildirpp.hash(state);
+    }
+}
+
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct MaildirsEntry {
+    pub maildirpp: bool,
+
+    /// The separator of nested names, see
+    /// [`Maildirs::set_hierarchy_separator`].
+    pub hierarchy_separator: char,
+
+    pub maildir: Maildir,
+    pub name: String,
+
+    /// Whether the folder holds entries itself.
+    ///
+    /// Folders that only exist as containers for nested folders are
+    /// not selectable, which IMAP represents as `\Noselect`.
+    pub selectable: bool,
+}
+
+impl MaildirsEntry {
+    /// Returns the name of the logical parent of the current entry,
+    /// like `A/B` for `A/B/C`, or `None` for top-level entries.
+    pub fn parent_name(&self) -> Option<String> {
+        let (parent, _) = self.name.rsplit_once(self.hierarchy_separator)?;
+        Some(parent.to_owned())
+    }
+
+    /// Returns the nesting depth of the current entry.
+    ///
+    /// Top-level entries have a depth of 1, like with
+    /// [`Maildirs::set_max_depth`].
+    pub fn depth(&self) -> usize {
+        self.name.split(self.hierarchy_separator).count()
+    }
+
+    /// Lists the immediate children of the current entry among the
+    /// entries of the given [`Maildirs`].
+    ///
+    /// The children of the root Maildir, in Maildir++ layouts, are
+    /// the top-level entries. Errors of [`Maildirs::iter`] are
+    /// yielded as well.
+    pub fn children<'a>(
+        &self,
+        mdirs: &'a Maildirs,
+    ) -> impl Iterator<Item = Result<MaildirsEntry>> + 'a {
+        let name = self.name.clone();
+        let is_root = self.maildir.path() == mdirs.path();
+
+        mdirs.iter().filter(move |entry| match entry {
+            Ok(entry) if is_root => {
+                entry.maildir.path() != mdirs.path() && entry.parent_name().is_none()
+            }
+            Ok(entry) => entry.parent_name().as_ref() == Some(&name),
+            Err(_) => true,
+        })
+    }
+}
+
+// =============================== ENTRY ================================
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MaildirEntry {
+    path: PathBuf,
+    info_separator: Cow<'static, str>,
+
+    /// The parts of the file name, parsed once when the path is set.
+    ///
+    /// This is `None` when the file name is not valid UTF-8.
+    name: Option<ParsedName>,
+}
+
+/// The parts of the file name of a Maildir entry, as lengths of its
+/// prefixes, so that accessors do not split the file name again.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+struct ParsedName {
+    /// The length of the id.
+    id: usize,
+
+    /// The length of the unique name, which is the id followed by its
+    /// `,X=<value>` fields.
+    unique_name: usize,
+}
+
+impl ParsedName {
+    fn new(file_name: &str, sep: &str) -> Self {
+        let unique_name = match file_name.rsplit_once(sep) {
+            Some((name, _)) => name,
+            None => file_name,
+        };
+
+        Self {
+            id: split_unique_name(unique_name).0.len(),
+            unique_name: unique_name.len(),
+        }
+    }
+}
+
+impl MaildirEntry {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self::parse(path.into(), Cow::Borrowed(DEFAULT_INFO_SEPARATOR))
+    }
+
+    fn parse(path: PathBuf, info_separator: Cow<'static, str>) -> Self {
+        let mut entry = Self {
+            path,
+            info_separator,
+            name: None,
+        };
+        entry.reparse();
+        entry
+    }
+
+    /// Sets the path of the current entry, parsing its file name.
+    fn set_path(&mut self, path: PathBuf) {
+        self.path = path;
+        self.reparse();
+    }
+
+    fn reparse(&mut self) {
+        self.name = self
+            .path
+            .file_name()
+            .and_then(OsStr::to_str)
+            .map(|file_name| ParsedName::new(file_name, &self.info_separator));
+    }
+
+    /// Sets the Maildir entry id ←→ info separator.
+    ///
+    /// See [`MaildirBuilder::set_info_separator`] for the allowed
+    /// values.
+    pub fn set_info_separator(&mut self, sep: impl Into<Cow<'static, str>>) -> Result<()> {
+        let sep = sep.into();
+        validate_info_separator(&sep)?;
+        self.info_separator = sep;
+        self.reparse();
+        Ok(())
+    }
+
+    pub fn with_info_separator(mut self, sep: impl Into<Cow<'static, str>>) -> Result<Self> {
+        self.set_info_separator(sep)?;
+        Ok(self)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn file_name(&self) -> Result<&str> {
+        let Some(name) = self.path.file_name() else {
+            let path = self.path.clone();
+            return Err(Error::GetMaildirEntryFileNameError(path));
+        };
+
+        match std::str::from_utf8(name.as_encoded_bytes()) {
+            Ok(name) => Ok(name),
+            Err(err) => {
+                let path = self.path.clone();
+                Err(Error::GetInvalidMaildirEntryFileNameError(err, path))
+            }
+        }
+    }
+
+    /// Returns `true` if the current entry lives in a `new`
+    /// directory, which means it has never been seen by a client.
+    pub fn is_new(&self) -> bool {
+        self.parent_name() == Some(OsStr::new(NEW))
+    }
+
+    /// Returns `true` if the current entry lives in a `cur`
+    /// directory.
+    pub fn is_cur(&self) -> bool {
+        self.parent_name() == Some(OsStr::new(CUR))
+    }
+
+    fn parent_name(&self) -> Option<&OsStr> {
+        self.path.parent().and_then(Path::file_name)
+    }
+
+    /// Returns the id of the current entry.
+    ///
+    /// The id is the unique name of the entry, without its info
+    /// section nor its `,X=<value>` fields (like the `,S=<size>`
+    /// field used by Dovecot).
+    ///
+    /// Generated ids never contain the info separator: `/`, `:` and
+    /// `;` characters of the hostname are stored as their octal
+    /// escapes (`\057`, `\072` and `\073`), as recommended by the
+    /// Maildir specification. The id is returned as stored, escapes
+    /// included, so that it can be given back to [`Maildir::find`].
+    pub fn id(&self) -> Result<&str> {
+        let (file_name, name) = self.parsed_name()?;
+        Ok(&file_name[..name.id])
+    }
+
+    /// Checks that the file name of the current entry can be parsed.
+    fn validate(&self) -> Result<()> {
+        if self.id()?.is_empty() {
+            return Err(Error::InvalidIdError(self.file_name()?.to_owned()));
+        }
+
+        if let Some((_, info)) = self.file_name()?.rsplit_once(&*self.info_separator) {
+            if !info.starts_with("2,") {
+                return Err(Error::InvalidInfoError(self.path.clone()));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the size of the current entry from its `,S=<size>`
+    /// file name field, if any.
+    pub fn size_hint(&self) -> Result<Option<u64>> {
+        let (_, fields) = split_unique_name(self.base_name()?);
+
+        Ok(fields
+            .split(',')
+            .find_map(|field| field.strip_prefix("S="))
+            .and_then(|size| size.parse().ok()))
+    }
+
+    /// Returns the SMTP envelope sender of the current entry from
+    /// its `,E=<sender>` file name field, if any.
+    ///
+    /// See [`Maildir::deliver_with_envelope`].
+    pub fn envelope_sender(&self) -> Result<Option<&str>> {
+        let (_, fields) = split_unique_name(self.base_name()?);
+
+        Ok(fields.split(',').find_map(|field| field.strip_prefix("E=")))
+    }
+
+    /// Returns the date the current entry was received at.
+    ///
+    /// The date is parsed from the leading `<secs>.` part of the
+    /// unique name. When the name does not match this pattern (for
+    /// example, when it was generated by another tool), the
+    /// modification time of the file is returned instead.
+    pub fn received_date(&self) -> Result<SystemTime> {
+        match self.received_secs() {
+            Some(secs) => Ok(UNIX_EPOCH + Duration::from_secs(secs)),
+            None => Ok(fs::metadata(&self.path)?.modified()?),
+        }
+    }
+
+    /// Returns the leading `<secs>.` part of the unique name of the
+    /// current entry, if any.
+    fn received_secs(&self) -> Option<u64> {
+        let (secs, _) = self.base_name().ok()?.split_once('.')?;
+        secs.parse().ok()
+    }
+
+    /// Returns the parts of the unique name of the current entry.
+    ///
+    /// Returns `None` when the unique name does not follow the
+    /// pattern of generated ids, see [`UniqueName::parse`].
+    pub fn unique_name(&self) -> Option<UniqueName> {
+        UniqueName::parse(self.id().ok()?)
+    }
+
+    /// Returns the unique name of the current entry along with its
+    /// fields, which is the file name without the info section.
+    fn base_name(&self) -> Result<&str> {
+        let (file_name, name) = self.parsed_name()?;
+        Ok(&file_name[..name.unique_name])
+    }
+
+    /// Returns the file name of the current entry along with its
+    /// parts, parsed when the path was set.
+    fn parsed_name(&self) -> Result<(&str, ParsedName)> {
+        let file_name = self.file_name()?;
+
+        // the name is always parsed when the file name is valid
+        let name = self
+            .name
+            .unwrap_or_else(|| ParsedName::new(file_name, &self.info_separator));
+
+        Ok((file_name, name))
+    }
+
+    /// Checks if the current entry can be opened for reading.
+    ///
+    /// Entries are built from paths only, so an entry owned by
+    /// another user without read permission is still listed by
+    /// [`Maildir::read`]. This function allows to detect such
+    /// entries before actually reading them.
+    pub fn is_readable(&self) -> bool {
+        File::open(&self.path).is_ok()
+    }
+
+    /// Opens the file of the current entry for reading.
+    ///
+    /// This allows advanced usages like seeking or memory mapping.
+    pub fn open(&self) -> Result<File> {
+        Ok(File::open(&self.path)?)
+    }
+
+    /// Copies the contents of the current entry to the given writer,
+    /// and returns the number of copied bytes.
+    ///
+    /// Unlike [`MaildirEntry::read`], contents are streamed instead
+    /// of being loaded into memory.
+    pub fn read_to_writer<W: Write>(&self, w: &mut W) -> Result<u64> {
+        Ok(io::copy(&mut self.open()?, w)?)
+    }
+
+    pub fn read(&self) -> Result<Vec<u8>> {
+        let contents = fs::read(&self.path)?;
+        Ok(contents)
+    }
+
+    /// Replaces the contents of the current entry.
+    ///
+    /// The contents are written to a tmp file of the Maildir, synced,
+    /// then renamed over the entry, so that readers never see a
+    /// partial write. The directory of the entry is synced as well.
+    /// The id and flags are kept, as well as the permissions and the
+    /// owner of the entry. Dovecot-style `,S=<size>` and `,W=<size>`
+    /// file name fields are updated to the new sizes, in which case
+    /// the tmp file is renamed to the resized file name and the
+    /// previous file is removed.
+    pub fn replace_contents(&mut self, contents: impl AsRef<[u8]>) -> Result<()> {
+        let contents = contents.as_ref();
+        let meta = fs::metadata(&self.path)?;
+        let mdir = MaildirBuilder::new()
+            .with_info_separator(self.info_separator.clone())?
+            .with_durable(true)
+            .build(self.root()?);
+        let (tmp_path, mut tmp_file) = mdir.create_tmp_file()?;
+        let tmp = RemoveOnDrop::new(tmp_path);
+
+        #[cfg(unix)]
+        {
+            let owner = (meta.uid(), meta.gid());
+            let tmp_meta = tmp_file.metadata()?;
+
+            if (tmp_meta.uid(), tmp_meta.gid()) != owner {
+                if let Err(err) = std::os::unix::fs::chown(tmp.path(), Some(owner.0), Some(owner.1))
+                {
+                    return Err(Error::ChangeOwnerError(err, tmp.path().to_owned()));
                 }
             }
-        }?;
+        }
 
-        tmp_file.write_all(contents.as_ref())?;
+        tmp_file.set_permissions(meta.permissions())?;
+        tmp_file.write_all(contents)?;
         tmp_file.sync_all()?;
 
-        let id = id.map_or_else(|| generate_id(tmp_file), Ok)?;
-        let next_parent_path = if new { &self.new } else { &self.cur };
-        let next_path = next_parent_path.join(if new {
-            id
-        } else {
-            self.format_file_name(id, flags.into_iter().collect())
-        });
+        let (file_name, name) = self.parsed_name()?;
+        let base_name = resize_unique_name(&file_name[..name.unique_name], contents);
+        let next_path = self
+            .path
+            .with_file_name(format!("{base_name}{}", &file_name[name.unique_name..]));
 
-        fs::rename(tmp_path, &next_path)?;
+        fs::rename(tmp.path(), &next_path)?;
+        tmp.disarm();
 
-        let entry = fs::read_dir(next_parent_path)?
-            .filter_map(|entry| entry.ok())
-            .find(|entry| entry.path() == next_path);
+        if next_path != self.path {
+            fs::remove_file(&self.path)?;
+        }
 
-        match entry {
-            Some(entry) => {
-                let entry = MaildirEntry::new(entry.path());
-                Ok(entry.with_info_separator(self.info_separator))
+        if let Some(parent) = next_path.parent() {
+            mdir.sync_dir(parent)?;
+        }
+
+        self.set_path(next_path);
+
+        Ok(())
+    }
+
+    /// Same as [`MaildirEntry::read`], but using [`tokio::fs`] so
+    /// that the executor is not blocked.
+    #[cfg(feature = "tokio")]
+    pub async fn read_async(&self) -> Result<Vec<u8>> {
+        let contents = tokio::fs::read(&self.path).await?;
+        Ok(contents)
+    }
+
+    pub fn read_headers(&self) -> Result<Vec<u8>> {
+        read_headers(&mut BufReader::new(self.open()?))
+    }
+
+    /// Reads the body of the current entry, which follows the first
+    /// empty line.
+    ///
+    /// Entries without empty line have an empty body. See
+    /// [`MaildirEntry::read_split`] to read both headers and body.
+    pub fn read_body(&self) -> Result<Vec<u8>> {
+        Ok(self.read_split()?.1)
+    }
+
+    /// Reads both the headers and the body of the current entry, in
+    /// a single pass.
+    ///
+    /// Headers are the same as [`MaildirEntry::read_headers`], which
+    /// include the empty line separating them from the body. Entries
+    /// without empty line are all headers, with an empty body.
+    pub fn read_split(&self) -> Result<(Vec<u8>, Vec<u8>)> {
+        let mut reader = BufReader::new(self.open()?);
+        let headers = read_headers(&mut reader)?;
+        let mut body = Vec::new();
+        reader.read_to_end(&mut body)?;
+        Ok((headers, body))
+    }
+
+    /// Returns the header fields of the current entry, as name/value
+    /// pairs.
+    ///
+    /// Fields keep their order and duplicates. Folded lines are
+    /// unfolded, but values are not decoded: MIME encoded words are
+    /// left as-is.
+    pub fn headers(&self) -> Result<Vec<(String, String)>> {
+        let headers = self.read_headers()?;
+        let headers = String::from_utf8_lossy(&headers);
+        let mut fields: Vec<(String, String)> = Vec::new();
+
+        for line in headers.lines() {
+            if line.starts_with([' ', '\t']) {
+                if let Some((_, value)) = fields.last_mut() {
+                    value.push_str(line);
+                }
+            } else if let Some((name, value)) = line.split_once(':') {
+                fields.push((name.trim().to_owned(), value.trim_start().to_owned()));
             }
-            None => Err(Error::FindNewMaildirEntryError(next_path)),
         }
+
+        Ok(fields)
     }
 
-    fn format_file_name(&self, id: String, flags: HashSet<Flag>) -> String {
-        format_file_name(self.info_separator, id, flags)
+    /// Returns the value of the first header field matching the
+    /// given name, case-insensitively.
+    pub fn header(&self, name: impl AsRef<str>) -> Result<Option<String>> {
+        let name = name.as_ref();
+
+        Ok(self
+            .headers()?
+            .into_iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value))
     }
-}
 
-impl Hash for Maildir {
-    fn hash<H: Hasher>(&self, state: &mut H) {
-        self.root.hash(state);
+    /// Returns the top-level content type of the current entry,
+    /// like `text/plain` or `multipart/mixed`.
+    ///
+    /// The value of the `Content-Type` header is returned lowercased
+    /// and without its parameters. Only headers are read, see
+    /// [`MaildirEntry::header`].
+    pub fn content_type(&self) -> Result<Option<String>> {
+        Ok(self.header("Content-Type")?.and_then(|value| {
+            let mime = value.split(';').next().unwrap_or_default().trim();
+            Some(mime.to_ascii_lowercase()).filter(|mime| !mime.is_empty())
+        }))
     }
-}
 
-impl<P: Into<PathBuf>> From<P> for Maildir {
-    fn from(root: P) -> Self {
-        let root = root.into();
+    /// Checks if the current entry seems to have attachments.
+    ///
+    /// This is a heuristic scan, not a MIME parser: the entry is
+    /// considered as having attachments when its top-level content
+    /// type is multipart and one of its lines is a
+    /// `Content-Disposition: attachment` header. Reading stops as
+    /// soon as the answer is known, so non-multipart entries are
+    /// only read up to the end of their headers. Unusual structures
+    /// may be missed.
+    pub fn has_attachments(&self) -> Result<bool> {
+        let mut reader = BufReader::new(File::open(&self.path)?);
+        let mut buffer = Vec::<u8>::new();
+        let mut field = String::new();
+        let mut top_level_headers = true;
+        let mut multipart = false;
 
-        let new = root.join(NEW);
-        let cur = root.join(CUR);
-        let tmp = root.join(TMP);
+        loop {
+            buffer.clear();
+            let len = reader.read_until(b'\n', &mut buffer)?;
+            let line = String::from_utf8_lossy(&buffer).to_ascii_lowercase();
+            let line = line.trim_end();
+
+            // unfold header fields spanning multiple lines
+            if len > 0 && line.starts_with([' ', '\t']) {
+                field.push(' ');
+                field.push_str(line.trim_start());
+                continue;
+            }
 
-        Self {
-            root,
-            new,
-            cur,
-            tmp,
-            info_separator: DEFAULT_INFO_SEPARATOR,
+            let is_content_type = field.starts_with("content-type:");
+            let is_content_disposition = field.starts_with("content-disposition:");
+
+            if is_content_type && field.contains("multipart/") && top_level_headers {
+                multipart = true;
+            } else if is_content_disposition && field.contains("attachment") && multipart {
+                return Ok(true);
+            }
+
+            field.clear();
+
+            if len == 0 {
+                break;
+            }
+
+            if line.is_empty() && top_level_headers {
+                if !multipart {
+                    break;
+                }
+
+                top_level_headers = false;
+            }
+
+            field.push_str(line);
         }
+
+        Ok(false)
     }
-}
 
-fn generate_tmp_id() -> String {
-    let ts = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
-    let secs = ts.as_secs();
-    let nanos = ts.subsec_nanos();
-    let counter = COUNTER.fetch_add(1, Ordering::SeqCst);
+    /// Returns the flags of the current entry.
+    ///
+    /// Flags are read from the info section with the `2` version
+    /// only: entries with experimental info (see
+    /// [`MaildirEntry::info`]) have no flag.
+    pub fn flags(&self) -> Result<HashSet<Flag>> {
+        // invalid flag chars are ignored
+        self.flags_raw().map(flags_from_str)
+    }
 
-    format!(
-        "{secs}.#{counter:x}M{nanos}P{pid}",
-        secs = secs,
-        counter = counter,
-        nanos = nanos,
-        pid = process::id()
-    )
-}
+    /// Returns the raw flag characters of the current entry, as they
+    /// appear after the `2,` of the info section.
+    ///
+    /// Unlike [`MaildirEntry::flags`], this function keeps flag
+    /// characters that do not match any known [`Flag`], like
+    /// Dovecot keywords.
+    pub fn flags_raw(&self) -> Result<&str> {
+        Ok(match self.info()? {
+            Some(('2', flags)) => flags,
+            _ => "",
+        })
+    }
 
-fn generate_id(file: File) -> Result<String> {
-    let meta = file.metadata()?;
+    /// Returns the version and the contents of the info section of
+    /// the current entry, if any.
+    ///
+    /// The info section follows the info separator, as
+    /// `<version>,<info>`: the version is `2` for standard info,
+    /// which holds flags, and `1` for experimental info, whose
+    /// semantics depend on the tool that wrote it. Fails with
+    /// [`Error::InvalidInfoError`] when the info section does not
+    /// match this pattern.
+    pub fn info(&self) -> Result<Option<(char, &str)>> {
+        let Some(info) = self.info_section()? else {
+            return Ok(None);
+        };
 
-    #[cfg(unix)]
-    let dev = meta.dev();
-    #[cfg(windows)]
-    let dev: u64 = 0;
+        let mut chars = info.chars();
 
-    #[cfg(unix)]
-    let ino = meta.ino();
-    #[cfg(windows)]
-    let ino: u64 = 0;
+        match (chars.next(), chars.next()) {
+            (Some(version), Some(',')) => Ok(Some((version, chars.as_str()))),
+            _ => Err(Error::InvalidInfoError(self.path.clone())),
+        }
+    }
 
-    let hostname = gethostname()
-        .into_string()
-        .expect("hostname is not valid UTF-8. how the fuck did you achieve that?");
+    /// Returns the raw info section of the current entry, which
+    /// follows the info separator.
+    fn info_section(&self) -> Result<Option<&str>> {
+        let (file_name, name) = self.parsed_name()?;
+        Ok(file_name[name.unique_name..].strip_prefix(&*self.info_separator))
+    }
 
-    Ok(format!("{}V{dev}I{ino}.{hostname}", generate_tmp_id()))
-}
+    /// Returns the names of the keywords of the current entry.
+    ///
+    /// Keywords are stored as lowercase flag characters, translated
+    /// into names using the `dovecot-keywords` file of the Maildir
+    /// (see [`Maildir::keywords`]). Letters without a matching name
+    /// are ignored.
+    pub fn keywords(&self) -> Result<Vec<String>> {
+        let keywords = keywords::read_keywords(&keywords::keywords_path(self.root()?))?;
+
+        Ok(self
+            .flags_raw()?
+            .chars()
+            .filter_map(|c| {
+                keywords
+                    .iter()
+                    .find(|(index, _)| keywords::keyword_letter(*index) == c)
+                    .map(|(_, name)| name.clone())
+            })
+            .collect())
+    }
 
-// =============================== LIST ================================
+    /// Sets the given keyword on the current entry.
+    ///
+    /// The keyword is allocated the next free index in the
+    /// `dovecot-keywords` file of the Maildir if it is not registered
+    /// yet, under an advisory lock on a sibling `.lock` file, then its
+    /// letter is appended to the flags of the entry.
+    /// Since only entries of `cur` can have flags, entries of `new`
+    /// are moved to `cur` along the way. The path of the current
+    /// entry is updated accordingly.
+    pub fn set_keyword(&mut self, name: impl AsRef<str>) -> Result<()> {
+        let letter =
+            keywords::allocate_keyword(&keywords::keywords_path(self.root()?), name.as_ref())?;
+        let mut unknown_flags = self.flags_raw()?.to_owned();
+
+        if unknown_flags.contains(letter) {
+            return Ok(());
+        }
 
-#[derive(Clone, Debug, Eq, PartialEq)]
-pub struct Maildirs {
-    root: PathBuf,
-    maildirpp: bool,
-    info_separator: &'static str,
-}
+        unknown_flags.push(letter);
+        unknown_flags.retain(|c| Flag::try_from(c).is_err());
+
+        let file_name = format_file_name(
+            &self.info_separator,
+            self.base_name()?,
+            self.flags()?,
+            &unknown_flags,
+        );
+        let next_path = if self.is_new() {
+            self.root()?.join(CUR).join(file_name)
+        } else {
+            self.path.with_file_name(file_name)
+        };
 
-impl Maildirs {
-    pub fn new(path: impl Into<PathBuf>) -> Self {
-        Self {
-            root: path.into(),
-            maildirpp: false,
-            info_separator: DEFAULT_INFO_SEPARATOR,
+        fs::rename(&self.path, &next_path)?;
+        self.set_path(next_path);
+
+        Ok(())
+    }
+
+    /// Returns the root of the Maildir the current entry lives in.
+    fn root(&self) -> Result<&Path> {
+        self.path
+            .parent()
+            .and_then(Path::parent)
+            .ok_or_else(|| Error::NoParentError(self.path.clone()))
+    }
+
+    pub fn has_flag(&self, flag: Flag) -> bool {
+        match self.flags() {
+            Ok(flags) => flags.contains(&flag),
+            Err(_) => false,
         }
     }
 
-    pub fn set_maildirpp(&mut self, maildirpp: bool) {
-        self.maildirpp = maildirpp;
+    pub fn has_trash_flag(&self) -> bool {
+        self.has_flag(Flag::Trashed)
+    }
+
+    /// Returns `true` if the current entry has the [`Flag::Draft`]
+    /// flag.
+    pub fn is_draft(&self) -> bool {
+        self.has_flag(Flag::Draft)
+    }
+
+    /// Returns `true` if the current entry has the [`Flag::Flagged`]
+    /// flag.
+    pub fn is_flagged(&self) -> bool {
+        self.has_flag(Flag::Flagged)
+    }
+
+    /// Returns `true` if the current entry has the [`Flag::Passed`]
+    /// flag, which means it has been forwarded, resent or bounced.
+    ///
+    /// See [`MaildirEntry::is_replied`] for replied entries.
+    pub fn is_forwarded(&self) -> bool {
+        self.has_flag(Flag::Passed)
+    }
+
+    /// Returns `true` if the current entry has the [`Flag::Replied`]
+    /// flag.
+    ///
+    /// See [`MaildirEntry::is_forwarded`] for forwarded entries.
+    pub fn is_replied(&self) -> bool {
+        self.has_flag(Flag::Replied)
+    }
+
+    /// Returns `true` if the current entry has the [`Flag::Seen`]
+    /// flag.
+    pub fn is_seen(&self) -> bool {
+        self.has_flag(Flag::Seen)
+    }
+
+    /// Returns `true` if the current entry has the [`Flag::Trashed`]
+    /// flag.
+    pub fn is_trashed(&self) -> bool {
+        self.has_flag(Flag::Trashed)
+    }
+
+    pub fn insert_flag(&mut self, flag: Flag) -> Result<()> {
+        self.insert_flags(Some(flag))
     }
 
-    pub fn with_maildirpp(mut self, maildirpp: bool) -> Self {
-        self.set_maildirpp(maildirpp);
-        self
+    pub fn insert_flags(&mut self, flags: impl IntoIterator<Item = Flag>) -> Result<()> {
+        let flags: Vec<Flag> = flags.into_iter().collect();
+
+        self.change_flags(|mut next_flags| {
+            let changed = flags
+                .iter()
+                .fold(false, |changed, flag| next_flags.insert(*flag) || changed);
+
+            Some(next_flags).filter(|_| changed)
+        })
     }
 
-    pub fn set_info_separator(&mut self, sep: &'static str) {
-        self.info_separator = sep;
+    pub fn update_flags(&mut self, flags: impl IntoIterator<Item = Flag>) -> Result<()> {
+        let flags: HashSet<Flag> = flags.into_iter().collect();
+        self.change_flags(|_| Some(flags.clone()))
     }
 
-    pub fn with_info_separator(mut self, sep: &'static str) -> Self {
-        self.set_info_separator(sep);
-        self
+    pub fn remove_flag(&mut self, flag: Flag) -> Result<()> {
+        self.remove_flags(Some(flag))
     }
 
-    pub fn path(&self) -> &Path {
-        &self.root
+    pub fn remove_flags(&mut self, flags: impl IntoIterator<Item = Flag>) -> Result<()> {
+        let flags: Vec<Flag> = flags.into_iter().collect();
+
+        self.change_flags(|mut next_flags| {
+            let changed = flags
+                .iter()
+                .fold(false, |changed, flag| next_flags.remove(flag) || changed);
+
+            Some(next_flags).filter(|_| changed)
+        })
     }
 
-    fn maildir(&self, name: impl AsRef<str>) -> Maildir {
-        let path = if self.maildirpp {
-            let mut path = self.root.clone();
+    /// Renames the current entry to its canonical file name, which is
+    /// `<unique name><sep>2,<flags>` with flags sorted and
+    /// deduplicated, even when its flags do not change.
+    ///
+    /// This fixes file names written by other tools with unsorted
+    /// flags, or with the `:` or `;` info separator when the other
+    /// one is configured. Unknown flags are preserved. Entries
+    /// without info section, like the ones of `new`, are left
+    /// untouched.
+    pub fn normalize(&mut self) -> Result<()> {
+        let file_name = self.file_name()?;
 
-            for component in PathBuf::from(name.as_ref()).components() {
-                if let Component::Normal(component) = component {
-                    let component = component.to_string_lossy();
-                    path.push(format!(".{}", component.trim_start_matches('.')))
-                }
-            }
+        let parts = match self.info_section()? {
+            Some(info) => match info.strip_prefix("2,") {
+                Some(flags) => Some((self.base_name()?, flags)),
+                None => return Err(Error::InvalidInfoError(self.path.clone())),
+            },
+            None => SPEC_INFO_SEPARATORS
+                .iter()
+                .find_map(|sep| file_name.rsplit_once(&format!("{sep}2,"))),
+        };
 
-            path
-        } else {
-            self.root.join(name.as_ref())
+        let Some((base_name, flags)) = parts else {
+            return Ok(());
         };
 
-        MaildirBuilder::new()
-            .with_info_separator(self.info_separator)
-            .build(path)
-    }
+        let unknown_flags: String = flags
+            .chars()
+            .filter(|c| Flag::try_from(*c).is_err())
+            .collect();
+        let next_file_name = format_file_name(
+            &self.info_separator,
+            base_name,
+            flags_from_str(flags),
+            &unknown_flags,
+        );
+
+        if next_file_name == file_name {
+            return Ok(());
+        }
 
-    pub fn create(&self, name: impl ToString) -> Result<Maildir> {
-        let mdir = self.maildir(name.to_string());
-        mdir.create_all()?;
-        Ok(mdir)
-    }
+        let next_path = self.path.with_file_name(next_file_name);
+        fs::rename(&self.path, &next_path)?;
+        self.set_path(next_path);
 
-    pub fn find(&self, name: impl AsRef<str>) -> Option<Maildir> {
-        Some(self.maildir(name)).filter(|mdir| mdir.exists())
+        Ok(())
     }
 
-    pub fn get(&self, name: impl AsRef<str>) -> Result<Maildir> {
-        let name = name.as_ref();
+    /// Renames the current entry with the flags computed by the given
+    /// function, or does nothing if the function returns `None`.
+    ///
+    /// Only the flags part of the file name is rewritten: the unique
+    /// name, including Dovecot-style `,S=` and `,W=` fields, and
+    /// unknown flags are preserved.
+    ///
+    /// If the entry has been renamed by another process in the
+    /// meantime, the entry is looked up again by id and the flags are
+    /// computed again from the up-to-date file name. This is retried
+    /// at most [`MAX_FLAGS_CHANGE_ATTEMPTS`] times.
+    fn change_flags(
+        &mut self,
+        compute_flags: impl Fn(HashSet<Flag>) -> Option<HashSet<Flag>>,
+    ) -> Result<()> {
+        for _ in 0..MAX_FLAGS_CHANGE_ATTEMPTS {
+            let Some(flags) = compute_flags(self.flags()?) else {
+                return Ok(());
+            };
 
-        match self.find(name) {
-            Some(mdir) => Ok(mdir),
-            None => Err(Error::GetMaildirByNameNotFoundError(name.to_owned())),
+            let prev_path = self.path();
+            let next_path = prev_path.with_file_name(self.format_file_name(flags)?);
+
+            match fs::rename(prev_path, &next_path) {
+                Ok(()) => {
+                    self.set_path(next_path);
+                    return Ok(());
+                }
+                Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                    self.refresh_path()?;
+                    continue;
+                }
+                Err(err) => {
+                    return Err(err.into());
+                }
+            }
         }
+
+        Err(Error::ConcurrentModificationError(self.path.clone()))
     }
 
-    pub fn iter(&self) -> impl Iterator<Item = MaildirsEntry> + '_ {
-        WalkDir::new(&self.root)
-            .follow_links(true)
-            .into_iter()
+    /// Looks up the current entry by id in its parent directory, and
+    /// updates its path accordingly.
+    fn refresh_path(&mut self) -> Result<()> {
+        let id = self.id()?.to_owned();
+
+        let Some(parent) = self.path.parent() else {
+            return Err(Error::NoParentError(self.path.clone()));
+        };
+
+        let path = fs::read_dir(parent)?
             .filter_map(|entry| entry.ok())
-            .filter(|entry| {
-                entry
-                    .file_name()
-                    .to_str()
-                    .map(|s| !self.maildirpp || s.starts_with("."))
-                    .unwrap_or(false)
-            })
-            .filter_map(|entry| {
-                let name = if self.maildirpp {
-                    if entry.path() == self.root {
-                        return Some(MaildirsEntry {
-                            maildirpp: self.maildirpp,
-                            maildir: Maildir::from(&self.root),
-                            name: self.root.file_name()?.to_str()?.to_owned(),
-                        });
-                    }
+            .map(|entry| entry.path())
+            .find(|path| {
+                let entry = MaildirEntry::parse(path.clone(), self.info_separator.clone());
 
-                    let subpath = entry.path().strip_prefix(&self.root).unwrap();
-                    let mut name = PathBuf::new();
+                entry.id().ok() == Some(id.as_str())
+            });
 
-                    for component in subpath.components() {
-                        if let Component::Normal(component) = component {
-                            let component = component.to_string_lossy();
-                            name.push(component.trim_start_matches('.'))
-                        }
-                    }
+        match path {
+            Some(path) => {
+                self.set_path(path);
+                Ok(())
+            }
+            None => Err(Error::GetMaildirEntryNotFoundError(id)),
+        }
+    }
 
-                    name.to_str()?.to_owned()
-                } else {
-                    entry
-                        .path()
-                        .strip_prefix(&self.root)
-                        .ok()?
-                        .to_str()?
-                        .to_owned()
-                };
+    /// Copies the current entry to the `cur` directory of the given
+    /// Maildir, and returns the path of the copy.
+    ///
+    /// When the file name is already taken, the duplicate policy of
+    /// the given Maildir applies (see
+    /// [`Maildir::set_duplicate_policy`]). Returns `None` when the
+    /// entry already lives in the given Maildir, or when it is
+    /// skipped.
+    pub fn copy(&self, mdir: &Maildir) -> Result<Option<PathBuf>> {
+        if Some(mdir.cur()) == self.path().parent() {
+            return Ok(None);
+        }
 
-                Some(MaildirsEntry {
-                    maildirpp: self.maildirpp,
-                    maildir: Maildir::from(entry.into_path()),
-                    name,
-                })
-            })
-            .filter_map(|entry| {
-                if entry.maildir.exists() {
-                    Some(entry)
-                } else {
-                    None
-                }
-            })
+        mdir.transfer(
+            &self.path,
+            mdir.cur(),
+            self.file_name()?,
+            &self.info_separator,
+            true,
+        )
     }
 
-    /// Removes the cur, new and tmp directories from the Maildir
-    /// matching the given name.
+    /// Moves the current entry to the `cur` directory of the given
+    /// Maildir, and returns its next path.
     ///
-    /// This function does not remove the root directory, nor its
-    /// nested folders. If you want to do so, see
-    /// [`Maildirs::remove_all`].
-    pub fn remove(&self, name: impl AsRef<str>) -> Result<()> {
-        let mdir = self.maildir(name);
-        mdir.remove()?;
+    /// See [`MaildirEntry::copy`].
+    pub fn r#move(&self, mdir: &Maildir) -> Result<Option<PathBuf>> {
+        if Some(mdir.cur()) == self.path().parent() {
+            return Ok(None);
+        }
+
+        mdir.transfer(
+            &self.path,
+            mdir.cur(),
+            self.file_name()?,
+            &self.info_separator,
+            false,
+        )
+    }
+
+    /// Moves the current entry from `new` to the sibling `cur`
+    /// directory, with the given flags.
+    ///
+    /// This is the classic "mark as read on first view" operation.
+    /// The info separator and flags are appended to the file name,
+    /// and the path of the current entry is updated accordingly. This
+    /// is a no-op when the entry does not live in `new`.
+    pub fn move_to_cur(&mut self, flags: impl IntoIterator<Item = Flag>) -> Result<()> {
+        let parent = self
+            .path
+            .parent()
+            .ok_or_else(|| Error::NoParentError(self.path.clone()))?;
+
+        if !self.is_new() {
+            return Ok(());
+        }
+
+        let root = parent
+            .parent()
+            .ok_or_else(|| Error::NoParentError(parent.to_owned()))?;
+        let next_path = root
+            .join(CUR)
+            .join(self.format_file_name(flags.into_iter().collect())?);
+
+        fs::rename(&self.path, &next_path)?;
+        self.set_path(next_path);
+
         Ok(())
     }
 
-    /// Removes the Maildir matching the given name, including nested
-    /// Maildirs.
+    /// Copies the current entry to the given Maildir.
     ///
-    /// This function removes the cur, new and tmp directories as well
-    /// as the root directory of the matching Maildir and its nested
-    /// Maildirs. See also [`Maildirs::remove`].
-    pub fn remove_all(&self, name: impl AsRef<str>) -> Result<()> {
-        let mdir = self.maildir(name);
-        mdir.remove_all()?;
+    /// Unlike [`MaildirEntry::copy`], this function returns an error
+    /// when the entry already lives in the destination Maildir.
+    pub fn copy_checked(&self, mdir: &Maildir) -> Result<PathBuf> {
+        self.copy(mdir)?
+            .ok_or_else(|| Error::CopyEmailSamePathError(self.path.clone()))
+    }
+
+    /// Moves the current entry to the given Maildir.
+    ///
+    /// Unlike [`MaildirEntry::move`], this function returns an error
+    /// when the entry already lives in the destination Maildir.
+    pub fn move_checked(&self, mdir: &Maildir) -> Result<PathBuf> {
+        self.r#move(mdir)?
+            .ok_or_else(|| Error::CopyEmailSamePathError(self.path.clone()))
+    }
+
+    pub fn remove(&self) -> Result<()> {
+        fs::remove_file(self.path())?;
         Ok(())
     }
-}
 
-impl Hash for Maildirs {
-    fn hash<H: Hasher>(&self, state: &mut H) {
-        self.root.hash(state);
-        self.maildirpp.hash(state);
+    /// Formats the file name of the current entry with the given
+    /// flags, preserving unknown flag characters.
+    fn format_file_name(&self, flags: HashSet<Flag>) -> Result<String> {
+        let unknown_flags: String = self
+            .flags_raw()?
+            .chars()
+            .filter(|c| Flag::try_from(*c).is_err())
+            .collect();
+
+        Ok(format_file_name(
+            &self.info_separator,
+            self.base_name()?,
+            flags,
+            &unknown_flags,
+        ))
     }
 }
 
-#[derive(Clone, Debug, Eq, Hash, PartialEq)]
-pub struct MaildirsEntry {
-    pub maildirpp: bool,
-    pub maildir: Maildir,
-    pub name: String,
+/// Orders entries by received date, then by id.
+///
+/// The received date is parsed from the leading `<secs>.` part of the
+/// unique name, see [`MaildirEntry::received_date`]. Unlike the
+/// latter, the modification time is never read: entries whose name
+/// does not match this pattern are considered received at the epoch,
+/// and entries whose id cannot be parsed are considered having an
+/// empty id. Remaining ties are broken by path, so that the order is
+/// total.
+impl Ord for MaildirEntry {
+    fn cmp(&self, other: &Self) -> cmp::Ordering {
+        let secs = |entry: &Self| entry.received_secs().unwrap_or_default();
+
+        secs(self)
+            .cmp(&secs(other))
+            .then_with(|| {
+                let id = self.id().unwrap_or_default();
+                id.cmp(other.id().unwrap_or_default())
+            })
+            .then_with(|| self.path.cmp(&other.path))
+            .then_with(|| self.info_separator.cmp(&other.info_separator))
+    }
 }
 
-// =============================== ENTRY ================================
+impl PartialOrd for MaildirEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
 
-#[derive(Clone, Debug, Eq, PartialEq)]
-pub struct MaildirEntry {
-    path: PathBuf,
-    info_separator: &'static str,
+/// A directory of a Maildir.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum MaildirDir {
+    /// The `new` directory, holding entries never seen by a client.
+    New,
+    /// The `cur` directory, holding seen entries.
+    Cur,
+    /// The `tmp` directory, holding entries being delivered.
+    Tmp,
 }
 
-impl MaildirEntry {
-    pub fn new(path: impl Into<PathBuf>) -> Self {
-        Self {
-            path: path.into(),
-            info_separator: DEFAULT_INFO_SEPARATOR,
+impl MaildirDir {
+    /// Returns the name of the directory, like `new`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MaildirDir::New => NEW,
+            MaildirDir::Cur => CUR,
+            MaildirDir::Tmp => TMP,
         }
     }
+}
 
-    pub fn set_info_separator(&mut self, sep: &'static str) {
-        self.info_separator = sep;
-    }
-
-    pub fn with_info_separator(mut self, sep: &'static str) -> Self {
-        self.set_info_separator(sep);
-        self
-    }
+/// What happens when an entry is moved or copied to a file name
+/// already taken in the destination Maildir.
+///
+/// See [`MaildirBuilder::set_duplicate_policy`].
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub enum DuplicatePolicy {
+    /// The existing entry is replaced.
+    Overwrite,
+    /// The entry is left untouched.
+    Skip,
+    /// The entry gets a newly generated id.
+    #[default]
+    Rename,
+}
 
-    pub fn path(&self) -> &Path {
-        &self.path
-    }
+/// Iterator over the entries of a Maildir, returned by
+/// [`Maildir::read`].
+#[derive(Debug)]
+pub struct MaildirEntries {
+    info_separator: Cow<'static, str>,
+    dirs: VecDeque<fs::ReadDir>,
 
-    pub fn file_name(&self) -> Result<&str> {
-        let Some(name) = self.path.file_name() else {
-            let path = self.path.clone();
-            return Err(Error::GetMaildirEntryFileNameError(path));
-        };
+    /// The directories to open once `dirs` are exhausted.
+    pending_dirs: VecDeque<PathBuf>,
 
-        match std::str::from_utf8(name.as_encoded_bytes()) {
-            Ok(name) => Ok(name),
-            Err(err) => {
-                let path = self.path.clone();
-                Err(Error::GetInvalidMaildirEntryFileNameError(err, path))
-            }
-        }
-    }
+    /// The entries sorted by id, when the stable order is enabled.
+    sorted: Option<vec::IntoIter<MaildirEntry>>,
 
-    pub fn id(&self) -> Result<&str> {
-        let file_name = self.file_name()?;
+    /// The error preventing the Maildir from being read, yielded
+    /// as the only item.
+    error: Option<Error>,
+}
 
-        Ok(match file_name.rsplit_once(self.info_separator) {
-            Some((id, _)) => id,
-            None => file_name,
-        })
-    }
+impl Iterator for MaildirEntries {
+    type Item = Result<MaildirEntry>;
 
-    pub fn read(&self) -> Result<Vec<u8>> {
-        let contents = fs::read(&self.path)?;
-        Ok(contents)
-    }
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(err) = self.error.take() {
+            return Some(Err(err));
+        }
 
-    pub fn read_headers(&self) -> Result<Vec<u8>> {
-        let file = File::open(&self.path)?;
-        let mut reader = BufReader::new(file);
-        let mut buffer = Vec::<u8>::new();
-        let mut headers = Vec::<u8>::new();
+        if let Some(sorted) = &mut self.sorted {
+            return sorted.next().map(Ok);
+        }
 
         loop {
-            match reader.read_until(b'\n', &mut buffer)? {
-                0 => {
-                    break;
-                }
-                1 if buffer[0] == b'\n' => {
-                    headers.push(b'\n');
-                    break;
-                }
-                2 if buffer[0] == b'\r' && buffer[1] == b'\n' => {
-                    headers.extend([b'\r', b'\n']);
-                    break;
-                }
-                _ => {
-                    headers.extend(&buffer);
-                    buffer.clear();
+            let Some(dir) = self.dirs.front_mut() else {
+                match fs::read_dir(self.pending_dirs.pop_front()?) {
+                    Ok(dir) => self.dirs.push_back(dir),
+                    Err(err) => return Some(Err(err.into())),
                 }
+                continue;
+            };
+
+            let Some(entry) = dir.next() else {
+                self.dirs.pop_front();
+                continue;
+            };
+
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(err) => return Some(Err(err.into())),
+            };
+
+            if is_entry(&entry) {
+                return Some(Ok(MaildirEntry::parse(
+                    entry.path(),
+                    self.info_separator.clone(),
+                )));
             }
         }
-
-        Ok(headers)
     }
+}
 
-    pub fn flags(&self) -> Result<HashSet<Flag>> {
-        Ok(match self.file_name()?.rsplit_once(self.info_separator) {
-            Some((_, flags)) => flags
-                .chars()
-                .map(TryFrom::try_from)
-                // remove invalid flag chars, including the "2,"
-                // located just after the info separator
-                .filter_map(Result::ok)
-                .collect(),
-            None => HashSet::new(),
-        })
+/// Extension trait for filtering iterators of Maildir entries.
+///
+/// Flags are parsed from entry file names, contents are never read.
+/// Entries whose flags cannot be parsed are considered as having no
+/// flag at all. Errors are passed through untouched.
+pub trait MaildirEntriesExt: Iterator<Item = Result<MaildirEntry>> + Sized {
+    /// Keeps only entries having the given flag.
+    fn with_flag(self, flag: Flag) -> impl Iterator<Item = Result<MaildirEntry>> {
+        self.filter(move |entry| keep(entry, |entry| entry.has_flag(flag)))
     }
 
-    pub fn has_trash_flag(&self) -> bool {
-        match self.flags() {
-            Ok(flags) => flags.contains(&Flag::Trashed),
-            Err(_) => false,
-        }
+    /// Keeps only entries not having the given flag.
+    fn without_flag(self, flag: Flag) -> impl Iterator<Item = Result<MaildirEntry>> {
+        self.filter(move |entry| keep(entry, |entry| !entry.has_flag(flag)))
     }
 
-    pub fn insert_flag(&mut self, flag: Flag) -> Result<()> {
-        self.insert_flags(Some(flag))
+    /// Keeps only entries having the [`Flag::Seen`] flag.
+    fn seen(self) -> impl Iterator<Item = Result<MaildirEntry>> {
+        self.with_flag(Flag::Seen)
     }
 
-    pub fn insert_flags(&mut self, flags: impl IntoIterator<Item = Flag>) -> Result<()> {
-        let (flags, changed) =
-            flags
-                .into_iter()
-                .fold((self.flags()?, false), |(mut flags, changed), flag| {
-                    let inserted = flags.insert(flag);
-                    (flags, changed || inserted)
-                });
-
-        if changed {
-            let prev_path = self.path();
-            let next_path = prev_path.with_file_name(self.format_file_name(flags)?);
+    /// Keeps only entries not having the [`Flag::Seen`] flag.
+    fn unseen(self) -> impl Iterator<Item = Result<MaildirEntry>> {
+        self.without_flag(Flag::Seen)
+    }
 
-            fs::rename(prev_path, &next_path)?;
-            self.path = next_path;
-        }
+    /// Keeps only entries having the [`Flag::Flagged`] flag.
+    fn flagged(self) -> impl Iterator<Item = Result<MaildirEntry>> {
+        self.with_flag(Flag::Flagged)
+    }
 
-        Ok(())
+    /// Keeps only entries that can be opened for reading.
+    ///
+    /// See [`MaildirEntry::is_readable`].
+    fn readable(self) -> impl Iterator<Item = Result<MaildirEntry>> {
+        self.filter(|entry| keep(entry, MaildirEntry::is_readable))
     }
 
-    pub fn update_flags(&mut self, flags: impl IntoIterator<Item = Flag>) -> Result<()> {
-        let prev_path = self.path();
-        let next_path =
-            prev_path.with_file_name(self.format_file_name(flags.into_iter().collect())?);
+    /// Keeps only entries that cannot be opened for reading.
+    ///
+    /// See [`MaildirEntry::is_readable`].
+    fn unreadable(self) -> impl Iterator<Item = Result<MaildirEntry>> {
+        self.filter(|entry| keep(entry, |entry| !entry.is_readable()))
+    }
+}
 
-        fs::rename(prev_path, &next_path)?;
-        self.path = next_path;
+impl<I: Iterator<Item = Result<MaildirEntry>>> MaildirEntriesExt for I {}
 
-        Ok(())
+/// Applies the given predicate to successfully read entries, errors
+/// are always kept.
+fn keep(entry: &Result<MaildirEntry>, predicate: impl Fn(&MaildirEntry) -> bool) -> bool {
+    match entry {
+        Ok(entry) => predicate(entry),
+        Err(_) => true,
     }
+}
 
-    pub fn remove_flag(&mut self, flag: Flag) -> Result<()> {
-        self.remove_flags(Some(flag))
+/// Splits the given unique name into the id and the `,X=<value>`
+/// fields, without the leading comma.
+fn split_unique_name(name: &str) -> (&str, &str) {
+    let field = name.match_indices(',').find(|(i, _)| {
+        let mut chars = name[i + 1..].chars();
+        matches!((chars.next(), chars.next()), (Some('A'..='Z'), Some('=')))
+    });
+
+    match field {
+        Some((i, _)) => (&name[..i], &name[i + 1..]),
+        None => (name, ""),
     }
+}
 
-    pub fn remove_flags<'a>(&mut self, flags: impl IntoIterator<Item = Flag>) -> Result<()> {
-        let (flags, changed) =
-            flags
-                .into_iter()
-                .fold((self.flags()?, false), |(mut flags, changed), ref flag| {
-                    let removed = flags.remove(flag);
-                    (flags, changed || removed)
-                });
+/// Rewrites the `,S=<size>` and `,W=<size>` fields of the given
+/// unique name to match the given contents.
+///
+/// `S` is the size in bytes, and `W` the size with CRLF line
+/// endings, as Dovecot computes it. Missing fields are not added.
+fn resize_unique_name(name: &str, contents: &[u8]) -> String {
+    let (id, fields) = split_unique_name(name);
 
-        if changed {
-            let prev_path = self.path();
-            let next_path = prev_path.with_file_name(self.format_file_name(flags)?);
+    if fields.is_empty() {
+        return id.to_owned();
+    }
 
-            fs::rename(prev_path, &next_path)?;
-            self.path = next_path;
+    let bare_lfs = contents
+        .iter()
+        .enumerate()
+        .filter(|(i, b)| **b == b'\n' && (*i == 0 || contents[i - 1] != b'\r'))
+        .count();
+
+    let fields = fields.split(',').map(|field| {
+        if field.starts_with("S=") {
+            format!("S={}", contents.len())
+        } else if field.starts_with("W=") {
+            format!("W={}", contents.len() + bare_lfs)
+        } else {
+            field.to_owned()
         }
+    });
 
-        Ok(())
+    let mut name = id.to_owned();
+
+    for field in fields {
+        name.push(',');
+        name.push_str(&field);
     }
 
-    pub fn copy(&self, mdir: &Maildir) -> Result<Option<PathBuf>> {
-        if Some(mdir.cur()) == self.path().parent() {
-            return Ok(None);
-        }
+    name
+}
 
-        let file_name = self.file_name()?;
-        let dest = mdir.cur().join(file_name);
-        fs::copy(self.path(), &dest)?;
+fn format_file_name(
+    sep: &str,
+    id: impl AsRef<str>,
+    flags: HashSet<Flag>,
+    unknown_flags: &str,
+) -> String {
+    let id = id.as_ref();
 
-        Ok(Some(dest))
-    }
+    // flags must be stored in ASCII order, see
+    // <https://cr.yp.to/proto/maildir.html>. Unknown flags, like
+    // lowercase keywords or uppercase flags of other tools, are
+    // sorted along with standard flags.
+    let mut chars: Vec<char> = flags_to_string(&flags).chars().collect();
+    chars.extend(unknown_flags.chars());
+    chars.sort();
+    chars.dedup();
 
-    pub fn r#move(&self, mdir: &Maildir) -> Result<Option<PathBuf>> {
-        if Some(mdir.cur()) == self.path().parent() {
-            return Ok(None);
-        }
+    let info: String = chars.into_iter().collect();
 
-        let file_name = self.file_name()?;
-        let dest = mdir.cur().join(file_name);
-        fs::rename(self.path(), &dest)?;
+    format!("{id}{sep}2,{info}")
+}
 
-        Ok(Some(dest))
-    }
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        sync::atomic::{AtomicUsize, Ordering},
+        time::{Duration, Instant},
+    };
 
-    pub fn remove(&self) -> Result<()> {
-        fs::remove_file(self.path())?;
-        Ok(())
-    }
+    use tempfile::tempdir;
 
-    fn format_file_name(&self, flags: HashSet<Flag>) -> Result<String> {
-        Ok(format_file_name(self.info_separator, self.id()?, flags))
+    use super::TMP_NAME_GENERATOR;
+    use crate::{Error, Maildir};
+
+    #[test]
+    fn write_conflicting_tmp_name() {
+        static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+        // the first generated name is taken, the next ones are free
+        TMP_NAME_GENERATOR.with(|generator| {
+            generator.set(Some(|| {
+                format!("tmp{}", CALLS.fetch_add(1, Ordering::SeqCst))
+            }))
+        });
+
+        let mdir = Maildir::from(tempdir().unwrap().into_path());
+        mdir.create_all().unwrap();
+        fs::write(mdir.tmp().join("tmp0"), b"taken").unwrap();
+
+        let start = Instant::now();
+        let entry = mdir.write_new(b"data").unwrap();
+
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert_eq!(CALLS.load(Ordering::SeqCst), 2);
+        assert_eq!(entry.read().unwrap(), b"data");
+        assert_eq!(fs::read(mdir.tmp().join("tmp0")).unwrap(), b"taken");
+        assert_eq!(fs::read_dir(mdir.tmp()).unwrap().count(), 1);
     }
-}
 
-fn format_file_name(sep: &'static str, id: impl AsRef<str>, flags: HashSet<Flag>) -> String {
-    let id = id.as_ref();
+    #[test]
+    fn write_exhausting_tmp_names() {
+        TMP_NAME_GENERATOR.with(|generator| generator.set(Some(|| String::from("taken"))));
 
-    let mut flags: Vec<&str> = flags.iter().map(AsRef::as_ref).collect();
-    flags.sort();
+        let mdir = Maildir::from(tempdir().unwrap().into_path());
+        mdir.create_all().unwrap();
+        fs::write(mdir.tmp().join("taken"), b"").unwrap();
 
-    format!("{id}{sep}2,{flags}", flags = flags.join(""))
+        let start = Instant::now();
+        let err = mdir.write_new(b"data").unwrap_err();
+
+        assert!(matches!(err, Error::DeliveryRetryExhaustedError(path) if path == mdir.tmp()));
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert_eq!(mdir.count_new().unwrap(), 0);
+        assert_eq!(fs::read(mdir.tmp().join("taken")).unwrap(), b"");
+    }
 }
@@ -0,0 +1,113 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::{MaildirEntry, Result};
+
+/// The sender used in `From ` lines when no sender is known, as
+/// commonly done by MTAs.
+static UNKNOWN_SENDER: &str = "MAILER-DAEMON";
+
+static WEEKDAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+
+static MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+impl MaildirEntry {
+    /// Builds the mbox `From ` separator line of the current entry,
+    /// without the trailing line break.
+    ///
+    /// The sender is the envelope sender stored on delivery (see
+    /// [`crate::Maildir::deliver_with_envelope`]). When the entry has
+    /// no envelope sender, the address of the `From:` header is used
+    /// instead. The date is the received date of the entry, in UTC.
+    pub fn mbox_from_line(&self) -> Result<String> {
+        let sender = match self.envelope_sender()? {
+            Some("") => UNKNOWN_SENDER.to_owned(),
+            Some(sender) => sender.to_owned(),
+            None => self
+                .header("From")?
+                .as_deref()
+                .and_then(parse_address)
+                .unwrap_or(UNKNOWN_SENDER)
+                .to_owned(),
+        };
+
+        let date = format_asctime(self.received_date()?);
+
+        Ok(format!("From {sender} {date}"))
+    }
+
+    /// Exports the current entry as an mbox message.
+    ///
+    /// The message starts with the line built by
+    /// [`MaildirEntry::mbox_from_line`], and ends with an empty line.
+    /// Lines of the contents starting with `From `, optionally
+    /// preceded by `>` characters, are quoted with an additional `>`
+    /// as described by the mboxrd format.
+    pub fn to_mbox(&self) -> Result<Vec<u8>> {
+        let contents = self.read()?;
+
+        let mut mbox = self.mbox_from_line()?.into_bytes();
+        mbox.push(b'\n');
+
+        for line in contents.split_inclusive(|b| *b == b'\n') {
+            let quotes = line.iter().take_while(|b| **b == b'>').count();
+
+            if line[quotes..].starts_with(b"From ") {
+                mbox.push(b'>');
+            }
+
+            mbox.extend_from_slice(line);
+        }
+
+        if !mbox.ends_with(b"\n") {
+            mbox.push(b'\n');
+        }
+
+        mbox.push(b'\n');
+
+        Ok(mbox)
+    }
+}
+
+/// Extracts the address of the given `From:` header value, either
+/// enclosed in angle brackets or standing alone.
+fn parse_address(value: &str) -> Option<&str> {
+    let address = match value.rsplit_once('<') {
+        Some((_, addr)) => addr.split_once('>')?.0,
+        None => value.trim(),
+    };
+
+    let valid = address.contains('@') && !address.contains(char::is_whitespace);
+    Some(address).filter(|_| valid)
+}
+
+/// Formats the given time like the C `asctime` function, in UTC, for
+/// example `Thu Jan  1 00:00:00 1970`.
+fn format_asctime(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+
+    let days = (secs / 86400) as i64;
+    let (hours, minutes, seconds) = (secs % 86400 / 3600, secs % 3600 / 60, secs % 60);
+
+    // converts days since epoch to a civil date, see
+    // <https://howardhinnant.github.io/date_algorithms.html>
+    let z = days + 719468;
+    let era = z / 146097;
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    // the epoch was a thursday
+    let weekday = WEEKDAYS[((days + 4) % 7) as usize];
+    let month = MONTHS[(month - 1) as usize];
+
+    format!("{weekday} {month} {day:>2} {hours:02}:{minutes:02}:{seconds:02} {year}")
+}
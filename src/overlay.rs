@@ -0,0 +1,188 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs, io,
+    path::PathBuf,
+};
+
+use crate::{maildir::write_atomically, Flag, Maildir, MaildirEntry, Result};
+
+/// The name of the sidecar file storing flags of overlay entries,
+/// located at the root of the primary Maildir.
+static OVERLAY_FLAGS: &str = "maildirs-overlay-flags";
+
+/// The source of an [`OverlayEntry`].
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum OverlaySource {
+    /// The entry comes from the primary, writable Maildir.
+    Primary,
+    /// The entry comes from the overlay, read-only Maildir.
+    Overlay,
+}
+
+/// A Maildir entry read from an [`OverlayMaildir`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct OverlayEntry {
+    source: OverlaySource,
+    entry: MaildirEntry,
+    flags: HashSet<Flag>,
+}
+
+impl OverlayEntry {
+    pub fn source(&self) -> OverlaySource {
+        self.source
+    }
+
+    pub fn entry(&self) -> &MaildirEntry {
+        &self.entry
+    }
+
+    /// Returns the flags of the entry.
+    ///
+    /// For overlay entries, flags come from the sidecar file of the
+    /// primary Maildir when set, otherwise from the entry file name.
+    pub fn flags(&self) -> &HashSet<Flag> {
+        &self.flags
+    }
+}
+
+/// A union of a primary Maildir and a read-only overlay Maildir,
+/// seen as one logical folder.
+///
+/// Entries are read from both Maildirs, but are always written to the
+/// primary one. Because the overlay Maildir is read-only, flags of
+/// overlay entries are stored in a sidecar file at the root of the
+/// primary Maildir.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct OverlayMaildir {
+    primary: Maildir,
+    overlay: Maildir,
+}
+
+impl OverlayMaildir {
+    pub fn new(primary: Maildir, overlay: Maildir) -> Self {
+        Self { primary, overlay }
+    }
+
+    pub fn primary(&self) -> &Maildir {
+        &self.primary
+    }
+
+    pub fn overlay(&self) -> &Maildir {
+        &self.overlay
+    }
+
+    /// Reads entries from both the primary and the overlay Maildirs.
+    pub fn read(&self) -> Result<impl Iterator<Item = Result<OverlayEntry>> + '_> {
+        let mut overlay_flags = self.read_overlay_flags()?;
+
+        let primary = self.primary.read()?.map(|entry| {
+            let entry = entry?;
+            Ok(OverlayEntry {
+                source: OverlaySource::Primary,
+                flags: entry.flags().unwrap_or_default(),
+                entry,
+            })
+        });
+
+        let overlay = self.overlay.read()?.map(move |entry| {
+            let entry = entry?;
+            let flags = entry
+                .id()
+                .ok()
+                .and_then(|id| overlay_flags.remove(id))
+                .unwrap_or_else(|| entry.flags().unwrap_or_default());
+
+            Ok(OverlayEntry {
+                source: OverlaySource::Overlay,
+                entry,
+                flags,
+            })
+        });
+
+        Ok(primary.chain(overlay))
+    }
+
+    pub fn write_new(&self, contents: impl AsRef<[u8]>) -> Result<MaildirEntry> {
+        self.primary.write_new(contents)
+    }
+
+    pub fn write_cur(
+        &self,
+        contents: impl AsRef<[u8]>,
+        flags: impl IntoIterator<Item = Flag>,
+    ) -> Result<MaildirEntry> {
+        self.primary.write_cur(contents, flags)
+    }
+
+    /// Replaces the flags of the given entry.
+    ///
+    /// Primary entries are renamed as usual, whereas flags of overlay
+    /// entries are stored in the sidecar file of the primary Maildir.
+    pub fn update_flags(
+        &self,
+        entry: &mut OverlayEntry,
+        flags: impl IntoIterator<Item = Flag>,
+    ) -> Result<()> {
+        let flags: HashSet<Flag> = flags.into_iter().collect();
+
+        match entry.source {
+            OverlaySource::Primary => {
+                entry.entry.update_flags(flags.clone())?;
+            }
+            OverlaySource::Overlay => {
+                let mut overlay_flags = self.read_overlay_flags()?;
+                overlay_flags.insert(entry.entry.id()?.to_owned(), flags.clone());
+                self.write_overlay_flags(&overlay_flags)?;
+            }
+        }
+
+        entry.flags = flags;
+        Ok(())
+    }
+
+    fn overlay_flags_path(&self) -> PathBuf {
+        self.primary.path().join(OVERLAY_FLAGS)
+    }
+
+    /// Reads the sidecar file, made of `<id> <flags>` lines.
+    fn read_overlay_flags(&self) -> Result<HashMap<String, HashSet<Flag>>> {
+        let contents = match fs::read_to_string(self.overlay_flags_path()) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(HashMap::new()),
+            Err(err) => return Err(err.into()),
+        };
+
+        let flags = contents
+            .lines()
+            .filter_map(|line| {
+                let (id, flags) = line.split_once(' ').unwrap_or((line, ""));
+                let flags = flags.chars().filter_map(|c| Flag::try_from(c).ok());
+                Some((id.to_owned(), flags.collect())).filter(|_| !id.is_empty())
+            })
+            .collect();
+
+        Ok(flags)
+    }
+
+    fn write_overlay_flags(&self, flags: &HashMap<String, HashSet<Flag>>) -> Result<()> {
+        let mut lines: Vec<String> = flags
+            .iter()
+            .map(|(id, flags)| {
+                let mut flags: Vec<&str> = flags.iter().map(AsRef::as_ref).collect();
+                flags.sort();
+                format!("{id} {}\n", flags.join(""))
+            })
+            .collect();
+        lines.sort();
+
+        write_atomically(self.overlay_flags_path(), lines.concat())
+    }
+}
+
+impl Maildir {
+    /// Builds an [`OverlayMaildir`] made of the current Maildir as
+    /// primary and the given Maildir as overlay.
+    pub fn with_overlay(&self, overlay: Maildir) -> OverlayMaildir {
+        OverlayMaildir::new(self.clone(), overlay)
+    }
+}
@@ -0,0 +1,183 @@
+use std::{
+    fmt,
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::Path,
+};
+
+use crate::{maildir::write_atomically, Error, Maildir, Result};
+
+/// The name of the quota file, located at the root of the Maildir.
+static MAILDIRSIZE: &str = "maildirsize";
+
+/// The quota of a Maildir, as stored in its `maildirsize` file.
+///
+/// The `maildirsize` file is maintained by Courier and Dovecot for
+/// quota enforcement, as described in the [Maildir++ quota
+/// specification]: the first line holds the quota definition, like
+/// `10485760S,1000C` for 10 MiB and 1000 messages, and each
+/// following line is a `<size> <count>` delta. The running totals
+/// are the sums of all deltas, which can be negative.
+///
+/// [Maildir++ quota specification]: http://www.courier-mta.org/imap/README.maildirquota.html
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+pub struct Quota {
+    size_limit: Option<u64>,
+    count_limit: Option<u64>,
+    size: i64,
+    count: i64,
+}
+
+impl Quota {
+    /// Returns the maximum total size of messages, in bytes.
+    pub fn size_limit(&self) -> Option<u64> {
+        self.size_limit
+    }
+
+    /// Returns the maximum number of messages.
+    pub fn count_limit(&self) -> Option<u64> {
+        self.count_limit
+    }
+
+    /// Returns the total size of messages, in bytes.
+    pub fn size(&self) -> i64 {
+        self.size
+    }
+
+    /// Returns the total number of messages.
+    pub fn count(&self) -> i64 {
+        self.count
+    }
+
+    /// Returns `true` if any of the limits is exceeded.
+    pub fn is_exceeded(&self) -> bool {
+        let exceeds = |limit: Option<u64>, total: i64| match limit {
+            Some(limit) => total > 0 && total as u64 > limit,
+            None => false,
+        };
+
+        exceeds(self.size_limit, self.size) || exceeds(self.count_limit, self.count)
+    }
+
+    fn parse(contents: &str) -> Option<Self> {
+        let mut lines = contents.lines();
+        let mut quota = Quota::default();
+
+        let definition = lines.next().unwrap_or_default();
+
+        for limit in definition.split(',').filter(|limit| !limit.is_empty()) {
+            if let Some(size) = limit.strip_suffix('S') {
+                quota.size_limit = Some(size.parse().ok()?);
+            } else if let Some(count) = limit.strip_suffix('C') {
+                quota.count_limit = Some(count.parse().ok()?);
+            } else {
+                return None;
+            }
+        }
+
+        for line in lines.filter(|line| !line.trim().is_empty()) {
+            let mut delta = line.split_whitespace();
+            quota.size += delta.next()?.parse::<i64>().ok()?;
+            quota.count += delta.next()?.parse::<i64>().ok()?;
+        }
+
+        Some(quota)
+    }
+}
+
+/// Displays the quota definition line, without the trailing line
+/// break.
+impl fmt::Display for Quota {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.size_limit, self.count_limit) {
+            (Some(size), Some(count)) => write!(f, "{size}S,{count}C"),
+            (Some(size), None) => write!(f, "{size}S"),
+            (None, Some(count)) => write!(f, "{count}C"),
+            (None, None) => Ok(()),
+        }
+    }
+}
+
+impl Maildir {
+    /// Reads the quota of the current Maildir from its `maildirsize`
+    /// file.
+    ///
+    /// Returns `None` when the Maildir has no `maildirsize` file.
+    pub fn quota(&self) -> Result<Option<Quota>> {
+        let path = self.path().join(MAILDIRSIZE);
+
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+
+        match Quota::parse(&contents) {
+            Some(quota) => Ok(Some(quota)),
+            None => Err(Error::ParseQuotaError(path)),
+        }
+    }
+
+    /// Rewrites the `maildirsize` file of the current Maildir by
+    /// summing the sizes of all entries from `new` and `cur`.
+    ///
+    /// The quota definition is preserved, or left empty if the file
+    /// does not exist yet. This allows to repair a corrupted file.
+    pub fn recalculate_quota(&self) -> Result<()> {
+        let path = self.path().join(MAILDIRSIZE);
+
+        let mut quota = match fs::read_to_string(&path) {
+            Ok(contents) => {
+                let definition = contents.lines().next().unwrap_or_default();
+                Quota::parse(definition).ok_or_else(|| Error::ParseQuotaError(path.clone()))?
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Quota::default(),
+            Err(err) => return Err(err.into()),
+        };
+
+        for entry in self.read()? {
+            quota.size += entry?.path().metadata()?.len() as i64;
+            quota.count += 1;
+        }
+
+        write_atomically(
+            &path,
+            format!(
+                "{quota}\n{size} {count}\n",
+                size = quota.size,
+                count = quota.count
+            ),
+        )
+    }
+}
+
+/// Appends a delta line for a new message of the given size to the
+/// `maildirsize` file located in the given Maildir root, if any.
+pub(crate) fn append_quota_delta(root: &Path, size: u64) -> Result<()> {
+    let file = OpenOptions::new().append(true).open(root.join(MAILDIRSIZE));
+
+    match file {
+        // a single small write in append mode does not interleave
+        // with concurrent appends
+        Ok(mut file) => Ok(file.write_all(format!("{size} 1\n").as_bytes())?),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Async counterpart of [`append_quota_delta`].
+#[cfg(feature = "tokio")]
+pub(crate) async fn append_quota_delta_async(root: &Path, size: u64) -> Result<()> {
+    use tokio::io::AsyncWriteExt;
+
+    let file = tokio::fs::OpenOptions::new()
+        .append(true)
+        .open(root.join(MAILDIRSIZE))
+        .await;
+
+    match file {
+        Ok(mut file) => Ok(file.write_all(format!("{size} 1\n").as_bytes()).await?),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(err) => Err(err.into()),
+    }
+}
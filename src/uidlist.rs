@@ -0,0 +1,199 @@
+use std::{
+    fmt,
+    fs::{self, File},
+    io,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{lock::lock_sibling, maildir::write_atomically, Error, Maildir, MaildirEntry, Result};
+
+/// The name of the UID list file, located at the root of the Maildir.
+static UIDLIST: &str = "maildirs-uidlist";
+
+/// The UID list of a Maildir.
+///
+/// The UID list maps Maildir entry ids to stable, monotonically
+/// increasing UIDs, as required by IMAP. It is stored in a
+/// `maildirs-uidlist` file at the root of the Maildir, inspired by
+/// the `dovecot-uidlist` format: the first line is `1 <uidvalidity>
+/// <next uid>`, and each following line is `<uid> <id>`.
+///
+/// Every change rewrites the file to a temporary file then renames
+/// it, so readers never see a partial file. Writers hold an exclusive
+/// advisory lock on a sibling `.lock` file from the moment they read
+/// the list until it is renamed, so that concurrent writers, in this
+/// process or others, never lose updates.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct Uidlist {
+    path: PathBuf,
+}
+
+impl Uidlist {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the UID validity of the list, creating the list if it
+    /// does not exist yet.
+    pub fn uidvalidity(&self) -> Result<u32> {
+        Ok(self.read_or_create()?.uidvalidity)
+    }
+
+    /// Returns the UID that will be assigned to the next entry.
+    pub fn next_uid(&self) -> Result<u32> {
+        Ok(self.read_or_create()?.next_uid)
+    }
+
+    /// Returns the UID of the entry matching the given id, if any.
+    pub fn uid_of(&self, id: impl AsRef<str>) -> Result<Option<u32>> {
+        let id = id.as_ref();
+
+        let Some(data) = self.read()? else {
+            return Ok(None);
+        };
+
+        Ok(data
+            .uids
+            .into_iter()
+            .find(|(_, i)| i == id)
+            .map(|(uid, _)| uid))
+    }
+
+    /// Assigns a UID to the given entry, or returns the UID already
+    /// assigned to it.
+    pub fn assign_uid(&self, entry: &MaildirEntry) -> Result<u32> {
+        let id = entry.id()?;
+
+        if let Some(uid) = self.uid_of(id)? {
+            return Ok(uid);
+        }
+
+        let _lock = self.lock()?;
+        let mut data = self.read_or_create_locked()?;
+
+        // the entry may have been assigned a uid while waiting for
+        // the lock
+        if let Some((uid, _)) = data.uids.iter().find(|(_, i)| i == id) {
+            return Ok(*uid);
+        }
+
+        let uid = data.next_uid;
+        data.next_uid += 1;
+        data.uids.push((uid, id.to_owned()));
+        self.write(&data)?;
+
+        Ok(uid)
+    }
+
+    fn read(&self) -> Result<Option<UidlistData>> {
+        let contents = match fs::read_to_string(&self.path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+
+        match UidlistData::parse(&contents) {
+            Some(data) => Ok(Some(data)),
+            None => Err(Error::ParseUidlistError(self.path.clone())),
+        }
+    }
+
+    fn read_or_create(&self) -> Result<UidlistData> {
+        if let Some(data) = self.read()? {
+            return Ok(data);
+        }
+
+        let _lock = self.lock()?;
+        self.read_or_create_locked()
+    }
+
+    /// Same as [`Uidlist::read_or_create`], for callers already
+    /// holding the lock.
+    fn read_or_create_locked(&self) -> Result<UidlistData> {
+        if let Some(data) = self.read()? {
+            return Ok(data);
+        }
+
+        let data = UidlistData {
+            uidvalidity: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as u32,
+            next_uid: 1,
+            uids: Vec::new(),
+        };
+
+        self.write(&data)?;
+        Ok(data)
+    }
+
+    fn write(&self, data: &UidlistData) -> Result<()> {
+        write_atomically(&self.path, data.to_string())
+    }
+
+    /// Locks the list for writing, blocking until the lock is
+    /// available. The lock is released when the returned file is
+    /// dropped.
+    ///
+    /// The list itself cannot be locked, since every write replaces
+    /// it with another file.
+    fn lock(&self) -> Result<File> {
+        lock_sibling(&self.path)
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct UidlistData {
+    uidvalidity: u32,
+    next_uid: u32,
+    uids: Vec<(u32, String)>,
+}
+
+impl UidlistData {
+    fn parse(contents: &str) -> Option<Self> {
+        let mut lines = contents.lines();
+        let mut header = lines.next()?.split(' ');
+
+        if header.next()? != "1" {
+            return None;
+        }
+
+        let uidvalidity = header.next()?.parse().ok()?;
+        let next_uid = header.next()?.parse().ok()?;
+
+        let uids = lines
+            .filter(|line| !line.is_empty())
+            .map(|line| {
+                let (uid, id) = line.split_once(' ')?;
+                Some((uid.parse().ok()?, id.to_owned()))
+            })
+            .collect::<Option<_>>()?;
+
+        Some(Self {
+            uidvalidity,
+            next_uid,
+            uids,
+        })
+    }
+}
+
+impl fmt::Display for UidlistData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "1 {} {}", self.uidvalidity, self.next_uid)?;
+
+        for (uid, id) in &self.uids {
+            writeln!(f, "{uid} {id}")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Maildir {
+    /// Returns the UID list of the current Maildir.
+    pub fn uidlist(&self) -> Uidlist {
+        Uidlist::new(self.path().join(UIDLIST))
+    }
+}
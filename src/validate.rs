@@ -1,17 +1,82 @@
-use crate::Error;
+//! Validation of caller-supplied Maildir entry ids and folder names.
 
-pub(crate) fn validate_id(id: String) -> Result<String, Error> {
-    if id.contains('/') {
-        Err(Error::InvalidIdError(id))
+use crate::{Error, Result};
+
+/// The info separators permitted by the Maildir specification.
+///
+/// Both are rejected, whatever the configured separator, so that ids
+/// and folder names can be shared between platforms.
+static INFO_SEPARATORS: [char; 2] = [':', ';'];
+
+/// The device names reserved by Windows, which cannot be used as file
+/// names, even with an extension.
+#[cfg(windows)]
+static WINDOWS_RESERVED_NAMES: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Validates a Maildir entry id.
+///
+/// An id cannot be empty, start with a period, contain slashes, info
+/// separators (`:` and `;`) or control characters (NUL included),
+/// start or end with whitespace, nor be a name reserved by Windows
+/// (on Windows only). Fails with [`Error::InvalidIdError`].
+pub fn validate_id(id: &str) -> Result<()> {
+    let invalid = id.is_empty()
+        || id.starts_with('.')
+        || id.contains('/')
+        || id.contains(INFO_SEPARATORS)
+        || id.contains(char::is_control)
+        || id.trim() != id
+        || is_reserved(id);
+
+    if invalid {
+        Err(Error::InvalidIdError(id.to_owned()))
     } else {
-        Ok(id)
+        Ok(())
     }
 }
 
-pub(crate) fn validate_folder(folder: &str) -> Result<(), Error> {
-    if folder.contains('/') {
-        Err(Error::InvalidFolderError(folder.to_string()))
+/// Validates a folder name.
+///
+/// A folder name cannot be empty, contain slashes, info separators
+/// (`:` and `;`) or control characters (NUL included), start or end
+/// with whitespace, nor be a name reserved by Windows (on Windows
+/// only). Fails with [`Error::InvalidFolderError`], holding the
+/// reason why the folder is invalid.
+pub fn validate_folder(folder: &str) -> Result<()> {
+    let reason = if folder.is_empty() {
+        "folder cannot be empty"
+    } else if folder.contains('/') {
+        "folder cannot contain slashes"
+    } else if folder.contains(INFO_SEPARATORS) {
+        "folder cannot contain info separators"
+    } else if folder.contains(char::is_control) {
+        "folder cannot contain control characters"
+    } else if folder.trim() != folder {
+        "folder cannot start or end with whitespace"
+    } else if is_reserved(folder) {
+        "folder cannot be a reserved Windows name"
     } else {
-        Ok(())
-    }
+        return Ok(());
+    };
+
+    Err(Error::InvalidFolderError(folder.to_owned(), reason))
+}
+
+/// Returns `true` if the given name is reserved by Windows.
+#[cfg(windows)]
+fn is_reserved(name: &str) -> bool {
+    let stem = name.split('.').next().unwrap_or_default().trim_end();
+
+    WINDOWS_RESERVED_NAMES
+        .iter()
+        .any(|reserved| stem.eq_ignore_ascii_case(reserved))
+}
+
+/// Returns `true` if the given name is reserved by Windows.
+#[cfg(not(windows))]
+fn is_reserved(_name: &str) -> bool {
+    false
 }
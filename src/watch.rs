@@ -0,0 +1,185 @@
+use std::{
+    collections::{HashSet, VecDeque},
+    path::PathBuf,
+    sync::mpsc::{self, Receiver, RecvTimeoutError},
+    time::Duration,
+};
+
+use notify::{event::ModifyKind, Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+
+use crate::{Maildir, MaildirEntry, Result};
+
+/// The default duration during which filesystem events are gathered
+/// before being translated into Maildir events.
+static DEFAULT_DEBOUNCE: Duration = Duration::from_millis(50);
+
+/// A change of a Maildir, reported by [`MaildirWatcher`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum MaildirEvent {
+    /// An entry has been delivered to `new` or `cur`.
+    NewMessage(MaildirEntry),
+
+    /// An entry has been renamed, like when its flags change or when
+    /// it moves from `new` to `cur`. The entry points to its next
+    /// path.
+    FlagsChanged(MaildirEntry),
+
+    /// An entry has been removed. The entry points to its previous
+    /// path, which does not exist anymore.
+    Removed(MaildirEntry),
+}
+
+impl Maildir {
+    /// Watches the `new` and `cur` directories of the current Maildir
+    /// for changes.
+    ///
+    /// The returned [`MaildirWatcher`] is a blocking iterator of
+    /// [`MaildirEvent`]s, which stops once the underlying watcher
+    /// fails.
+    pub fn watch(&self) -> Result<MaildirWatcher> {
+        let (sender, events) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(sender)?;
+
+        watcher.watch(self.new(), RecursiveMode::NonRecursive)?;
+        watcher.watch(self.cur(), RecursiveMode::NonRecursive)?;
+
+        Ok(MaildirWatcher {
+            mdir: self.clone(),
+            _watcher: watcher,
+            events,
+            pending: VecDeque::new(),
+            debounce: DEFAULT_DEBOUNCE,
+        })
+    }
+}
+
+/// The watcher of a Maildir, returned by [`Maildir::watch`].
+///
+/// Filesystem events are gathered until none is received for the
+/// debounce duration (see [`MaildirWatcher::set_debounce`]), then
+/// translated into [`MaildirEvent`]s: an entry disappearing and an
+/// entry appearing with the same id within the same batch, like when
+/// flags change, is reported as [`MaildirEvent::FlagsChanged`]
+/// instead of a removal followed by a new message. Entries appearing
+/// and disappearing within the same batch are not reported.
+#[derive(Debug)]
+pub struct MaildirWatcher {
+    mdir: Maildir,
+
+    /// The underlying watcher, kept alive so that events keep being
+    /// sent.
+    _watcher: RecommendedWatcher,
+
+    events: Receiver<notify::Result<Event>>,
+    pending: VecDeque<Result<MaildirEvent>>,
+    debounce: Duration,
+}
+
+impl MaildirWatcher {
+    /// Sets the duration during which filesystem events are gathered
+    /// before being translated. Defaults to 50 milliseconds.
+    pub fn set_debounce(&mut self, debounce: Duration) {
+        self.debounce = debounce;
+    }
+
+    pub fn with_debounce(mut self, debounce: Duration) -> Self {
+        self.set_debounce(debounce);
+        self
+    }
+
+    /// Translates the given batch of filesystem events into Maildir
+    /// events.
+    fn translate(&mut self, batch: Vec<notify::Result<Event>>) {
+        let mut paths: Vec<PathBuf> = Vec::new();
+        let mut created: HashSet<PathBuf> = HashSet::new();
+
+        for event in batch {
+            let event = match event {
+                Ok(event) => event,
+                Err(err) => {
+                    self.pending.push_back(Err(err.into()));
+                    continue;
+                }
+            };
+
+            match event.kind {
+                EventKind::Create(_) => created.extend(event.paths.iter().cloned()),
+                EventKind::Remove(_) | EventKind::Modify(ModifyKind::Name(_)) => (),
+                _ => continue,
+            }
+
+            for path in event.paths {
+                if !paths.contains(&path) {
+                    paths.push(path);
+                }
+            }
+        }
+
+        let mut appeared = Vec::new();
+        let mut disappeared = Vec::new();
+
+        for path in paths {
+            let is_dotfile = match path.file_name().and_then(|name| name.to_str()) {
+                Some(name) => name.starts_with('.'),
+                None => true,
+            };
+
+            if is_dotfile {
+                continue;
+            }
+
+            if path.is_file() {
+                appeared.push(self.mdir.entry(path));
+            } else if !path.exists() && !created.contains(&path) {
+                disappeared.push(self.mdir.entry(path));
+            }
+        }
+
+        let mut renamed = HashSet::new();
+
+        for entry in appeared {
+            match entry.id() {
+                Ok(id) if disappeared.iter().any(|prev| prev.id().ok() == Some(id)) => {
+                    renamed.insert(id.to_owned());
+                    self.pending
+                        .push_back(Ok(MaildirEvent::FlagsChanged(entry)));
+                }
+                _ => {
+                    self.pending.push_back(Ok(MaildirEvent::NewMessage(entry)));
+                }
+            }
+        }
+
+        for entry in disappeared {
+            if !matches!(entry.id(), Ok(id) if renamed.contains(id)) {
+                self.pending.push_back(Ok(MaildirEvent::Removed(entry)));
+            }
+        }
+    }
+}
+
+impl Iterator for MaildirWatcher {
+    type Item = Result<MaildirEvent>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(event) = self.pending.pop_front() {
+                return Some(event);
+            }
+
+            // blocks until the first event of the batch, then gathers
+            // events until the debounce duration elapses without any
+            let mut batch = vec![self.events.recv().ok()?];
+
+            loop {
+                match self.events.recv_timeout(self.debounce) {
+                    Ok(event) => batch.push(event),
+                    Err(RecvTimeoutError::Timeout) => break,
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+            }
+
+            self.translate(batch);
+        }
+    }
+}
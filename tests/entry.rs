@@ -1,6 +1,16 @@
-use std::collections::HashSet;
+use std::{
+    collections::HashSet,
+    fs,
+    io::{Read, Seek, SeekFrom},
+    sync::Barrier,
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
-use maildirs::{Flag, Maildirs};
+use maildirs::{
+    DuplicatePolicy, Error, Flag, Maildir, MaildirBuilder, MaildirEntriesExt, MaildirEntry,
+    Maildirs, UniqueName,
+};
 use tempfile::tempdir;
 
 #[test]
@@ -38,9 +48,10 @@ fn manage_maildir_entries() {
     assert_eq!(a.read().unwrap().count(), 1);
     assert_eq!(b.read().unwrap().count(), 1);
 
+    // the moved entry collides with its copy, so it gets a new name
     entry.r#move(&b).unwrap();
     assert_eq!(a.read().unwrap().count(), 0);
-    assert_eq!(b.read().unwrap().count(), 1);
+    assert_eq!(b.read().unwrap().count(), 2);
 }
 
 #[test]
@@ -63,3 +74,1106 @@ fn change_maildir_entry_flags() {
     let expected_flags = HashSet::from_iter([Flag::Draft]);
     assert_eq!(entry.flags().unwrap(), expected_flags);
 }
+
+#[test]
+fn move_maildir_entry_to_same_maildir() {
+    let mdirs = Maildirs::new(tempdir().unwrap().into_path());
+    let a = mdirs.create("a").unwrap();
+    let b = mdirs.create("b").unwrap();
+    let entry = a.write_cur(b"data", None).unwrap();
+
+    assert_eq!(entry.copy(&a).unwrap(), None);
+    assert_eq!(entry.r#move(&a).unwrap(), None);
+    assert!(matches!(
+        entry.copy_checked(&a),
+        Err(Error::CopyEmailSamePathError(_))
+    ));
+    assert!(matches!(
+        entry.move_checked(&a),
+        Err(Error::CopyEmailSamePathError(_))
+    ));
+    assert_eq!(a.read().unwrap().count(), 1);
+
+    let dest = entry.move_checked(&b).unwrap();
+    assert_eq!(dest.parent(), Some(b.cur()));
+    assert_eq!(a.read().unwrap().count(), 0);
+    assert_eq!(b.read().unwrap().count(), 1);
+}
+
+#[test]
+fn copy_and_move_maildir_entry_to_colliding_name() {
+    let mdirs = Maildirs::new(tempdir().unwrap().into_path());
+    let a = mdirs.create("a").unwrap();
+    let b = mdirs.create("b").unwrap();
+    let entry = a.write_cur(b"a", [Flag::Seen]).unwrap();
+    fs::write(b.cur().join(entry.file_name().unwrap()), b"b").unwrap();
+
+    let dest = entry.copy(&b).unwrap().unwrap();
+    assert_ne!(dest.file_name(), entry.path().file_name());
+    assert_eq!(fs::read(&dest).unwrap(), b"a");
+
+    let dest = entry.r#move(&b).unwrap().unwrap();
+    assert!(!entry.path().exists());
+    assert_eq!(fs::read(&dest).unwrap(), b"a");
+    assert_eq!(
+        MaildirEntry::new(&dest).flags().unwrap(),
+        HashSet::from_iter([Flag::Seen])
+    );
+
+    let collided = b.cur().join(entry.file_name().unwrap());
+    assert_eq!(fs::read(collided).unwrap(), b"b");
+    assert_eq!(b.read().unwrap().count(), 3);
+}
+
+#[cfg(unix)]
+#[test]
+fn copy_maildir_entry_keeps_permissions() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mdirs = Maildirs::new(tempdir().unwrap().into_path());
+    let a = mdirs.create("a").unwrap();
+    let b = mdirs.create("b").unwrap();
+    let entry = a.write_cur(b"a", [Flag::Seen]).unwrap();
+    fs::set_permissions(entry.path(), fs::Permissions::from_mode(0o640)).unwrap();
+
+    let dest = entry.copy(&b).unwrap().unwrap();
+    let mode = fs::metadata(dest).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o640);
+}
+
+#[test]
+fn copy_and_move_maildir_entry_with_duplicate_policy() {
+    let mdirs = Maildirs::new(tempdir().unwrap().into_path());
+    let a = mdirs.create("a").unwrap();
+    let entry = a.write_cur(b"a", [Flag::Seen]).unwrap();
+    let file_name = entry.file_name().unwrap();
+
+    let b = mdirs.create("b").unwrap();
+    let collided = b.cur().join(file_name);
+    fs::write(&collided, b"b").unwrap();
+
+    let b = b.with_duplicate_policy(DuplicatePolicy::Skip);
+    assert_eq!(entry.copy(&b).unwrap(), None);
+    assert_eq!(entry.r#move(&b).unwrap(), None);
+    assert!(entry.path().exists());
+    assert_eq!(fs::read(&collided).unwrap(), b"b");
+    assert_eq!(b.read().unwrap().count(), 1);
+
+    let b = b.with_duplicate_policy(DuplicatePolicy::Rename);
+    let dest = entry.copy(&b).unwrap().unwrap();
+    assert_ne!(dest, collided);
+    assert_eq!(fs::read(&collided).unwrap(), b"b");
+    assert_eq!(b.read().unwrap().count(), 2);
+
+    let b = b.with_duplicate_policy(DuplicatePolicy::Overwrite);
+    let dest = entry.r#move(&b).unwrap().unwrap();
+    assert_eq!(dest, collided);
+    assert!(!entry.path().exists());
+    assert_eq!(fs::read(&collided).unwrap(), b"a");
+    assert_eq!(b.read().unwrap().count(), 2);
+
+    // the policy can be set by the builder as well
+    let b = MaildirBuilder::new()
+        .with_duplicate_policy(DuplicatePolicy::Skip)
+        .build(b.path());
+    let entry = a.write_cur(b"a", [Flag::Seen]).unwrap();
+    fs::write(b.cur().join(entry.file_name().unwrap()), b"b").unwrap();
+    assert_eq!(entry.copy(&b).unwrap(), None);
+}
+
+#[test]
+fn filter_maildir_entries_by_flag() {
+    let mdirs = Maildirs::new(tempdir().unwrap().into_path());
+    let mdir = mdirs.create("mdir").unwrap();
+    mdir.write_new(b"data").unwrap();
+    mdir.write_cur(b"data", None).unwrap();
+    mdir.write_cur(b"data", [Flag::Seen]).unwrap();
+    mdir.write_cur(b"data", [Flag::Seen, Flag::Flagged])
+        .unwrap();
+    mdir.write_cur(b"data", [Flag::Draft]).unwrap();
+
+    assert_eq!(mdir.read().unwrap().seen().count(), 2);
+    assert_eq!(mdir.read().unwrap().unseen().count(), 3);
+    assert_eq!(mdir.read().unwrap().flagged().count(), 1);
+    assert_eq!(mdir.read().unwrap().with_flag(Flag::Draft).count(), 1);
+    assert_eq!(mdir.read().unwrap().without_flag(Flag::Draft).count(), 4);
+    assert_eq!(mdir.read().unwrap().seen().unseen().count(), 0);
+}
+
+#[test]
+fn deliver_file_to_maildir() {
+    let dir = tempdir().unwrap();
+    let src = dir.path().join("message.eml");
+    fs::write(&src, b"data").unwrap();
+
+    let mdirs = Maildirs::new(dir.path().join("mdirs"));
+    let mdir = mdirs.create("mdir").unwrap();
+
+    let entry = mdir.deliver_file(&src, None, true).unwrap();
+    assert_eq!(entry.path().parent(), Some(mdir.new()));
+    assert_eq!(entry.read().unwrap(), b"data");
+
+    let entry = mdir.deliver_file(&src, [Flag::Seen], false).unwrap();
+    assert_eq!(entry.path().parent(), Some(mdir.cur()));
+    assert_eq!(entry.flags().unwrap(), HashSet::from_iter([Flag::Seen]));
+    assert_eq!(entry.read().unwrap(), b"data");
+
+    // the source file is hard linked, not moved nor copied
+    assert!(src.is_file());
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        let src_ino = fs::metadata(&src).unwrap().ino();
+        assert_eq!(fs::metadata(entry.path()).unwrap().ino(), src_ino);
+    }
+
+    assert_eq!(mdir.read().unwrap().count(), 2);
+    assert_eq!(fs::read_dir(mdir.tmp()).unwrap().count(), 0);
+}
+
+#[test]
+fn filter_unreadable_maildir_entries() {
+    let mdirs = Maildirs::new(tempdir().unwrap().into_path());
+    let mdir = mdirs.create("mdir").unwrap();
+    let readable = mdir.write_cur(b"data", None).unwrap();
+
+    // a removed entry cannot be opened, whatever the privileges
+    let removed = mdir.write_cur(b"data", None).unwrap();
+    fs::remove_file(removed.path()).unwrap();
+    assert!(readable.is_readable());
+    assert!(!removed.is_readable());
+
+    let entries = || {
+        vec![
+            Ok(readable.clone()),
+            Ok(removed.clone()),
+            Err(Error::InvalidKeywordError(String::new())),
+        ]
+        .into_iter()
+    };
+
+    // errors are kept by both filters
+    let filtered: Vec<_> = entries().readable().collect();
+    assert_eq!(filtered.len(), 2);
+    assert_eq!(filtered[0].as_ref().unwrap(), &readable);
+    assert!(filtered[1].is_err());
+
+    let filtered: Vec<_> = entries().unreadable().collect();
+    assert_eq!(filtered.len(), 2);
+    assert_eq!(filtered[0].as_ref().unwrap(), &removed);
+    assert!(filtered[1].is_err());
+}
+
+#[cfg(unix)]
+#[test]
+fn filter_maildir_entries_without_read_permission() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mdirs = Maildirs::new(tempdir().unwrap().into_path());
+    let mdir = mdirs.create("mdir").unwrap();
+    mdir.write_cur(b"data", None).unwrap();
+    let entry = mdir.write_cur(b"data", None).unwrap();
+    fs::set_permissions(entry.path(), fs::Permissions::from_mode(0o000)).unwrap();
+
+    if fs::File::open(entry.path()).is_ok() {
+        eprintln!("skipped: privileged users can read entries without read permission");
+        return;
+    }
+
+    assert!(!entry.is_readable());
+    assert_eq!(mdir.read().unwrap().count(), 2);
+    assert_eq!(mdir.read().unwrap().readable().count(), 1);
+
+    let unreadable: Vec<_> = mdir
+        .read()
+        .unwrap()
+        .unreadable()
+        .map(Result::unwrap)
+        .collect();
+    assert_eq!(unreadable, vec![entry]);
+}
+
+#[test]
+fn preserve_unknown_maildir_entry_flags() {
+    let mdirs = Maildirs::new(tempdir().unwrap().into_path());
+    let mdir = mdirs.create("mdir").unwrap();
+    let entry = mdir.write_cur(b"data", [Flag::Seen]).unwrap();
+    let id = entry.id().unwrap().to_owned();
+
+    // simulate keywords written by another tool
+    let path = entry
+        .path()
+        .with_file_name(format!("{}ab", entry.file_name().unwrap()));
+    fs::rename(entry.path(), path).unwrap();
+    let mut entry = mdir.get(&id).unwrap();
+
+    assert_eq!(entry.flags_raw().unwrap(), "Sab");
+    assert_eq!(entry.flags().unwrap(), HashSet::from_iter([Flag::Seen]));
+
+    entry.insert_flag(Flag::Flagged).unwrap();
+    assert_eq!(entry.flags_raw().unwrap(), "FSab");
+
+    entry.remove_flag(Flag::Seen).unwrap();
+    assert_eq!(entry.flags_raw().unwrap(), "Fab");
+
+    entry.update_flags([Flag::Draft]).unwrap();
+    assert_eq!(entry.flags_raw().unwrap(), "Dab");
+    assert_eq!(entry.id().unwrap(), id);
+}
+
+#[test]
+fn preserve_maildir_entry_fields() {
+    let mdirs = Maildirs::new(tempdir().unwrap().into_path());
+    let mdir = mdirs.create("mdir").unwrap();
+
+    // simulate an entry delivered by Dovecot
+    let path = mdir.cur().join("1700000000.M1P1.host,S=123,W=130:2,FS");
+    fs::write(path, b"data").unwrap();
+    let mut entry = mdir.get("1700000000.M1P1.host").unwrap();
+
+    entry.insert_flag(Flag::Replied).unwrap();
+    assert_eq!(
+        entry.file_name().unwrap(),
+        "1700000000.M1P1.host,S=123,W=130:2,FRS"
+    );
+
+    entry.remove_flags([Flag::Seen, Flag::Flagged]).unwrap();
+    assert_eq!(
+        entry.file_name().unwrap(),
+        "1700000000.M1P1.host,S=123,W=130:2,R"
+    );
+
+    entry.update_flags([Flag::Draft]).unwrap();
+    assert_eq!(
+        entry.file_name().unwrap(),
+        "1700000000.M1P1.host,S=123,W=130:2,D"
+    );
+    assert_eq!(entry.size_hint().unwrap(), Some(123));
+}
+
+#[test]
+fn change_concurrently_renamed_maildir_entry_flags() {
+    let mdirs = Maildirs::new(tempdir().unwrap().into_path());
+    let mdir = mdirs.create("mdir").unwrap();
+    let mut entry = mdir.write_cur(b"data", [Flag::Passed]).unwrap();
+    let mut stale_entry = entry.clone();
+
+    // simulate another process renaming the entry
+    entry.insert_flag(Flag::Seen).unwrap();
+    assert!(!stale_entry.path().exists());
+
+    stale_entry.insert_flag(Flag::Flagged).unwrap();
+    assert!(stale_entry.path().exists());
+    assert_eq!(stale_entry.id().unwrap(), entry.id().unwrap());
+
+    let expected_flags = HashSet::from_iter([Flag::Passed, Flag::Seen, Flag::Flagged]);
+    assert_eq!(stale_entry.flags().unwrap(), expected_flags);
+    assert_eq!(mdir.read().unwrap().count(), 1);
+}
+
+#[test]
+fn write_maildir_entry_with_size_field() {
+    let root = tempdir().unwrap().into_path();
+    let mdir = MaildirBuilder::new().with_size_field(true).build(root);
+    mdir.create_all().unwrap();
+
+    let entry = mdir.write_new(b"data").unwrap();
+    assert!(entry.file_name().unwrap().ends_with(",S=4"));
+    assert!(!entry.id().unwrap().contains(','));
+    assert_eq!(entry.size_hint().unwrap(), Some(4));
+    assert_eq!(mdir.get(entry.id().unwrap()).unwrap(), entry);
+
+    let mut entry = mdir.write_cur(b"data", [Flag::Seen]).unwrap();
+    let id = entry.id().unwrap().to_owned();
+    assert_eq!(entry.size_hint().unwrap(), Some(4));
+
+    entry.insert_flag(Flag::Flagged).unwrap();
+    assert_eq!(entry.id().unwrap(), id);
+    assert_eq!(entry.size_hint().unwrap(), Some(4));
+
+    // the size field is disabled by default
+    let mdir = Maildirs::new(tempdir().unwrap().into_path())
+        .create("mdir")
+        .unwrap();
+    let entry = mdir.write_new(b"data").unwrap();
+    assert_eq!(entry.file_name().unwrap(), entry.id().unwrap());
+    assert_eq!(entry.size_hint().unwrap(), None);
+}
+
+#[test]
+fn get_maildir_entry_received_date() {
+    let mdirs = Maildirs::new(tempdir().unwrap().into_path());
+    let mdir = mdirs.create("mdir").unwrap();
+
+    let before = SystemTime::now() - Duration::from_secs(1);
+    let entry = mdir.write_new(b"data").unwrap();
+    let date = entry.received_date().unwrap();
+    assert!(date >= before && date <= SystemTime::now());
+
+    fs::write(mdir.cur().join("1700000000.foo.host"), b"data").unwrap();
+    let entry = mdir.get("1700000000.foo.host").unwrap();
+    let expected_date = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+    assert_eq!(entry.received_date().unwrap(), expected_date);
+
+    // names generated by other tools fall back to the file mtime
+    fs::write(mdir.cur().join("foreign-name"), b"data").unwrap();
+    let entry = mdir.get("foreign-name").unwrap();
+    let mtime = fs::metadata(entry.path()).unwrap().modified().unwrap();
+    assert_eq!(entry.received_date().unwrap(), mtime);
+
+    fs::write(mdir.cur().join("not-an-epoch.foo.host"), b"data").unwrap();
+    let entry = mdir.get("not-an-epoch.foo.host").unwrap();
+    let mtime = fs::metadata(entry.path()).unwrap().modified().unwrap();
+    assert_eq!(entry.received_date().unwrap(), mtime);
+}
+
+#[test]
+fn read_maildir_entries_sorted_by_date() {
+    let mdirs = Maildirs::new(tempdir().unwrap().into_path());
+    let mdir = mdirs.create("mdir").unwrap();
+    fs::write(mdir.cur().join("1700000002.b.host:2,S"), b"data").unwrap();
+    fs::write(mdir.new().join("1700000000.c.host"), b"data").unwrap();
+    fs::write(mdir.cur().join("1700000001.a.host:2,"), b"data").unwrap();
+
+    let ids: Vec<_> = mdir
+        .read_sorted_by_date()
+        .unwrap()
+        .iter()
+        .map(|entry| entry.id().unwrap().to_owned())
+        .collect();
+    let expected_ids = [
+        "1700000000.c.host",
+        "1700000001.a.host",
+        "1700000002.b.host",
+    ];
+    assert_eq!(ids, expected_ids);
+
+    let ids: Vec<_> = mdir
+        .read_sorted_by_date_desc()
+        .unwrap()
+        .iter()
+        .map(|entry| entry.id().unwrap().to_owned())
+        .collect();
+    let expected_ids = [
+        "1700000002.b.host",
+        "1700000001.a.host",
+        "1700000000.c.host",
+    ];
+    assert_eq!(ids, expected_ids);
+}
+
+#[test]
+fn check_maildir_entry_attachments() {
+    let mdirs = Maildirs::new(tempdir().unwrap().into_path());
+    let mdir = mdirs.create("mdir").unwrap();
+
+    let plain = concat!(
+        "From: alice@localhost\r\n",
+        "Content-Type: text/plain\r\n",
+        "\r\n",
+        "Content-Disposition: attachment\r\n",
+    );
+    let entry = mdir.write_new(plain).unwrap();
+    assert!(!entry.has_attachments().unwrap());
+
+    let alternative = concat!(
+        "From: alice@localhost\r\n",
+        "Content-Type: multipart/alternative; boundary=\"b\"\r\n",
+        "\r\n",
+        "--b\r\n",
+        "Content-Type: text/plain\r\n",
+        "\r\n",
+        "Hello\r\n",
+        "--b\r\n",
+        "Content-Type: text/html\r\n",
+        "\r\n",
+        "<p>Hello</p>\r\n",
+        "--b--\r\n",
+    );
+    let entry = mdir.write_new(alternative).unwrap();
+    assert!(!entry.has_attachments().unwrap());
+
+    let mixed = concat!(
+        "From: alice@localhost\r\n",
+        "Content-Type: multipart/mixed;\r\n",
+        "\tboundary=\"b\"\r\n",
+        "\r\n",
+        "--b\r\n",
+        "Content-Type: text/plain\r\n",
+        "\r\n",
+        "Hello\r\n",
+        "--b\r\n",
+        "Content-Type: application/pdf\r\n",
+        "Content-Disposition:\r\n",
+        " attachment; filename=\"doc.pdf\"\r\n",
+        "\r\n",
+        "JVBERi0K\r\n",
+        "--b--\r\n",
+    );
+    let entry = mdir.write_new(mixed).unwrap();
+    assert!(entry.has_attachments().unwrap());
+}
+
+#[test]
+fn normalize_maildir_entry_separators() {
+    let root = tempdir().unwrap().into_path();
+    let mdir = Maildir::from(root).with_info_separator(":").unwrap();
+    mdir.create_all().unwrap();
+    mdir.write_cur(b"data", [Flag::Seen]).unwrap();
+    fs::write(mdir.cur().join("1700000000.a.host;2,FS"), b"data").unwrap();
+
+    let entry = mdir.get("1700000000.a.host;2,FS").unwrap();
+    assert!(entry.flags().unwrap().is_empty());
+
+    assert_eq!(mdir.normalize_separators().unwrap(), 1);
+    assert_eq!(mdir.normalize_separators().unwrap(), 0);
+
+    let entry = mdir.get("1700000000.a.host").unwrap();
+    assert_eq!(entry.file_name().unwrap(), "1700000000.a.host:2,FS");
+    let expected_flags = HashSet::from_iter([Flag::Flagged, Flag::Seen]);
+    assert_eq!(entry.flags().unwrap(), expected_flags);
+    assert_eq!(mdir.count().unwrap(), 2);
+}
+
+#[test]
+fn write_maildir_entry_with_id() {
+    let mdirs = Maildirs::new(tempdir().unwrap().into_path());
+    let mdir = mdirs.create("mdir").unwrap();
+
+    let entry = mdir.write_new_with_id(b"data", "my-id").unwrap();
+    assert_eq!(entry.id().unwrap(), "my-id");
+    assert_eq!(entry.path().parent(), Some(mdir.new()));
+
+    let entry = mdir
+        .write_cur_with_id(b"data", [Flag::Seen], "my-other-id")
+        .unwrap();
+    assert_eq!(entry.id().unwrap(), "my-other-id");
+    assert_eq!(entry.flags().unwrap(), HashSet::from_iter([Flag::Seen]));
+
+    // redeliveries do not overwrite existing entries
+    let res = mdir.write_cur_with_id(b"other data", None, "my-id");
+    assert!(matches!(res, Err(Error::AlreadyExistsError(_))));
+    let res = mdir.write_new_with_id(b"other data", "my-other-id");
+    assert!(matches!(res, Err(Error::AlreadyExistsError(_))));
+    assert_eq!(mdir.get("my-id").unwrap().read().unwrap(), b"data");
+    assert_eq!(mdir.count().unwrap(), 2);
+
+    for id in ["", ".hidden", "a/b", "a:b"] {
+        let res = mdir.write_new_with_id(b"data", id);
+        assert!(matches!(res, Err(Error::InvalidIdError(_))));
+    }
+
+    assert_eq!(mdir.count().unwrap(), 2);
+    assert_eq!(fs::read_dir(mdir.tmp()).unwrap().count(), 0);
+}
+
+#[test]
+fn write_maildir_entry_with_id_concurrently() {
+    let mdirs = Maildirs::new(tempdir().unwrap().into_path());
+    let mdir = mdirs.create("mdir").unwrap();
+    let barrier = Barrier::new(8);
+
+    let results: Vec<_> = thread::scope(|scope| {
+        let handles: Vec<_> = (0..8u8)
+            .map(|i| {
+                let (mdir, barrier) = (&mdir, &barrier);
+                scope.spawn(move || {
+                    barrier.wait();
+                    mdir.write_new_with_id([i], "my-id")
+                })
+            })
+            .collect();
+
+        handles.into_iter().map(|h| h.join().unwrap()).collect()
+    });
+
+    // exactly one delivery wins, the others never overwrite it
+    let (written, taken): (Vec<_>, Vec<_>) = results.into_iter().partition(Result::is_ok);
+    assert_eq!(written.len(), 1);
+    assert!(taken
+        .iter()
+        .all(|res| matches!(res, Err(Error::AlreadyExistsError(_)))));
+
+    let entry = written.into_iter().next().unwrap().unwrap();
+    assert_eq!(
+        mdir.get("my-id").unwrap().read().unwrap(),
+        entry.read().unwrap()
+    );
+    assert_eq!(mdir.count().unwrap(), 1);
+    assert_eq!(fs::read_dir(mdir.tmp()).unwrap().count(), 0);
+}
+
+#[test]
+fn read_maildir_entry_headers() {
+    let mdirs = Maildirs::new(tempdir().unwrap().into_path());
+    let mdir = mdirs.create("mdir").unwrap();
+    let contents = concat!(
+        "Received: from a\r\n",
+        "Subject: =?utf-8?q?Hello?=\r\n",
+        "  world\r\n",
+        "Received: from b\r\n",
+        "X-Empty:\r\n",
+        "\r\n",
+        "Body: not a header\r\n",
+    );
+    let entry = mdir.write_new(contents).unwrap();
+
+    let expected_headers = vec![
+        ("Received".into(), "from a".into()),
+        ("Subject".into(), "=?utf-8?q?Hello?=  world".into()),
+        ("Received".into(), "from b".into()),
+        ("X-Empty".into(), "".into()),
+    ];
+    assert_eq!(entry.headers().unwrap(), expected_headers);
+
+    let subject = entry.header("subject").unwrap();
+    assert_eq!(subject.as_deref(), Some("=?utf-8?q?Hello?=  world"));
+    let received = entry.header("RECEIVED").unwrap();
+    assert_eq!(received.as_deref(), Some("from a"));
+    assert_eq!(entry.header("Body").unwrap(), None);
+
+    // LF line endings are handled as well
+    let entry = mdir.write_new("Subject: a\n\tb\n\nbody\n").unwrap();
+    let subject = entry.header("Subject").unwrap();
+    assert_eq!(subject.as_deref(), Some("a\tb"));
+}
+
+#[test]
+fn read_maildir_entry_body() {
+    let mdirs = Maildirs::new(tempdir().unwrap().into_path());
+    let mdir = mdirs.create("mdir").unwrap();
+
+    let entry = mdir
+        .write_cur(b"Subject: crlf\r\n\r\nbody\r\n\r\nend", None)
+        .unwrap();
+    assert_eq!(entry.read_body().unwrap(), b"body\r\n\r\nend");
+    assert_eq!(
+        entry.read_split().unwrap(),
+        (
+            b"Subject: crlf\r\n\r\n".to_vec(),
+            b"body\r\n\r\nend".to_vec()
+        )
+    );
+
+    let entry = mdir.write_cur(b"Subject: lf\n\nbody\n", None).unwrap();
+    assert_eq!(entry.read_body().unwrap(), b"body\n");
+    assert_eq!(entry.read_split().unwrap().0, entry.read_headers().unwrap());
+
+    let entry = mdir.write_cur(b"Subject: no body\n", None).unwrap();
+    assert_eq!(entry.read_body().unwrap(), b"");
+    assert_eq!(
+        entry.read_split().unwrap(),
+        (b"Subject: no body\n".to_vec(), Vec::new())
+    );
+}
+
+#[test]
+fn get_maildir_entry_content_type() {
+    let mdirs = Maildirs::new(tempdir().unwrap().into_path());
+    let mdir = mdirs.create("mdir").unwrap();
+
+    let entry = mdir
+        .write_new("Content-Type: text/plain; charset=utf-8\r\n\r\nbody\r\n")
+        .unwrap();
+    assert_eq!(entry.content_type().unwrap().as_deref(), Some("text/plain"));
+
+    let entry = mdir
+        .write_new("content-type: Text/HTML\n\n<p>body</p>\n")
+        .unwrap();
+    assert_eq!(entry.content_type().unwrap().as_deref(), Some("text/html"));
+
+    let contents = concat!(
+        "Content-Type: multipart/mixed;\r\n",
+        "\tboundary=\"boundary\"\r\n",
+        "\r\n",
+        "--boundary\r\n",
+        "Content-Type: text/plain\r\n",
+    );
+    let entry = mdir.write_new(contents).unwrap();
+    assert_eq!(
+        entry.content_type().unwrap().as_deref(),
+        Some("multipart/mixed")
+    );
+
+    // the media type itself can be folded
+    let entry = mdir
+        .write_new("Content-Type:\r\n  TEXT/plain;\r\n charset=us-ascii\r\n\r\nbody\r\n")
+        .unwrap();
+    assert_eq!(entry.content_type().unwrap().as_deref(), Some("text/plain"));
+
+    // the content type of the body is not a header
+    let entry = mdir
+        .write_new("Subject: a\r\n\r\nContent-Type: text/html\r\n")
+        .unwrap();
+    assert_eq!(entry.content_type().unwrap(), None);
+}
+
+#[test]
+fn find_maildir_entry_by_id() {
+    let mdirs = Maildirs::new(tempdir().unwrap().into_path());
+    let mdir = mdirs.create("mdir").unwrap().with_size_field(true);
+
+    mdir.write_new_with_id(b"new", "id").unwrap();
+    mdir.write_cur_with_id(b"cur", [Flag::Seen], "id-2")
+        .unwrap();
+    mdir.write_cur_with_id(b"prefix", None, "i").unwrap();
+
+    assert_eq!(mdir.get("id").unwrap().read().unwrap(), b"new");
+    assert_eq!(mdir.get("id-2").unwrap().read().unwrap(), b"cur");
+    assert_eq!(mdir.get("i").unwrap().read().unwrap(), b"prefix");
+    assert!(mdir.find("id-").unwrap().is_none());
+    assert!(mdir.find("id-20").unwrap().is_none());
+}
+
+#[test]
+fn check_maildir_entry_existence_by_id() {
+    let mdirs = Maildirs::new(tempdir().unwrap().into_path());
+    let mdir = mdirs.create("mdir").unwrap().with_size_field(true);
+
+    mdir.write_new_with_id(b"new", "id").unwrap();
+    mdir.write_cur_with_id(b"cur", [Flag::Seen], "id-2")
+        .unwrap();
+    fs::create_dir(mdir.cur().join("dir:2,")).unwrap();
+    fs::write(mdir.cur().join("a:b:2,S"), b"").unwrap();
+
+    assert!(mdir.contains("id").unwrap());
+    assert!(mdir.contains("id-2").unwrap());
+    assert!(!mdir.contains("i").unwrap());
+    assert!(!mdir.contains("id-20").unwrap());
+    assert!(!mdir.contains("dir").unwrap());
+    assert!(!mdir.contains("a").unwrap());
+    assert!(mdir.contains("a:b").unwrap());
+    assert!(!mdir.contains("missing").unwrap());
+}
+
+#[test]
+fn find_maildir_entry_by_escaped_id() {
+    let mdirs = Maildirs::new(tempdir().unwrap().into_path());
+    let mdir = mdirs.create("mdir").unwrap();
+
+    // a hostname like "host:name" is stored escaped
+    let id = r"1234.M1P1V1I1.host\072name";
+    fs::write(mdir.cur().join(format!("{id},S=4:2,S")), "data").unwrap();
+
+    let entry = mdir.find(id).unwrap().unwrap();
+    assert_eq!(entry.id().unwrap(), id);
+    assert_eq!(entry.flags().unwrap(), HashSet::from_iter([Flag::Seen]));
+}
+
+#[test]
+fn check_maildir_entry_flags() {
+    let mdirs = Maildirs::new(tempdir().unwrap().into_path());
+    let mdir = mdirs.create("mdir").unwrap();
+
+    let entry = mdir.write_cur(b"", []).unwrap();
+    assert!(!entry.is_draft());
+    assert!(!entry.is_flagged());
+    assert!(!entry.is_forwarded());
+    assert!(!entry.is_replied());
+    assert!(!entry.is_seen());
+    assert!(!entry.is_trashed());
+
+    let entry = mdir.write_cur(b"", [Flag::Passed]).unwrap();
+    assert!(entry.is_forwarded());
+    assert!(!entry.is_replied());
+
+    let entry = mdir.write_cur(b"", [Flag::Replied]).unwrap();
+    assert!(entry.is_replied());
+    assert!(!entry.is_forwarded());
+
+    let entry = mdir.write_cur(b"", [Flag::Draft]).unwrap();
+    assert!(entry.is_draft());
+
+    let entry = mdir.write_cur(b"", [Flag::Flagged]).unwrap();
+    assert!(entry.is_flagged());
+
+    let entry = mdir.write_cur(b"", [Flag::Seen]).unwrap();
+    assert!(entry.is_seen());
+
+    let entry = mdir.write_cur(b"", [Flag::Trashed]).unwrap();
+    assert!(entry.is_trashed());
+}
+
+#[test]
+fn stream_maildir_entry() {
+    let mdirs = Maildirs::new(tempdir().unwrap().into_path());
+    let mdir = mdirs.create("mdir").unwrap();
+    let entry = mdir.write_new(b"data").unwrap();
+
+    let mut contents = Vec::new();
+    assert_eq!(entry.read_to_writer(&mut contents).unwrap(), 4);
+    assert_eq!(contents, b"data");
+
+    let mut file = entry.open().unwrap();
+    file.seek(SeekFrom::Start(2)).unwrap();
+
+    let mut contents = String::new();
+    file.read_to_string(&mut contents).unwrap();
+    assert_eq!(contents, "ta");
+
+    entry.remove().unwrap();
+    assert!(entry.read_to_writer(&mut Vec::new()).is_err());
+}
+
+#[test]
+fn parse_maildir_entry_file_name_once() {
+    let mdirs = Maildirs::new(tempdir().unwrap().into_path());
+    let mdir = mdirs.create("mdir").unwrap();
+    fs::write(mdir.cur().join("1234.a,b,S=4,W=5:2,Sa"), "data").unwrap();
+
+    let mut entry = mdir.read().unwrap().next().unwrap().unwrap();
+    assert_eq!(entry.id().unwrap(), "1234.a,b");
+    assert_eq!(entry.size_hint().unwrap(), Some(4));
+    assert_eq!(entry.flags().unwrap(), HashSet::from_iter([Flag::Seen]));
+    assert_eq!(entry.flags_raw().unwrap(), "Sa");
+
+    // parts are parsed again when the path changes
+    entry.insert_flag(Flag::Flagged).unwrap();
+    assert_eq!(entry.id().unwrap(), "1234.a,b");
+    assert_eq!(entry.size_hint().unwrap(), Some(4));
+    assert_eq!(entry.flags_raw().unwrap(), "FSa");
+
+    let entry = entry.with_info_separator(";").unwrap();
+    assert_eq!(entry.id().unwrap(), "1234.a,b");
+    assert_eq!(entry.flags_raw().unwrap(), "");
+    assert!(entry.flags().unwrap().is_empty());
+}
+
+#[test]
+fn check_maildir_entry_location() {
+    let mdirs = Maildirs::new(tempdir().unwrap().into_path());
+    let mdir = mdirs.create("mdir").unwrap();
+
+    let entry = mdir.write_new(b"").unwrap();
+    assert!(entry.is_new());
+    assert!(!entry.is_cur());
+
+    let entry = mdir.write_cur(b"", None).unwrap();
+    assert!(entry.is_cur());
+    assert!(!entry.is_new());
+
+    let entry = MaildirEntry::new(mdir.tmp().join("1234.a"));
+    assert!(!entry.is_new());
+    assert!(!entry.is_cur());
+
+    let entry = MaildirEntry::new("1234.a");
+    assert!(!entry.is_new());
+    assert!(!entry.is_cur());
+}
+
+#[test]
+fn move_maildir_entry_to_cur() {
+    let mdirs = Maildirs::new(tempdir().unwrap().into_path());
+    let mdir = mdirs.create("mdir").unwrap();
+
+    let mut entry = mdir.write_new(b"data").unwrap();
+    let id = entry.id().unwrap().to_owned();
+
+    entry.move_to_cur([Flag::Seen]).unwrap();
+    assert_eq!(entry.path().parent(), Some(mdir.cur()));
+    assert_eq!(entry.id().unwrap(), id);
+    assert_eq!(entry.flags().unwrap(), HashSet::from_iter([Flag::Seen]));
+    assert_eq!(mdir.count_new().unwrap(), 0);
+    assert_eq!(mdir.count_cur().unwrap(), 1);
+
+    // entries already in cur are left untouched
+    let path = entry.path().to_owned();
+    entry.move_to_cur([Flag::Flagged]).unwrap();
+    assert_eq!(entry.path(), path);
+    assert_eq!(entry.flags().unwrap(), HashSet::from_iter([Flag::Seen]));
+}
+
+#[cfg(unix)]
+#[test]
+fn read_maildir_entries_leniently() {
+    use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+    let mdirs = Maildirs::new(tempdir().unwrap().into_path());
+    let mdir = mdirs.create("mdir").unwrap();
+    mdir.write_new(b"data").unwrap();
+    mdir.write_cur(b"data", [Flag::Seen]).unwrap();
+
+    let non_utf8 = mdir.cur().join(OsStr::from_bytes(b"invalid\xff:2,S"));
+    let empty_id = mdir.cur().join(":2,S");
+    let invalid_info = mdir.cur().join("id:1,experimental");
+    for path in [&non_utf8, &empty_id, &invalid_info] {
+        fs::write(path, b"data").unwrap();
+    }
+
+    let (entries, mut failures) = mdir.read_lenient().unwrap();
+    assert_eq!(entries.len(), 2);
+    assert_eq!(failures.len(), 3);
+
+    failures.sort_by(|(a, _), (b, _)| a.cmp(b));
+    assert_eq!(failures[0].0, empty_id);
+    assert!(matches!(failures[0].1, Error::InvalidIdError(_)));
+    assert_eq!(failures[1].0, invalid_info);
+    assert!(matches!(failures[1].1, Error::InvalidInfoError(_)));
+    assert_eq!(failures[2].0, non_utf8);
+    assert!(matches!(
+        failures[2].1,
+        Error::GetInvalidMaildirEntryFileNameError(..)
+    ));
+}
+
+#[test]
+fn store_maildir_entry_and_get_id() {
+    let root = tempdir().unwrap().into_path();
+    let mdir = MaildirBuilder::new().with_size_field(true).build(root);
+    mdir.create_all().unwrap();
+
+    let (entry, id) = mdir.store_and_get_id(b"data", None, true).unwrap();
+    assert_eq!(entry.path().parent(), Some(mdir.new()));
+    assert_eq!(entry.id().unwrap(), id);
+
+    let (entry, id) = mdir.store_and_get_id(b"data", [Flag::Seen], false).unwrap();
+    assert_eq!(entry.path().parent(), Some(mdir.cur()));
+    assert_eq!(entry.id().unwrap(), id);
+    assert_eq!(mdir.get(&id).unwrap(), entry);
+}
+
+#[test]
+fn replace_maildir_entry_contents() {
+    let mdirs = Maildirs::new(tempdir().unwrap().into_path());
+    let mdir = mdirs.create("mdir").unwrap();
+    let mut entry = mdir
+        .write_cur(b"Subject: old\r\n\r\nbody", [Flag::Seen])
+        .unwrap();
+    let id = entry.id().unwrap().to_owned();
+    let path = entry.path().to_owned();
+
+    entry
+        .replace_contents(b"DKIM-Signature: x\r\nSubject: old\r\n\r\nbody")
+        .unwrap();
+
+    assert_eq!(entry.path(), path);
+    assert_eq!(entry.id().unwrap(), id);
+    assert_eq!(entry.flags().unwrap(), HashSet::from_iter([Flag::Seen]));
+    assert_eq!(
+        mdir.get(&id).unwrap().read().unwrap(),
+        b"DKIM-Signature: x\r\nSubject: old\r\n\r\nbody"
+    );
+    assert_eq!(fs::read_dir(mdir.tmp()).unwrap().count(), 0);
+    assert_eq!(mdir.count().unwrap(), 1);
+
+    // size fields follow the new contents
+    let mdir = mdirs.create("sized").unwrap();
+    let path = mdir.cur().join("1700000000.a.localhost,S=4,W=5,E=a@b:2,S");
+    fs::write(&path, b"a\r\nb").unwrap();
+    let mut entry = mdir.get("1700000000.a.localhost").unwrap();
+    entry.replace_contents(b"a\nb\r\nc\n").unwrap();
+
+    assert_eq!(
+        entry.file_name().unwrap(),
+        "1700000000.a.localhost,S=7,W=9,E=a@b:2,S"
+    );
+    assert_eq!(entry.id().unwrap(), "1700000000.a.localhost");
+    assert_eq!(entry.size_hint().unwrap(), Some(7));
+    assert!(!path.exists());
+    assert_eq!(mdir.size_on_disk().unwrap(), 7);
+    assert_eq!(mdir.count().unwrap(), 1);
+
+    // the info separator of the entry is kept
+    let mdir = mdirs
+        .create("semicolon")
+        .unwrap()
+        .with_info_separator(";")
+        .unwrap();
+    fs::write(mdir.cur().join("1700000000.b.localhost,S=1;2,S"), b"a").unwrap();
+    let mut entry = mdir.get("1700000000.b.localhost").unwrap();
+    entry.replace_contents(b"ab").unwrap();
+
+    assert_eq!(entry.file_name().unwrap(), "1700000000.b.localhost,S=2;2,S");
+    assert_eq!(entry.flags().unwrap(), HashSet::from_iter([Flag::Seen]));
+    assert_eq!(mdir.count().unwrap(), 1);
+}
+
+#[test]
+fn sort_maildir_entries() {
+    let mdirs = Maildirs::new(tempdir().unwrap().into_path());
+    let mdir = mdirs.create("mdir").unwrap();
+
+    for name in [
+        "1700000002.b.localhost:2,",
+        "1700000001.b.localhost:2,",
+        "1700000001.a.localhost:2,S",
+        "not-a-date:2,",
+    ] {
+        fs::write(mdir.cur().join(name), b"").unwrap();
+    }
+
+    let mut entries: Vec<MaildirEntry> = mdir.read().unwrap().map(Result::unwrap).collect();
+    entries.sort();
+
+    let ids: Vec<&str> = entries.iter().map(|entry| entry.id().unwrap()).collect();
+    assert_eq!(
+        ids,
+        vec![
+            "not-a-date",
+            "1700000001.a.localhost",
+            "1700000001.b.localhost",
+            "1700000002.b.localhost",
+        ]
+    );
+}
+
+#[test]
+fn parse_unique_names() {
+    let mdirs = Maildirs::new(tempdir().unwrap().into_path());
+    let mdir = mdirs.create("mdir").unwrap();
+
+    let entry = mdir.write_new(b"data").unwrap();
+    let name = entry.unique_name().unwrap();
+    let (secs, _) = entry.id().unwrap().split_once('.').unwrap();
+    assert_eq!(name.secs.to_string(), secs);
+    assert!(name.nanos.is_some());
+    assert!(name.counter.is_some());
+    assert_eq!(name.pid, Some(std::process::id()));
+    assert!(name.dev.is_some());
+    assert!(name.ino.is_some());
+    assert!(entry.id().unwrap().ends_with(&format!(".{}", name.host)));
+
+    assert_eq!(
+        UniqueName::parse("1700000000.#1fM123P42V7I8.mail.example.org"),
+        Some(UniqueName {
+            secs: 1700000000,
+            nanos: Some(123),
+            counter: Some(0x1f),
+            pid: Some(42),
+            dev: Some(7),
+            ino: Some(8),
+            host: "mail.example.org".into(),
+        })
+    );
+
+    // names of other tools following the same pattern
+    assert_eq!(
+        UniqueName::parse("1700000000.M123P42.localhost"),
+        Some(UniqueName {
+            secs: 1700000000,
+            nanos: Some(123),
+            counter: None,
+            pid: Some(42),
+            dev: None,
+            ino: None,
+            host: "localhost".into(),
+        })
+    );
+
+    // names of other tools not following the pattern
+    for name in [
+        "1700000000.12345_6.localhost",
+        "1700000000.R42.localhost",
+        "1700000000.P42P43.localhost",
+        "1700000000.M.localhost",
+        "1700000000.P42",
+        "1700000000..localhost",
+        "+1700000000.P42.localhost",
+        "not-a-date.P42.localhost",
+        "message",
+    ] {
+        assert_eq!(UniqueName::parse(name), None, "{name}");
+    }
+
+    fs::write(mdir.cur().join("1700000000.12345_6.localhost:2,S"), b"").unwrap();
+    let entry = mdir.get("1700000000.12345_6.localhost").unwrap();
+    assert_eq!(entry.unique_name(), None);
+}
+
+#[test]
+fn normalize_maildir_entry() {
+    let mdirs = Maildirs::new(tempdir().unwrap().into_path());
+    let mdir = mdirs.create("mdir").unwrap();
+
+    let path = mdir.cur().join("1700000000.a.localhost,S=4:2,TbSaFS");
+    fs::write(&path, b"data").unwrap();
+    let mut entry = mdir.get("1700000000.a.localhost").unwrap();
+    entry.normalize().unwrap();
+
+    let expected_path = mdir.cur().join("1700000000.a.localhost,S=4:2,FSTab");
+    assert_eq!(entry.path(), expected_path);
+    assert!(!path.exists());
+    assert_eq!(fs::read(entry.path()).unwrap(), b"data");
+
+    // canonical file names are left untouched
+    entry.normalize().unwrap();
+    assert_eq!(entry.path(), expected_path);
+
+    // unknown uppercase flags are sorted along with standard ones
+    fs::write(mdir.cur().join("1700000000.d.localhost:2,ZSA"), b"").unwrap();
+    let mut entry = mdir.get("1700000000.d.localhost").unwrap();
+    entry.normalize().unwrap();
+    assert_eq!(entry.file_name().unwrap(), "1700000000.d.localhost:2,ASZ");
+
+    entry.insert_flag(Flag::Draft).unwrap();
+    assert_eq!(entry.flags_raw().unwrap(), "ADSZ");
+
+    // the other info separator of the specification is replaced
+    fs::write(mdir.cur().join("1700000000.b.localhost;2,SR"), b"").unwrap();
+    let mut entry = MaildirEntry::new(mdir.cur().join("1700000000.b.localhost;2,SR"));
+    entry.normalize().unwrap();
+    assert_eq!(entry.file_name().unwrap(), "1700000000.b.localhost:2,RS");
+    assert_eq!(entry.id().unwrap(), "1700000000.b.localhost");
+
+    // entries without info section are left untouched
+    let mut entry = mdir.write_new(b"data").unwrap();
+    let path = entry.path().to_owned();
+    entry.normalize().unwrap();
+    assert_eq!(entry.path(), path);
+
+    fs::write(mdir.cur().join("1700000000.c.localhost:1,data"), b"").unwrap();
+    let mut entry = mdir.get("1700000000.c.localhost").unwrap();
+    let err = entry.normalize().unwrap_err();
+    assert!(matches!(err, Error::InvalidInfoError(_)));
+}
+
+#[test]
+fn read_maildir_entry_info() {
+    let mdirs = Maildirs::new(tempdir().unwrap().into_path());
+    let mdir = mdirs.create("mdir").unwrap();
+
+    let entry = mdir.write_cur(b"data", [Flag::Seen]).unwrap();
+    assert_eq!(entry.info().unwrap(), Some(('2', "S")));
+
+    let entry = mdir.write_new(b"data").unwrap();
+    assert_eq!(entry.info().unwrap(), None);
+    assert!(entry.flags().unwrap().is_empty());
+
+    // experimental info is not read as flags
+    fs::write(mdir.cur().join("1700000000.a.localhost:1,FS=x"), b"").unwrap();
+    let entry = mdir.get("1700000000.a.localhost").unwrap();
+    assert_eq!(entry.info().unwrap(), Some(('1', "FS=x")));
+    assert!(entry.flags().unwrap().is_empty());
+    assert_eq!(entry.flags_raw().unwrap(), "");
+
+    fs::write(mdir.cur().join("1700000000.b.localhost:S"), b"").unwrap();
+    let entry = mdir.get("1700000000.b.localhost").unwrap();
+    assert!(matches!(entry.info(), Err(Error::InvalidInfoError(_))));
+    assert!(matches!(entry.flags(), Err(Error::InvalidInfoError(_))));
+}
+
+#[test]
+fn write_maildir_entry_with_date() {
+    let mdirs = Maildirs::new(tempdir().unwrap().into_path());
+    let mdir = mdirs.create("mdir").unwrap();
+
+    let older = UNIX_EPOCH + Duration::new(1_000_000_000, 42);
+    let newer = UNIX_EPOCH + Duration::from_secs(1_500_000_000);
+
+    let b = mdir.write_cur_with_date(b"b", [Flag::Seen], newer).unwrap();
+    let a = mdir.write_cur_with_date(b"a", None, older).unwrap();
+    let c = mdir.write_cur_with_date(b"c", None, older).unwrap();
+
+    assert!(a.id().unwrap().starts_with("1000000000."));
+    assert!(b.id().unwrap().starts_with("1500000000."));
+    assert_eq!(a.unique_name().unwrap().nanos, Some(42));
+    assert_ne!(a.id().unwrap(), c.id().unwrap());
+    assert_eq!(
+        a.received_date().unwrap(),
+        UNIX_EPOCH + Duration::from_secs(1_000_000_000)
+    );
+    assert_eq!(b.flags().unwrap(), HashSet::from_iter([Flag::Seen]));
+
+    let entries = mdir.read_sorted_by_date().unwrap();
+    assert_eq!(entries.last().unwrap().read().unwrap(), b"b");
+}
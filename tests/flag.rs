@@ -0,0 +1,110 @@
+use std::collections::HashSet;
+
+use maildirs::{flags_from_bits, flags_from_str, flags_to_bits, flags_to_string, Error, Flag};
+
+#[test]
+fn flag_from_char() {
+    assert_eq!(Flag::try_from('S').unwrap(), Flag::Seen);
+
+    let err = Flag::try_from('s').unwrap_err();
+    assert!(matches!(err, Error::LowercaseFlagError('s', 'S')));
+    assert!(err.to_string().contains("did you mean S?"));
+
+    // other lowercase letters are keywords, not typos
+    let err = Flag::try_from('a').unwrap_err();
+    assert!(matches!(err, Error::InvalidFlagError('a')));
+    assert!(!err.to_string().contains("did you mean"));
+}
+
+#[test]
+fn all_flags() {
+    let flags = Flag::all();
+    assert_eq!(HashSet::from(flags).len(), 6);
+
+    for flag in flags {
+        let c = flag.as_ref().chars().next().unwrap();
+        assert_eq!(Flag::try_from(c).unwrap(), flag);
+    }
+
+    // the canonical order matches the ASCII order of flag letters
+    let mut sorted = flags;
+    sorted.sort_by_key(|flag| flag.as_ref().to_owned());
+    assert_eq!(sorted, flags);
+    assert!(Flag::Draft < Flag::Trashed);
+}
+
+#[test]
+fn flag_description() {
+    assert_eq!(Flag::Passed.description(), "Forwarded");
+    assert_eq!(Flag::Replied.description(), "Replied");
+
+    let descriptions: HashSet<_> = Flag::all().iter().map(Flag::description).collect();
+    assert_eq!(descriptions.len(), 6);
+}
+
+#[test]
+fn flag_to_and_from_str() {
+    for flag in Flag::all() {
+        assert_eq!(flag.to_string().parse::<Flag>().unwrap(), flag);
+    }
+
+    assert_eq!(Flag::Seen.to_string(), "S");
+    assert!(matches!("SF".parse::<Flag>(), Err(Error::InvalidFlagsError(s)) if s == "SF"));
+    assert!("".parse::<Flag>().is_err());
+    assert!("x".parse::<Flag>().is_err());
+}
+
+#[test]
+fn flags_to_and_from_string() {
+    let flags = HashSet::from_iter([Flag::Seen, Flag::Replied, Flag::Flagged]);
+    assert_eq!(flags_to_string(&flags), "FRS");
+    assert_eq!(flags_to_string(&HashSet::new()), "");
+
+    assert_eq!(flags_from_str("FRS"), flags);
+    assert_eq!(flags_from_str("SabRF"), flags);
+    assert_eq!(flags_from_str(""), HashSet::new());
+}
+
+#[test]
+fn flag_to_and_from_imap() {
+    let mappings = [
+        (Flag::Draft, "\\Draft"),
+        (Flag::Flagged, "\\Flagged"),
+        (Flag::Passed, "$Forwarded"),
+        (Flag::Replied, "\\Answered"),
+        (Flag::Seen, "\\Seen"),
+        (Flag::Trashed, "\\Deleted"),
+    ];
+
+    for (flag, name) in mappings {
+        assert_eq!(flag.to_imap(), name);
+        assert_eq!(Flag::from_imap(name), Some(flag));
+        assert_eq!(Flag::from_imap(&name.to_uppercase()), Some(flag));
+        assert_eq!(Flag::from_imap(&name.to_lowercase()), Some(flag));
+    }
+
+    // passed is not an IMAP system flag
+    assert_eq!(Flag::from_imap("\\Forwarded"), None);
+    assert_eq!(Flag::from_imap("$forwarded"), Some(Flag::Passed));
+
+    assert_eq!(Flag::from_imap("\\Recent"), None);
+    assert_eq!(Flag::from_imap("Seen"), None);
+}
+
+#[test]
+fn flags_to_and_from_bits() {
+    assert_eq!(Flag::Draft.bit(), 0x01);
+    assert_eq!(Flag::Trashed.bit(), 0x20);
+
+    for bits in 0..64u8 {
+        let flags = flags_from_bits(bits);
+        assert_eq!(flags.len(), bits.count_ones() as usize);
+        assert_eq!(flags_to_bits(&flags), bits);
+    }
+
+    assert_eq!(flags_from_bits(0xff), HashSet::from_iter(Flag::all()));
+    assert_eq!(
+        flags_to_bits(&HashSet::from_iter([Flag::Seen, Flag::Replied])),
+        0x18
+    );
+}
@@ -0,0 +1,50 @@
+#![cfg(feature = "hash")]
+
+use maildirs::Maildirs;
+use tempfile::tempdir;
+
+#[test]
+fn hash_contents() {
+    let mdirs = Maildirs::new(tempdir().unwrap().into_path());
+    let mdir = mdirs.create("mdir").unwrap();
+
+    let a = mdir.write_new(b"data").unwrap();
+    let b = mdir.write_cur(b"data", None).unwrap();
+    let c = mdir.write_cur(b"other data", None).unwrap();
+
+    assert_eq!(a.content_hash().unwrap(), b.content_hash().unwrap());
+    assert_ne!(a.content_hash().unwrap(), c.content_hash().unwrap());
+
+    // SHA-256 of "data"
+    assert_eq!(a.content_hash().unwrap()[..4], [0x3a, 0x6e, 0xb0, 0x79]);
+
+    // large entries are hashed in chunks
+    let large = vec![b'a'; 100_000];
+    let d = mdir.write_new(&large).unwrap();
+    let e = mdir.write_new(&large).unwrap();
+    assert_eq!(d.content_hash().unwrap(), e.content_hash().unwrap());
+}
+
+#[test]
+fn find_duplicates() {
+    let mdirs = Maildirs::new(tempdir().unwrap().into_path());
+    let mdir = mdirs.create("mdir").unwrap();
+    assert!(mdir.find_duplicates().unwrap().is_empty());
+
+    let a = mdir.write_new(b"a").unwrap();
+    let b = mdir.write_new(b"b").unwrap();
+    mdir.write_new(b"c").unwrap();
+    let a2 = mdir.write_cur(b"a", None).unwrap();
+    let b2 = mdir.write_cur(b"b", None).unwrap();
+    let b3 = mdir.write_cur(b"b", None).unwrap();
+
+    let mut expected = vec![vec![a, a2], vec![b, b2, b3]];
+
+    for group in &mut expected {
+        group.sort_by(|a, b| a.path().cmp(b.path()));
+    }
+
+    expected.sort_by(|a, b| a[0].path().cmp(b[0].path()));
+
+    assert_eq!(mdir.find_duplicates().unwrap(), expected);
+}
@@ -0,0 +1,114 @@
+use std::{collections::HashSet, fs, sync::Barrier, thread};
+
+use maildirs::{Flag, Maildirs};
+use tempfile::tempdir;
+
+#[test]
+fn read_keywords() {
+    let mdirs = Maildirs::new(tempdir().unwrap().into_path());
+    let mdir = mdirs.create("mdir").unwrap();
+
+    assert_eq!(mdir.keywords().unwrap(), vec![]);
+
+    fs::write(mdir.path().join("dovecot-keywords"), "0 $Label1\n1 Junk\n").unwrap();
+
+    assert_eq!(
+        mdir.keywords().unwrap(),
+        vec![(0, "$Label1".to_owned()), (1, "Junk".to_owned())]
+    );
+
+    let entry = mdir.write_cur(b"", [Flag::Seen]).unwrap();
+    let path = entry.path().to_str().unwrap().to_owned() + "bz";
+    fs::rename(entry.path(), path).unwrap();
+
+    let entry = mdir.read().unwrap().next().unwrap().unwrap();
+    assert_eq!(entry.keywords().unwrap(), vec!["Junk".to_owned()]);
+
+    fs::write(mdir.path().join("dovecot-keywords"), "junk").unwrap();
+    assert!(mdir.keywords().is_err());
+}
+
+#[test]
+fn set_keyword() {
+    let mdirs = Maildirs::new(tempdir().unwrap().into_path());
+    let mdir = mdirs.create("mdir").unwrap();
+
+    fs::write(mdir.path().join("dovecot-keywords"), "0 $Label1\n").unwrap();
+
+    let mut entry = mdir.write_cur(b"", [Flag::Seen]).unwrap();
+
+    entry.set_keyword("Junk").unwrap();
+    entry.set_keyword("$Label1").unwrap();
+    entry.set_keyword("Junk").unwrap();
+
+    assert!(entry.path().exists());
+    assert_eq!(entry.flags_raw().unwrap(), "Sab");
+    assert_eq!(entry.flags().unwrap(), HashSet::from_iter([Flag::Seen]));
+    assert_eq!(
+        entry.keywords().unwrap(),
+        vec!["$Label1".to_owned(), "Junk".to_owned()]
+    );
+    assert_eq!(
+        mdir.keywords().unwrap(),
+        vec![(0, "$Label1".to_owned()), (1, "Junk".to_owned())]
+    );
+
+    assert!(entry.set_keyword("Not junk").is_err());
+
+    // entries of new cannot have flags, so they move to cur
+    let mut entry = mdir.write_new(b"").unwrap();
+    let id = entry.id().unwrap().to_owned();
+    let path = entry.path().to_owned();
+    entry.set_keyword("Junk").unwrap();
+
+    assert!(!path.exists());
+    assert!(entry.path().exists());
+    assert!(!entry.is_new());
+    assert_eq!(entry.path().parent(), Some(mdir.cur()));
+    assert_eq!(entry.id().unwrap(), id);
+    assert_eq!(entry.flags_raw().unwrap(), "b");
+    assert_eq!(mdir.repair().unwrap(), Default::default());
+}
+
+#[test]
+fn exhaust_keywords() {
+    let mdirs = Maildirs::new(tempdir().unwrap().into_path());
+    let mdir = mdirs.create("mdir").unwrap();
+
+    let mut entry = mdir.write_cur(b"", []).unwrap();
+
+    for i in 0..26 {
+        entry.set_keyword(format!("Keyword{i}")).unwrap();
+    }
+
+    assert_eq!(entry.flags_raw().unwrap(), "abcdefghijklmnopqrstuvwxyz");
+    assert!(entry.set_keyword("Keyword26").is_err());
+}
+
+#[test]
+fn set_keywords_concurrently() {
+    let mdirs = Maildirs::new(tempdir().unwrap().into_path());
+    let mdir = mdirs.create("mdir").unwrap();
+    let barrier = Barrier::new(8);
+
+    // every writer allocates its own keyword at the same time
+    let letters: HashSet<String> = thread::scope(|scope| {
+        let writers: Vec<_> = (0..8)
+            .map(|i| {
+                let (mdir, barrier) = (&mdir, &barrier);
+                scope.spawn(move || {
+                    let mut entry = mdir.write_cur(b"", []).unwrap();
+                    barrier.wait();
+                    entry.set_keyword(format!("Keyword{i}")).unwrap();
+                    entry.flags_raw().unwrap().to_owned()
+                })
+            })
+            .collect();
+
+        writers.into_iter().map(|w| w.join().unwrap()).collect()
+    });
+
+    // no keyword is lost, and every keyword gets its own letter
+    assert_eq!(letters.len(), 8);
+    assert_eq!(mdir.keywords().unwrap().len(), 8);
+}
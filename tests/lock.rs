@@ -0,0 +1,20 @@
+use maildirs::Maildir;
+use tempfile::tempdir;
+
+#[test]
+fn lock() {
+    let mdir = Maildir::from(tempdir().unwrap().into_path());
+    mdir.create_all().unwrap();
+
+    let lock = mdir.lock().unwrap();
+    assert_eq!(lock.path(), mdir.path().join(".lock"));
+    assert!(mdir.try_lock().unwrap().is_none());
+
+    drop(lock);
+    let lock = mdir.try_lock().unwrap();
+    assert!(lock.is_some());
+    assert!(mdir.try_lock().unwrap().is_none());
+
+    drop(lock);
+    assert!(mdir.lock().is_ok());
+}
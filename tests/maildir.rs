@@ -1,4 +1,13 @@
-use maildirs::Maildir;
+use std::{
+    collections::HashSet,
+    fs, thread,
+    time::{Duration, SystemTime},
+};
+
+use maildirs::{
+    DuplicatePolicy, Error, Flag, Maildir, MaildirBuilder, MaildirDir, MaildirEntries,
+    MaildirEntriesExt, Maildirs,
+};
 use tempfile::tempdir;
 
 #[test]
@@ -12,6 +21,69 @@ fn create() {
     assert_eq!(mdir.create_all().is_ok(), true);
 }
 
+#[test]
+fn open() {
+    let root = tempdir().unwrap().into_path().join("mdir");
+    assert!(matches!(
+        Maildir::open(&root),
+        Err(Error::ReadMaildirError(_))
+    ));
+
+    let mdir = Maildir::open_or_create(&root).unwrap();
+    assert!(mdir.exists());
+    let entry = mdir.write_new(b"data").unwrap();
+
+    // opening an existing Maildir keeps its entries
+    let mdir = Maildir::open_or_create(&root).unwrap();
+    assert_eq!(mdir.get(entry.id().unwrap()).unwrap(), entry);
+    let mdir = Maildir::open(&root).unwrap();
+    assert_eq!(mdir.count().unwrap(), 1);
+}
+
+#[test]
+fn from_existing() {
+    let root = tempdir().unwrap().into_path().join("mdir");
+    assert!(matches!(
+        Maildir::from_existing(&root),
+        Err(Error::ReadMaildirError(_))
+    ));
+
+    // a directory missing tmp is not a Maildir
+    fs::create_dir_all(root.join("cur")).unwrap();
+    fs::create_dir_all(root.join("new")).unwrap();
+    assert!(matches!(
+        Maildir::from_existing(&root),
+        Err(Error::ReadMaildirError(_))
+    ));
+
+    fs::write(root.join("tmp"), b"").unwrap();
+    assert!(Maildir::from_existing(&root).is_err());
+
+    fs::remove_file(root.join("tmp")).unwrap();
+    fs::create_dir(root.join("tmp")).unwrap();
+    let mdir = Maildir::from_existing(&root).unwrap();
+    assert_eq!(mdir, Maildir::from(&root));
+}
+
+#[test]
+fn clone() {
+    let mdir = Maildir::from(tempdir().unwrap().into_path());
+    let clone = mdir.clone();
+    assert_eq!(clone, mdir);
+    assert!(HashSet::from([mdir.clone()]).contains(&clone));
+
+    // setters do not affect clones
+    let clone = clone.with_size_field(true);
+    assert_ne!(clone, mdir);
+    assert_eq!(clone.path(), mdir.path());
+
+    clone.create_all().unwrap();
+    let entry = clone.write_new(b"data").unwrap();
+    assert!(entry.size_hint().unwrap().is_some());
+    let entry = mdir.write_new(b"data").unwrap();
+    assert!(entry.size_hint().unwrap().is_none());
+}
+
 #[test]
 fn remove() {
     let mdir = Maildir::from(tempdir().unwrap().into_path());
@@ -22,3 +94,820 @@ fn remove() {
     mdir.remove_all().unwrap();
     assert_eq!(mdir.exists(), false);
 }
+
+#[test]
+fn info_separator() {
+    let mut mdir = Maildir::from(tempdir().unwrap().into_path());
+
+    assert!(mdir.set_info_separator(":").is_ok());
+    assert!(mdir.set_info_separator(";").is_ok());
+
+    assert!(mdir.set_info_separator("").is_err());
+    assert!(mdir.set_info_separator("/").is_err());
+    assert!(mdir.set_info_separator(".").is_err());
+    assert!(mdir.set_info_separator("\0").is_err());
+    assert!(mdir.set_info_separator("::").is_err());
+
+    assert!(MaildirBuilder::new().with_info_separator("/").is_err());
+    assert!(Maildirs::new(mdir.path()).with_info_separator(";").is_ok());
+
+    // separators can be loaded at runtime
+    let sep = String::from("!");
+    assert!(mdir.set_info_separator(String::from("/")).is_err());
+    assert!(mdir.set_info_separator(sep.clone()).is_ok());
+
+    mdir.create_all().unwrap();
+    let entry = mdir.write_cur(b"data", [Flag::Seen]).unwrap();
+    assert!(entry.file_name().unwrap().ends_with(&format!("{sep}2,S")));
+    assert_eq!(entry.flags().unwrap(), HashSet::from_iter([Flag::Seen]));
+
+    let mdirs = Maildirs::new(mdir.path()).with_info_separator(sep).unwrap();
+    assert!(mdirs.create("mdir").is_ok());
+}
+
+#[test]
+fn count() {
+    let mdir = Maildir::from(tempdir().unwrap().into_path());
+    mdir.create_all().unwrap();
+    assert_eq!(mdir.count().unwrap(), 0);
+
+    mdir.write_new(b"data").unwrap();
+    mdir.write_new(b"data").unwrap();
+    mdir.write_cur(b"data", None).unwrap();
+    fs::write(mdir.cur().join(".dotfile"), b"data").unwrap();
+    fs::create_dir(mdir.cur().join("dir")).unwrap();
+
+    assert_eq!(mdir.count_new().unwrap(), 2);
+    assert_eq!(mdir.count_cur().unwrap(), 1);
+    assert_eq!(mdir.count().unwrap(), 3);
+    assert_eq!(mdir.count().unwrap(), mdir.read().unwrap().count());
+
+    // counting does not move new entries to cur
+    assert_eq!(mdir.count_new().unwrap(), 2);
+}
+
+#[test]
+fn size_on_disk() {
+    let mdir = Maildir::from(tempdir().unwrap().into_path());
+    mdir.create_all().unwrap();
+    assert_eq!(mdir.size_on_disk().unwrap(), 0);
+
+    mdir.write_new(b"data").unwrap();
+    mdir.write_cur(b"message", None).unwrap();
+    fs::write(mdir.tmp().join("tmp"), b"in progress").unwrap();
+    fs::write(mdir.cur().join(".dotfile"), b"data").unwrap();
+
+    assert_eq!(mdir.size_on_disk().unwrap(), 11);
+
+    // the size field is preferred over the actual size
+    fs::write(mdir.cur().join("1700000000.a.localhost,S=100:2,"), b"data").unwrap();
+    assert_eq!(mdir.size_on_disk().unwrap(), 111);
+
+    let dirs = [MaildirDir::New, MaildirDir::Cur, MaildirDir::Tmp];
+    assert_eq!(mdir.size_on_disk_dirs(&dirs).unwrap(), 122);
+    assert_eq!(mdir.size_on_disk_dirs(&[MaildirDir::New]).unwrap(), 4);
+}
+
+#[test]
+fn remove_partially_created() {
+    let mdir = Maildir::from(tempdir().unwrap().into_path());
+    mdir.create_all().unwrap();
+    fs::remove_dir(mdir.new()).unwrap();
+
+    mdir.remove().unwrap();
+    assert!(!mdir.cur().exists());
+    assert!(!mdir.tmp().exists());
+    assert!(mdir.path().exists());
+
+    // errors name the directory that cannot be removed
+    fs::write(mdir.cur(), "").unwrap();
+
+    match mdir.remove().unwrap_err() {
+        Error::RemoveMaildirError(_, path) => assert_eq!(path, mdir.cur()),
+        err => panic!("unexpected error {err}"),
+    }
+}
+
+#[test]
+fn read() {
+    struct Paginator {
+        entries: MaildirEntries,
+        page_size: usize,
+    }
+
+    impl Paginator {
+        fn next_page(&mut self) -> Vec<Vec<u8>> {
+            (&mut self.entries)
+                .take(self.page_size)
+                .map(|entry| entry.unwrap().read().unwrap())
+                .collect()
+        }
+    }
+
+    let mdir = Maildir::from(tempdir().unwrap().into_path());
+    mdir.create_all().unwrap();
+    mdir.write_new(b"data").unwrap();
+    mdir.write_new(b"data").unwrap();
+    mdir.write_cur(b"data", None).unwrap();
+
+    let mut paginator = Paginator {
+        entries: mdir.read().unwrap(),
+        page_size: 2,
+    };
+
+    assert_eq!(paginator.next_page().len(), 2);
+    assert_eq!(paginator.next_page(), vec![b"data".to_vec()]);
+    assert!(paginator.next_page().is_empty());
+}
+
+#[test]
+fn iterate_over_entries() {
+    let mdir = Maildir::from(tempdir().unwrap().into_path());
+    mdir.create_all().unwrap();
+    mdir.write_new(b"new").unwrap();
+    mdir.write_cur(b"cur", None).unwrap();
+
+    let mut count = 0;
+
+    for entry in &mdir {
+        assert!(entry.is_ok());
+        count += 1;
+    }
+
+    assert_eq!(count, 2);
+
+    // errors preventing the Maildir from being read are yielded
+    mdir.remove_all().unwrap();
+
+    let mut entries = mdir.into_iter();
+    assert!(entries.next().unwrap().is_err());
+    assert!(entries.next().is_none());
+}
+
+#[test]
+fn read_tmp() {
+    let mdir = Maildir::from(tempdir().unwrap().into_path());
+    mdir.create_all().unwrap();
+    mdir.write_new(b"new").unwrap();
+    mdir.write_cur(b"cur", [Flag::Seen]).unwrap();
+    let staged = mdir.stage(b"tmp", [Flag::Seen]).unwrap();
+
+    assert_eq!(mdir.read().unwrap().count(), 2);
+    assert_eq!(mdir.read_all().unwrap().count(), 3);
+
+    let entries: Vec<_> = mdir.read_dirs(&[MaildirDir::Tmp]).unwrap().collect();
+    assert_eq!(entries.len(), 1);
+
+    let entry = entries[0].as_ref().unwrap();
+    assert_eq!(entry.path(), staged.path());
+    assert_eq!(entry.read().unwrap(), b"tmp");
+    assert!(entry.flags().unwrap().is_empty());
+
+    assert_eq!(mdir.read_dirs(&[]).unwrap().count(), 0);
+}
+
+#[test]
+fn dir_paths() {
+    let mdir = Maildir::from(tempdir().unwrap().into_path());
+
+    for (dir, path) in [
+        (MaildirDir::New, mdir.new()),
+        (MaildirDir::Cur, mdir.cur()),
+        (MaildirDir::Tmp, mdir.tmp()),
+    ] {
+        assert_eq!(mdir.dir_path(dir), path);
+        assert_eq!(mdir.dir_path(dir), mdir.path().join(dir.as_str()));
+    }
+
+    assert_eq!(MaildirDir::New.as_str(), "new");
+    assert_eq!(MaildirDir::Cur.as_str(), "cur");
+    assert_eq!(MaildirDir::Tmp.as_str(), "tmp");
+}
+
+#[test]
+fn read_new_with_broken_cur() {
+    let mdir = Maildir::from(tempdir().unwrap().into_path());
+    mdir.create_all().unwrap();
+    mdir.write_new(b"data").unwrap();
+    mdir.write_new(b"data").unwrap();
+
+    // cur is only opened once new is exhausted
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        fs::set_permissions(mdir.cur(), fs::Permissions::from_mode(0o000)).unwrap();
+        let privileged = fs::read_dir(mdir.cur()).is_ok();
+
+        if !privileged {
+            let entries = mdir.read().unwrap().take(2);
+            assert!(entries
+                .map(Result::unwrap)
+                .all(|e| e.path().starts_with(mdir.new())));
+            assert!(mdir.read().unwrap().nth(2).unwrap().is_err());
+        }
+
+        fs::set_permissions(mdir.cur(), fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    fs::remove_dir(mdir.cur()).unwrap();
+
+    let entries = mdir.read().unwrap().take(2);
+    assert!(entries
+        .map(Result::unwrap)
+        .all(|e| e.path().starts_with(mdir.new())));
+    assert!(mdir.read().unwrap().nth(2).unwrap().is_err());
+    assert!(mdir.read().unwrap().nth(3).is_none());
+}
+
+#[test]
+fn generate_ids_without_separators() {
+    let mdir = Maildir::from(tempdir().unwrap().into_path());
+    mdir.create_all().unwrap();
+
+    let entry = mdir.write_cur(b"data", None).unwrap();
+    let id = entry.id().unwrap();
+
+    assert!(!id.contains(['/', ':', ';']));
+    assert_eq!(mdir.find(id).unwrap().unwrap().path(), entry.path());
+}
+
+#[test]
+fn clean_tmp_on_write_failure() {
+    let mdir = Maildir::from(tempdir().unwrap().into_path());
+    mdir.create_all().unwrap();
+
+    // the final rename fails once the tmp file has been written
+    fs::remove_dir(mdir.new()).unwrap();
+    fs::remove_dir(mdir.cur()).unwrap();
+
+    assert!(mdir.write_new(b"data").is_err());
+    assert!(mdir.write_cur(b"data", None).is_err());
+    assert_eq!(fs::read_dir(mdir.tmp()).unwrap().count(), 0);
+
+    let src = mdir.path().join("src");
+    fs::write(&src, b"data").unwrap();
+    assert!(mdir.deliver_file(&src, None, true).is_err());
+    assert_eq!(fs::read_dir(mdir.tmp()).unwrap().count(), 0);
+    assert!(src.exists());
+}
+
+#[test]
+fn write_concurrently() {
+    let mdir = Maildir::from(tempdir().unwrap().into_path());
+    mdir.create_all().unwrap();
+
+    thread::scope(|scope| {
+        for _ in 0..8 {
+            scope.spawn(|| {
+                for _ in 0..50 {
+                    mdir.write_new(b"data").unwrap();
+                }
+            });
+        }
+    });
+
+    assert_eq!(mdir.count_new().unwrap(), 400);
+    assert_eq!(fs::read_dir(mdir.tmp()).unwrap().count(), 0);
+}
+
+#[test]
+fn stable_order() {
+    let mdir = MaildirBuilder::new()
+        .with_stable_order(true)
+        .build(tempdir().unwrap().into_path());
+    mdir.create_all().unwrap();
+
+    for id in ["c", "a", "e"] {
+        mdir.write_new_with_id(b"data", id).unwrap();
+    }
+
+    for id in ["d", "b"] {
+        mdir.write_cur_with_id(b"data", None, id).unwrap();
+    }
+
+    let read_ids = || {
+        mdir.read()
+            .unwrap()
+            .map(|entry| entry.unwrap().id().unwrap().to_owned())
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(read_ids(), vec!["a", "b", "c", "d", "e"]);
+    assert_eq!(read_ids(), read_ids());
+}
+
+#[cfg(unix)]
+#[test]
+fn modes() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let root = tempdir().unwrap().into_path().join("mdir");
+    let mdir = MaildirBuilder::new()
+        .with_file_mode(0o640)
+        .with_dir_mode(0o750)
+        .build(root);
+    mdir.create_all().unwrap();
+
+    for dir in [mdir.path(), mdir.cur(), mdir.new(), mdir.tmp()] {
+        let mode = fs::metadata(dir).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o750);
+    }
+
+    let entry = mdir.write_new(b"data").unwrap();
+    let mode = fs::metadata(entry.path()).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o640);
+}
+
+#[cfg(unix)]
+#[test]
+fn owner() {
+    use std::os::unix::fs::{chown, MetadataExt};
+
+    let root = tempdir().unwrap().into_path();
+
+    // changing owners requires privileges
+    let probe = root.join("probe");
+    fs::write(&probe, b"").unwrap();
+    if chown(&probe, Some(1), Some(1)).is_err() {
+        let mdir = MaildirBuilder::new()
+            .with_owner(1, 1)
+            .build(root.join("mdir"));
+        assert!(matches!(
+            mdir.create_all(),
+            Err(Error::ChangeOwnerError(_, _))
+        ));
+        return;
+    }
+
+    let mdir = MaildirBuilder::new()
+        .with_owner(1, 1)
+        .build(root.join("mdir"));
+    mdir.create_all().unwrap();
+
+    for dir in [mdir.path(), mdir.cur(), mdir.new(), mdir.tmp()] {
+        let metadata = fs::metadata(dir).unwrap();
+        assert_eq!((metadata.uid(), metadata.gid()), (1, 1));
+    }
+
+    let entry = mdir.write_new(b"data").unwrap();
+    let metadata = fs::metadata(entry.path()).unwrap();
+    assert_eq!((metadata.uid(), metadata.gid()), (1, 1));
+}
+
+#[test]
+fn move_all_entries() {
+    let root = tempdir().unwrap().into_path();
+    let src = Maildir::from(root.join("src"));
+    let dest = Maildir::from(root.join("dest"));
+    src.create_all().unwrap();
+    dest.create_all().unwrap();
+
+    src.write_new(b"new").unwrap();
+    let seen = src.write_cur(b"seen", [Flag::Seen]).unwrap();
+    src.write_cur(b"flagged", [Flag::Flagged]).unwrap();
+
+    // the destination already holds an entry with the same name
+    fs::copy(seen.path(), dest.cur().join(seen.file_name().unwrap())).unwrap();
+
+    assert_eq!(src.move_all_to(&dest).unwrap(), (1, 2));
+    assert_eq!(src.count().unwrap(), 0);
+    assert_eq!(dest.count_new().unwrap(), 1);
+    assert_eq!(dest.count_cur().unwrap(), 3);
+
+    let mut seen = dest
+        .read()
+        .unwrap()
+        .with_flag(Flag::Seen)
+        .map(Result::unwrap);
+    let (a, b) = (seen.next().unwrap(), seen.next().unwrap());
+    assert_ne!(a.id().unwrap(), b.id().unwrap());
+    assert_eq!(a.read().unwrap(), b"seen");
+    assert_eq!(b.read().unwrap(), b"seen");
+
+    let flagged = dest.read().unwrap().with_flag(Flag::Flagged).count();
+    assert_eq!(flagged, 1);
+
+    assert!(dest.move_all_to(&dest).is_err());
+}
+
+#[test]
+fn move_all_entries_across_info_separators() {
+    let root = tempdir().unwrap().into_path();
+    let src = Maildir::from(root.join("src"))
+        .with_info_separator(";")
+        .unwrap();
+    let dest = Maildir::from(root.join("dest"));
+    src.create_all().unwrap();
+    dest.create_all().unwrap();
+
+    let seen = src.write_cur(b"seen", [Flag::Seen]).unwrap();
+    let flagged = src.write_cur(b"flagged", [Flag::Flagged]).unwrap();
+    assert!(seen.file_name().unwrap().ends_with(";2,S"));
+
+    // the destination already holds an entry with the same name
+    let name = format!("{}:2,S", seen.id().unwrap());
+    fs::write(dest.cur().join(name), b"other").unwrap();
+
+    assert_eq!(src.move_all_to(&dest).unwrap(), (0, 2));
+    assert_eq!(dest.count_cur().unwrap(), 3);
+
+    let path = dest.cur().join(format!("{}:2,F", flagged.id().unwrap()));
+    assert_eq!(fs::read(path).unwrap(), b"flagged");
+
+    let mut seen = dest
+        .read()
+        .unwrap()
+        .with_flag(Flag::Seen)
+        .map(Result::unwrap);
+    let (a, b) = (seen.next().unwrap(), seen.next().unwrap());
+    assert_ne!(a.id().unwrap(), b.id().unwrap());
+    assert!(a.file_name().unwrap().ends_with(":2,S"));
+    assert!(b.file_name().unwrap().ends_with(":2,S"));
+}
+
+#[test]
+fn stage_entries() {
+    let mdir = Maildir::from(tempdir().unwrap().into_path());
+    mdir.create_all().unwrap();
+
+    let staged = mdir.stage(b"new", None).unwrap();
+    assert!(staged.path().starts_with(mdir.tmp()));
+    assert_eq!(mdir.count().unwrap(), 0);
+
+    let entry = staged.commit_new().unwrap();
+    assert!(entry.path().starts_with(mdir.new()));
+    assert_eq!(entry.read().unwrap(), b"new");
+
+    let entry = mdir
+        .stage(b"cur", [Flag::Seen])
+        .unwrap()
+        .commit_cur()
+        .unwrap();
+    assert!(entry.path().starts_with(mdir.cur()));
+    assert!(entry.has_flag(Flag::Seen));
+    assert_eq!(entry.read().unwrap(), b"cur");
+
+    assert_eq!(mdir.count().unwrap(), 2);
+    assert_eq!(fs::read_dir(mdir.tmp()).unwrap().count(), 0);
+
+    // aborted and dropped messages are removed from tmp
+    let staged = mdir.stage(b"aborted", None).unwrap();
+    let path = staged.path().to_owned();
+    assert!(path.exists());
+    staged.abort().unwrap();
+    assert!(!path.exists());
+
+    drop(mdir.stage(b"dropped", None).unwrap());
+
+    assert_eq!(mdir.count().unwrap(), 2);
+    assert_eq!(fs::read_dir(mdir.tmp()).unwrap().count(), 0);
+}
+
+#[test]
+fn clean_tmp_on_build() {
+    let root = tempdir().unwrap().into_path();
+    Maildir::from(&root).create_all().unwrap();
+
+    let write_tmp_file = |name: &str, age: Duration| {
+        let path = root.join("tmp").join(name);
+        let file = fs::File::create(&path).unwrap();
+        file.set_modified(SystemTime::now() - age).unwrap();
+        path
+    };
+
+    let hour = Duration::from_secs(60 * 60);
+    let old = write_tmp_file("old", 37 * hour);
+    let recent = write_tmp_file("recent", 2 * hour);
+
+    MaildirBuilder::new().with_tmp_cleanup(false).build(&root);
+    assert!(old.exists());
+
+    MaildirBuilder::new().build(&root);
+    assert!(!old.exists());
+    assert!(recent.exists());
+
+    MaildirBuilder::new().with_tmp_max_age(hour).build(&root);
+    assert!(!recent.exists());
+}
+
+#[test]
+fn get_and_set_flags_by_id() {
+    let mdirs = Maildirs::new(tempdir().unwrap().into_path());
+    let mdir = mdirs.create("mdir").unwrap();
+    let entry = mdir.write_cur(b"data", [Flag::Seen]).unwrap();
+    let id = entry.id().unwrap();
+
+    assert_eq!(mdir.flags_of(id).unwrap(), HashSet::from_iter([Flag::Seen]));
+
+    mdir.set_flags(id, [Flag::Flagged, Flag::Replied]).unwrap();
+    assert!(!entry.path().exists());
+    assert_eq!(
+        mdir.flags_of(id).unwrap(),
+        HashSet::from_iter([Flag::Flagged, Flag::Replied])
+    );
+
+    assert!(matches!(
+        mdir.flags_of("unknown"),
+        Err(Error::GetMaildirEntryNotFoundError(_))
+    ));
+    assert!(matches!(
+        mdir.set_flags("unknown", []),
+        Err(Error::GetMaildirEntryNotFoundError(_))
+    ));
+}
+
+#[test]
+fn get_many_entries() {
+    let mdir = Maildir::from(tempdir().unwrap().into_path());
+    mdir.create_all().unwrap();
+
+    let ids: Vec<String> = (0..50)
+        .map(|i| {
+            let entry = if i % 2 == 0 {
+                mdir.write_new(b"data").unwrap()
+            } else {
+                mdir.write_cur(b"data", [Flag::Seen]).unwrap()
+            };
+            entry.id().unwrap().to_owned()
+        })
+        .collect();
+
+    let entries = mdir
+        .get_many(&[&ids[42], "missing", &ids[7], &ids[42], "other"])
+        .unwrap();
+
+    let results: Vec<(&str, Option<&str>)> = entries
+        .iter()
+        .map(|(id, entry)| (id.as_str(), entry.as_ref().map(|e| e.id().unwrap())))
+        .collect();
+    assert_eq!(
+        results,
+        vec![
+            (ids[42].as_str(), Some(ids[42].as_str())),
+            ("missing", None),
+            (ids[7].as_str(), Some(ids[7].as_str())),
+            (ids[42].as_str(), Some(ids[42].as_str())),
+            ("other", None),
+        ]
+    );
+
+    assert_eq!(
+        entries[2].1.as_ref().unwrap().path().parent(),
+        Some(mdir.cur())
+    );
+    assert!(mdir.get_many(&[]).unwrap().is_empty());
+}
+
+#[test]
+fn diff() {
+    let root = tempdir().unwrap().into_path();
+    let a = Maildir::from(root.join("a"));
+    let b = Maildir::from(root.join("b"));
+    a.create_all().unwrap();
+    b.create_all().unwrap();
+    assert!(a.diff(&b).unwrap().is_empty());
+
+    a.write_new_with_id(b"", "both").unwrap();
+    b.write_new_with_id(b"", "both").unwrap();
+    a.write_cur_with_id(b"", [Flag::Seen], "same-flags")
+        .unwrap();
+    b.write_cur_with_id(b"", [Flag::Seen], "same-flags")
+        .unwrap();
+    a.write_cur_with_id(b"", [Flag::Seen], "other-flags")
+        .unwrap();
+    b.write_cur_with_id(b"", [Flag::Flagged], "other-flags")
+        .unwrap();
+    a.write_new_with_id(b"", "seen-in-b").unwrap();
+    b.write_cur_with_id(b"", [Flag::Seen], "seen-in-b").unwrap();
+    a.write_new_with_id(b"", "a-2").unwrap();
+    a.write_cur_with_id(b"", None, "a-1").unwrap();
+    b.write_new_with_id(b"", "b").unwrap();
+
+    let diff = a.diff(&b).unwrap();
+    assert_eq!(diff.only_in_self, vec!["a-1", "a-2"]);
+    assert_eq!(diff.only_in_other, vec!["b"]);
+    assert_eq!(diff.in_both_flags_differ, vec!["other-flags", "seen-in-b"]);
+
+    let diff = b.diff(&a).unwrap();
+    assert_eq!(diff.only_in_self, vec!["b"]);
+    assert_eq!(diff.only_in_other, vec!["a-1", "a-2"]);
+    assert!(a.diff(&a).unwrap().is_empty());
+}
+
+#[test]
+fn update_flags_in_bulk() {
+    let mdirs = Maildirs::new(tempdir().unwrap().into_path());
+    let mdir = mdirs.create("mdir").unwrap();
+
+    let mut changes: Vec<(String, HashSet<Flag>)> = (0..100)
+        .map(|i| {
+            let entry = match i % 2 {
+                0 => mdir.write_new(b"data").unwrap(),
+                _ => mdir.write_cur(b"data", [Flag::Draft]).unwrap(),
+            };
+            let id = entry.id().unwrap().to_owned();
+            (id, HashSet::from_iter([Flag::Seen]))
+        })
+        .collect();
+
+    changes.push(("unknown".to_owned(), HashSet::new()));
+
+    let misses = mdir.update_flags_bulk(&changes).unwrap();
+    assert_eq!(misses, vec!["unknown".to_owned()]);
+    assert_eq!(mdir.count().unwrap(), 100);
+
+    for entry in mdir.read().unwrap() {
+        let entry = entry.unwrap();
+        assert_eq!(entry.flags().unwrap(), HashSet::from_iter([Flag::Seen]));
+    }
+
+    // the last change of an id wins
+    let id = changes[0].0.clone();
+    let changes = [
+        (id.clone(), HashSet::from_iter([Flag::Flagged])),
+        (id.clone(), HashSet::from_iter([Flag::Replied])),
+    ];
+
+    assert!(mdir.update_flags_bulk(&changes).unwrap().is_empty());
+    assert_eq!(mdir.count().unwrap(), 100);
+    assert_eq!(
+        mdir.get(&id).unwrap().flags().unwrap(),
+        HashSet::from_iter([Flag::Replied])
+    );
+}
+
+#[test]
+fn purge_trashed_entries() {
+    let mdirs = Maildirs::new(tempdir().unwrap().into_path());
+    let mdir = mdirs.create("mdir").unwrap();
+
+    mdir.write_new(b"new").unwrap();
+    mdir.write_cur(b"trashed", [Flag::Trashed]).unwrap();
+    mdir.write_cur(b"seen and trashed", [Flag::Seen, Flag::Trashed])
+        .unwrap();
+    let seen = mdir.write_cur(b"seen", [Flag::Seen]).unwrap();
+
+    assert_eq!(mdir.purge_trashed().unwrap(), 2);
+    assert_eq!(mdir.count().unwrap(), 2);
+    assert!(seen.path().exists());
+    assert_eq!(mdir.purge_trashed().unwrap(), 0);
+
+    fs::write(mdir.tmp().join("delivery"), b"in progress").unwrap();
+    mdir.empty().unwrap();
+    assert!(mdir.exists());
+    assert_eq!(mdir.count().unwrap(), 0);
+    assert!(mdir.tmp().join("delivery").exists());
+}
+
+#[test]
+fn move_all_new_entries_to_cur() {
+    let mdirs = Maildirs::new(tempdir().unwrap().into_path());
+    let mdir = mdirs.create("mdir").unwrap();
+
+    let mut ids: HashSet<String> = (0..3)
+        .map(|_| mdir.write_new(b"data").unwrap().id().unwrap().to_owned())
+        .collect();
+
+    // an entry of cur already uses the name of the first entry
+    let id = ids.iter().next().unwrap().clone();
+    fs::write(mdir.cur().join(format!("{id}:2,")), b"other data").unwrap();
+    ids.remove(&id);
+
+    let entries = mdir.move_all_new_to_cur().unwrap();
+    assert_eq!(mdir.count_new().unwrap(), 0);
+    assert_eq!(mdir.count_cur().unwrap(), 4);
+    assert_eq!(entries.len(), 3);
+
+    for entry in &entries {
+        assert!(entry.is_cur());
+        assert_eq!(entry.flags_raw().unwrap(), "");
+        assert_eq!(entry.read().unwrap(), b"data");
+        assert!(entry.file_name().unwrap().ends_with(":2,"));
+    }
+
+    let moved_ids: HashSet<String> = entries
+        .iter()
+        .map(|entry| entry.id().unwrap().to_owned())
+        .collect();
+    assert!(moved_ids.is_superset(&ids));
+    assert!(!moved_ids.contains(&id));
+
+    // skipped entries stay in new
+    let mdir = mdir.with_duplicate_policy(DuplicatePolicy::Skip);
+    let id = mdir.write_new(b"data").unwrap().id().unwrap().to_owned();
+    fs::write(mdir.cur().join(format!("{id}:2,")), b"other data").unwrap();
+    assert!(mdir.move_all_new_to_cur().unwrap().is_empty());
+    assert_eq!(mdir.count_new().unwrap(), 1);
+}
+
+#[test]
+fn repair_new_entries_with_info() {
+    let mdirs = Maildirs::new(tempdir().unwrap().into_path());
+    let mdir = mdirs.create("mdir").unwrap();
+
+    let valid = mdir.write_new(b"valid").unwrap();
+    fs::write(mdir.new().join("1.empty.localhost:2,"), b"empty").unwrap();
+    fs::write(mdir.new().join("2.flags.localhost:2,SF"), b"flags").unwrap();
+
+    let report = mdir.repair().unwrap();
+    assert_eq!(
+        report.stripped,
+        vec![(
+            mdir.new().join("1.empty.localhost:2,"),
+            mdir.new().join("1.empty.localhost")
+        )]
+    );
+    assert_eq!(
+        report.moved,
+        vec![(
+            mdir.new().join("2.flags.localhost:2,SF"),
+            mdir.cur().join("2.flags.localhost:2,FS")
+        )]
+    );
+
+    assert!(valid.path().exists());
+    assert_eq!(mdir.count_new().unwrap(), 2);
+    assert_eq!(
+        mdir.flags_of("2.flags.localhost").unwrap(),
+        HashSet::from_iter([Flag::Seen, Flag::Flagged])
+    );
+
+    assert!(mdir.repair().unwrap().is_empty());
+}
+
+#[cfg(unix)]
+#[test]
+fn deliver_file_options() {
+    use std::os::unix::fs::{MetadataExt, PermissionsExt};
+
+    let root = tempdir().unwrap().into_path();
+    let src = root.join("src.eml");
+    fs::write(&src, b"file").unwrap();
+    fs::set_permissions(&src, fs::Permissions::from_mode(0o644)).unwrap();
+    let mtime = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+    fs::File::options()
+        .write(true)
+        .open(&src)
+        .unwrap()
+        .set_modified(mtime)
+        .unwrap();
+
+    let deliver = |builder: MaildirBuilder, name: &str| {
+        let mdir = builder.build(root.join(name));
+        mdir.create_all().unwrap();
+        let entry = mdir.deliver_file(&src, [], false).unwrap();
+        assert_eq!(entry.read().unwrap(), b"file");
+        fs::metadata(entry.path()).unwrap()
+    };
+
+    // hard linked by default, which shares the source inode
+    let meta = deliver(MaildirBuilder::new(), "linked");
+    assert_eq!(meta.ino(), fs::metadata(&src).unwrap().ino());
+    assert_eq!(meta.modified().unwrap(), mtime);
+
+    // copied when the modification time is not preserved
+    let meta = deliver(MaildirBuilder::new().with_preserve_mtime(false), "fresh");
+    assert_ne!(meta.ino(), fs::metadata(&src).unwrap().ino());
+    assert!(meta.modified().unwrap() > mtime);
+
+    // copied with the configured mode, like other deliveries
+    let meta = deliver(MaildirBuilder::new().with_file_mode(0o600), "mode");
+    assert_ne!(meta.ino(), fs::metadata(&src).unwrap().ino());
+    assert_eq!(meta.mode() & 0o777, 0o600);
+    assert_eq!(meta.modified().unwrap(), mtime);
+
+    // the source is left untouched
+    let meta = fs::metadata(&src).unwrap();
+    assert_eq!(meta.mode() & 0o777, 0o644);
+    assert_eq!(meta.modified().unwrap(), mtime);
+}
+
+#[test]
+fn durable_deliveries() {
+    let root = tempdir().unwrap().into_path();
+    let mdir = MaildirBuilder::new()
+        .with_durable(true)
+        .build(root.join("mdir"));
+    mdir.create_all().unwrap();
+
+    let new = mdir.write_new(b"new").unwrap();
+    let cur = mdir.write_cur(b"cur", [Flag::Seen]).unwrap();
+    let staged = mdir.stage(b"staged", []).unwrap().commit_new().unwrap();
+
+    let src = root.join("src.eml");
+    fs::write(&src, b"file").unwrap();
+    let delivered = mdir.deliver_file(&src, [], false).unwrap();
+
+    for (entry, contents) in [
+        (new, b"new".as_slice()),
+        (cur, b"cur"),
+        (staged, b"staged"),
+        (delivered, b"file"),
+    ] {
+        assert_eq!(entry.read().unwrap(), contents);
+    }
+
+    assert_eq!(mdir.count().unwrap(), 4);
+    assert_eq!(fs::read_dir(mdir.tmp()).unwrap().count(), 0);
+}
@@ -1,6 +1,6 @@
 use std::{collections::HashSet, fs};
 
-use maildirs::{Maildir, Maildirs, MaildirsEntry};
+use maildirs::{Error, Maildir, Maildirs, MaildirsEntry};
 use tempfile::tempdir;
 
 #[test]
@@ -34,6 +34,28 @@ fn get() {
     assert_eq!(subdir.path(), mdirs.path().join(".Subdir").join(".Subdir"));
 }
 
+#[test]
+fn get_inbox() {
+    let mdirs = Maildirs::new(tempdir().unwrap().path()).with_maildirpp(true);
+    Maildir::from(mdirs.path()).create_all().unwrap();
+    mdirs.create("sent").unwrap();
+    mdirs.create("Sent").unwrap();
+
+    assert_eq!(mdirs.get("INBOX").unwrap().path(), mdirs.path());
+    assert_eq!(mdirs.get("inbox").unwrap(), mdirs.get("InBox").unwrap());
+
+    // other names remain case-sensitive
+    assert_eq!(
+        mdirs.get("sent").unwrap().path(),
+        mdirs.path().join(".sent")
+    );
+    assert_eq!(
+        mdirs.get("Sent").unwrap().path(),
+        mdirs.path().join(".Sent")
+    );
+    assert!(mdirs.get("SENT").is_err());
+}
+
 #[test]
 fn iter() {
     let mdirs = Maildirs::new(tempdir().unwrap().path()).with_maildirpp(true);
@@ -43,26 +65,49 @@ fn iter() {
     fs::create_dir(mdirs.path().join(".dot-no-maildir")).unwrap();
     fs::create_dir(mdirs.path().join("no-dot-no-maildir")).unwrap();
 
-    // it should not list missing inbox
+    // it should not list missing inbox, but should list containers
     let expected_mdirs = HashSet::from_iter([
         MaildirsEntry {
             maildirpp: true,
+            hierarchy_separator: '/',
             maildir: Maildir::from(mdirs.path().join(".Subdir")),
             name: "Subdir".into(),
+            selectable: true,
         },
         MaildirsEntry {
             maildirpp: true,
+            hierarchy_separator: '/',
             maildir: Maildir::from(mdirs.path().join(".Subdir/.Subdir")),
             name: "Subdir/Subdir".into(),
+            selectable: true,
         },
         MaildirsEntry {
             maildirpp: true,
+            hierarchy_separator: '/',
             maildir: Maildir::from(mdirs.path().join(".A").join(".B").join(".C")),
             name: "A/B/C".into(),
+            selectable: true,
+        },
+        MaildirsEntry {
+            maildirpp: true,
+            hierarchy_separator: '/',
+            maildir: Maildir::from(mdirs.path().join(".A")),
+            name: "A".into(),
+            selectable: false,
+        },
+        MaildirsEntry {
+            maildirpp: true,
+            hierarchy_separator: '/',
+            maildir: Maildir::from(mdirs.path().join(".A").join(".B")),
+            name: "A/B".into(),
+            selectable: false,
         },
     ]);
 
-    assert_eq!(mdirs.iter().collect::<HashSet<_>>(), expected_mdirs);
+    assert_eq!(
+        mdirs.iter().map(Result::unwrap).collect::<HashSet<_>>(),
+        expected_mdirs
+    );
 
     // create the inbox, then check that it is listed properly
     Maildir::from(mdirs.path()).create_all().unwrap();
@@ -70,6 +115,7 @@ fn iter() {
     let expected_mdirs = HashSet::from_iter([
         MaildirsEntry {
             maildirpp: true,
+            hierarchy_separator: '/',
             maildir: Maildir::from(mdirs.path()),
             name: mdirs
                 .path()
@@ -77,25 +123,49 @@ fn iter() {
                 .unwrap()
                 .to_string_lossy()
                 .to_string(),
+            selectable: true,
         },
         MaildirsEntry {
             maildirpp: true,
+            hierarchy_separator: '/',
             maildir: Maildir::from(mdirs.path().join(".Subdir")),
             name: "Subdir".into(),
+            selectable: true,
         },
         MaildirsEntry {
             maildirpp: true,
+            hierarchy_separator: '/',
             maildir: Maildir::from(mdirs.path().join(".Subdir/.Subdir")),
             name: "Subdir/Subdir".into(),
+            selectable: true,
         },
         MaildirsEntry {
             maildirpp: true,
+            hierarchy_separator: '/',
             maildir: Maildir::from(mdirs.path().join(".A").join(".B").join(".C")),
             name: "A/B/C".into(),
+            selectable: true,
+        },
+        MaildirsEntry {
+            maildirpp: true,
+            hierarchy_separator: '/',
+            maildir: Maildir::from(mdirs.path().join(".A")),
+            name: "A".into(),
+            selectable: false,
+        },
+        MaildirsEntry {
+            maildirpp: true,
+            hierarchy_separator: '/',
+            maildir: Maildir::from(mdirs.path().join(".A").join(".B")),
+            name: "A/B".into(),
+            selectable: false,
         },
     ]);
 
-    assert_eq!(mdirs.iter().collect::<HashSet<_>>(), expected_mdirs);
+    assert_eq!(
+        mdirs.iter().map(Result::unwrap).collect::<HashSet<_>>(),
+        expected_mdirs
+    );
 }
 
 #[test]
@@ -107,29 +177,230 @@ fn remove() {
     let expected_mdirs = HashSet::from_iter([
         MaildirsEntry {
             maildirpp: true,
+            hierarchy_separator: '/',
             maildir: Maildir::from(mdirs.path().join(".Subdir")),
             name: "Subdir".into(),
+            selectable: true,
         },
         MaildirsEntry {
             maildirpp: true,
+            hierarchy_separator: '/',
             maildir: Maildir::from(mdirs.path().join(".Subdir/.Subdir")),
             name: "Subdir/Subdir".into(),
+            selectable: true,
         },
     ]);
 
-    assert_eq!(mdirs.iter().collect::<HashSet<_>>(), expected_mdirs);
+    assert_eq!(
+        mdirs.iter().map(Result::unwrap).collect::<HashSet<_>>(),
+        expected_mdirs
+    );
 
     mdirs.remove("Subdir/.Subdir").unwrap();
 
     let expected_mdirs = HashSet::from_iter([MaildirsEntry {
         maildirpp: true,
+        hierarchy_separator: '/',
         maildir: Maildir::from(mdirs.path().join(".Subdir")),
         name: "Subdir".into(),
+        selectable: true,
     }]);
 
-    assert_eq!(mdirs.iter().collect::<HashSet<_>>(), expected_mdirs);
+    assert_eq!(
+        mdirs.iter().map(Result::unwrap).collect::<HashSet<_>>(),
+        expected_mdirs
+    );
 
     mdirs.remove("..Subdir").unwrap();
 
-    assert_eq!(mdirs.iter().collect::<HashSet<_>>(), HashSet::default());
+    assert_eq!(
+        mdirs.iter().map(Result::unwrap).collect::<HashSet<_>>(),
+        HashSet::default()
+    );
+}
+
+#[test]
+fn subfolders() {
+    let mdir = Maildir::from(tempdir().unwrap().path().join("INBOX"));
+    mdir.create_all().unwrap();
+
+    let subfolders = mdir.subfolders();
+    assert_eq!(subfolders.path(), mdir.path());
+
+    let sent = subfolders.create("Sent").unwrap();
+    assert_eq!(sent.path(), mdir.path().join(".Sent"));
+    subfolders.create("Sent/2024").unwrap();
+
+    let expected_mdirs = HashSet::from_iter([
+        MaildirsEntry {
+            maildirpp: true,
+            hierarchy_separator: '/',
+            maildir: Maildir::from(mdir.path().join(".Sent")),
+            name: "Sent".into(),
+            selectable: true,
+        },
+        MaildirsEntry {
+            maildirpp: true,
+            hierarchy_separator: '/',
+            maildir: Maildir::from(mdir.path().join(".Sent").join(".2024")),
+            name: "Sent/2024".into(),
+            selectable: true,
+        },
+    ]);
+
+    assert_eq!(
+        subfolders
+            .iter()
+            .map(Result::unwrap)
+            .collect::<HashSet<_>>(),
+        expected_mdirs
+    );
+}
+
+#[test]
+fn subscriptions() {
+    let mdirs = Maildirs::new(tempdir().unwrap().path()).with_maildirpp(true);
+    mdirs.create("Sent").unwrap();
+    mdirs.create("Archive/2024").unwrap();
+    mdirs.create("Trash").unwrap();
+    assert!(mdirs.subscriptions().unwrap().is_empty());
+
+    mdirs.subscribe("Sent").unwrap();
+    mdirs.subscribe(".Archive/..2024").unwrap();
+    mdirs.subscribe("Trash").unwrap();
+    mdirs.subscribe("Sent").unwrap();
+    mdirs.unsubscribe("Trash").unwrap();
+    mdirs.unsubscribe("Drafts").unwrap();
+
+    // subscriptions survive a reload
+    let mdirs = Maildirs::new(mdirs.path()).with_maildirpp(true);
+    let expected_subscriptions = vec![String::from("Sent"), String::from("Archive/2024")];
+    assert_eq!(mdirs.subscriptions().unwrap(), expected_subscriptions);
+
+    mdirs.unsubscribe("Sent").unwrap();
+    mdirs.unsubscribe("Archive/2024").unwrap();
+    assert!(mdirs.subscriptions().unwrap().is_empty());
+}
+
+#[test]
+fn rename() {
+    let mdirs = Maildirs::new(tempdir().unwrap().path()).with_maildirpp(true);
+    mdirs.create("A").unwrap();
+    mdirs.create("A/B/C").unwrap();
+    mdirs.create("D").unwrap();
+
+    let mdir = mdirs.rename("A", "E").unwrap();
+    assert!(mdir.exists());
+    assert_eq!(mdir.path(), mdirs.path().join(".E"));
+    assert!(mdirs.find("A/B/C").is_none());
+    assert_eq!(
+        mdirs.get("E/B/C").unwrap().path(),
+        mdirs.path().join(".E").join(".B").join(".C")
+    );
+
+    let err = mdirs.rename("E", "D").unwrap_err();
+    assert!(matches!(err, Error::AlreadyExistsError(path) if path == mdirs.path().join(".D")));
+    assert!(mdirs.find("E/B/C").is_some());
+
+    // the inbox is the root, which holds all other folders
+    mdirs.create("INBOX").unwrap();
+
+    for (from, to) in [("INBOX", "F"), ("inbox", "F"), ("E", "INBOX")] {
+        let err = mdirs.rename(from, to).unwrap_err();
+        assert!(matches!(err, Error::InvalidFolderError(..)));
+    }
+
+    assert!(mdirs.find("INBOX").is_some());
+    assert!(mdirs.find("E/B/C").is_some());
+    assert!(mdirs.find("F").is_none());
+}
+
+#[test]
+fn create_child() {
+    let mdirs = Maildirs::new(tempdir().unwrap().path()).with_maildirpp(true);
+    let root = mdirs.create("INBOX").unwrap();
+
+    let parent = root.create_child("Parent").unwrap();
+    assert!(parent.exists());
+    assert_eq!(parent.path(), mdirs.path().join(".Parent"));
+    assert!(parent.path().join("maildirfolder").is_file());
+
+    let child = parent.create_child("Child").unwrap();
+    assert!(child.exists());
+    assert_eq!(child.path(), mdirs.path().join(".Parent.Child"));
+    assert!(child.path().join("maildirfolder").is_file());
+
+    assert!(matches!(
+        root.create_child("Parent/Child"),
+        Err(Error::InvalidFolderError(..))
+    ));
+}
+
+#[test]
+fn navigate_hierarchy() {
+    let mdirs = Maildirs::new(tempdir().unwrap().into_path()).with_maildirpp(true);
+    mdirs.create("INBOX").unwrap();
+    mdirs.create("A").unwrap();
+    mdirs.create("A/B").unwrap();
+
+    let entries: Vec<MaildirsEntry> = mdirs.iter().map(Result::unwrap).collect();
+    let root = entries
+        .iter()
+        .find(|entry| entry.maildir.path() == mdirs.path())
+        .unwrap();
+
+    let children: Vec<String> = root
+        .children(&mdirs)
+        .map(|entry| entry.unwrap().name)
+        .collect();
+    assert_eq!(children, vec!["A".to_owned()]);
+
+    let b = entries.iter().find(|entry| entry.name == "A/B").unwrap();
+    assert_eq!(b.parent_name(), Some("A".to_owned()));
+    assert_eq!(b.depth(), 2);
+}
+
+#[test]
+fn hierarchy_separator() {
+    let mdirs = Maildirs::new(tempdir().unwrap().into_path())
+        .with_maildirpp(true)
+        .with_hierarchy_separator('.');
+
+    let b = mdirs.create("A.B").unwrap();
+    assert_eq!(b.path(), mdirs.path().join(".A.B"));
+    mdirs.create("A").unwrap();
+    mdirs.subscribe("A.B").unwrap();
+
+    let names: HashSet<String> = mdirs.iter().map(|entry| entry.unwrap().name).collect();
+    assert_eq!(
+        names,
+        HashSet::from_iter(["A".to_owned(), "A.B".to_owned()])
+    );
+
+    let b = mdirs.iter().map(Result::unwrap).find(|e| e.name == "A.B");
+    assert_eq!(b.unwrap().parent_name(), Some("A".to_owned()));
+
+    for name in ["A..B", ".A", "A.", "A/B"] {
+        assert!(matches!(
+            mdirs.create(name),
+            Err(Error::InvalidFolderError(..))
+        ));
+    }
+
+    mdirs.rename("A", "C").unwrap();
+    assert!(mdirs.path().join(".C").is_dir());
+    assert!(mdirs.path().join(".C.B").is_dir());
+    assert!(!mdirs.path().join(".A.B").exists());
+    assert_eq!(mdirs.subscriptions().unwrap(), vec!["C.B".to_owned()]);
+}
+
+#[test]
+fn create_standard() {
+    let mdirs = Maildirs::new(tempdir().unwrap().into_path()).with_maildirpp(true);
+    let standard = mdirs.create_standard().unwrap();
+
+    assert_eq!(standard[0].path(), mdirs.path());
+    assert_eq!(standard[1].path(), mdirs.path().join(".Sent"));
+    assert!(standard.iter().all(Maildir::exists));
+    assert_eq!(mdirs.iter().count(), 6);
 }
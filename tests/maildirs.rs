@@ -1,6 +1,9 @@
-use std::{collections::HashSet, fs};
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+};
 
-use maildirs::{Maildir, Maildirs, MaildirsEntry};
+use maildirs::{Error, Maildir, Maildirs, MaildirsEntry};
 use tempfile::tempdir;
 
 #[test]
@@ -39,26 +42,125 @@ fn iter() {
     fs::create_dir(mdirs.path().join(".dot-no-maildir")).unwrap();
     fs::create_dir(mdirs.path().join("no-dot-no-maildir")).unwrap();
 
-    // it should not list missing inbox
+    // it should not list missing inbox, but should list containers
     let expected_mdirs = HashSet::from_iter([
         MaildirsEntry {
             maildirpp: false,
+            hierarchy_separator: '/',
             maildir: Maildir::from(mdirs.path().join("Subdir")),
             name: "Subdir".into(),
+            selectable: true,
         },
         MaildirsEntry {
             maildirpp: false,
+            hierarchy_separator: '/',
             maildir: Maildir::from(mdirs.path().join("Subdir/Subdir")),
             name: "Subdir/Subdir".into(),
+            selectable: true,
         },
         MaildirsEntry {
             maildirpp: false,
+            hierarchy_separator: '/',
             maildir: Maildir::from(mdirs.path().join("A").join("B").join("C")),
             name: "A/B/C".into(),
+            selectable: true,
+        },
+        MaildirsEntry {
+            maildirpp: false,
+            hierarchy_separator: '/',
+            maildir: Maildir::from(mdirs.path().join("A")),
+            name: "A".into(),
+            selectable: false,
+        },
+        MaildirsEntry {
+            maildirpp: false,
+            hierarchy_separator: '/',
+            maildir: Maildir::from(mdirs.path().join("A").join("B")),
+            name: "A/B".into(),
+            selectable: false,
         },
     ]);
 
-    assert_eq!(mdirs.iter().collect::<HashSet<_>>(), expected_mdirs);
+    assert_eq!(
+        mdirs.iter().map(Result::unwrap).collect::<HashSet<_>>(),
+        expected_mdirs
+    );
+}
+
+#[test]
+fn iter_containers() {
+    let mdirs = Maildirs::new(tempdir().unwrap().into_path());
+    mdirs.create("A/B/C/D").unwrap();
+    mdirs.create("G").unwrap();
+    fs::create_dir_all(mdirs.path().join("A/E/F")).unwrap();
+    fs::create_dir_all(mdirs.path().join("G/H")).unwrap();
+
+    let list = |mdirs: &Maildirs| -> Vec<(String, bool)> {
+        mdirs
+            .iter()
+            .map(|entry| {
+                let entry = entry.unwrap();
+                (entry.name, entry.selectable)
+            })
+            .collect()
+    };
+
+    // containers come right before their first nested Maildir
+    let entries = list(&mdirs);
+    let position = |name: &str| entries.iter().position(|(n, _)| n == name).unwrap();
+    assert_eq!(entries.len(), 5);
+    assert!(position("A") < position("A/B"));
+    assert!(position("A/B") < position("A/B/C"));
+    assert!(position("A/B/C") < position("A/B/C/D"));
+    assert!(entries.contains(&("A/B/C".into(), false)));
+    assert!(entries.contains(&("A/B/C/D".into(), true)));
+    assert!(entries.contains(&("G".into(), true)));
+
+    // containers at the maximum depth are still detected
+    let mut entries = list(&mdirs.with_max_depth(Some(2)));
+    entries.sort();
+    assert_eq!(
+        entries,
+        vec![
+            ("A".into(), false),
+            ("A/B".into(), false),
+            ("G".into(), true)
+        ]
+    );
+}
+
+#[test]
+fn navigate_hierarchy() {
+    let mdirs = Maildirs::new(tempdir().unwrap().into_path());
+    mdirs.create("A/B/C").unwrap();
+    mdirs.create("A/D").unwrap();
+
+    let entries: HashMap<String, MaildirsEntry> = mdirs
+        .iter()
+        .map(|entry| {
+            let entry = entry.unwrap();
+            (entry.name.clone(), entry)
+        })
+        .collect();
+
+    let a = &entries["A"];
+    assert_eq!(a.parent_name(), None);
+    assert_eq!(a.depth(), 1);
+
+    let c = &entries["A/B/C"];
+    assert_eq!(c.parent_name(), Some("A/B".to_owned()));
+    assert_eq!(c.depth(), 3);
+
+    let children: HashSet<String> = a
+        .children(&mdirs)
+        .map(|entry| entry.unwrap().name)
+        .collect();
+    assert_eq!(
+        children,
+        HashSet::from_iter(["A/B".to_owned(), "A/D".to_owned()])
+    );
+
+    assert_eq!(c.children(&mdirs).count(), 0);
 }
 
 #[test]
@@ -70,29 +172,368 @@ fn remove() {
     let expected_mdirs = HashSet::from_iter([
         MaildirsEntry {
             maildirpp: false,
+            hierarchy_separator: '/',
             maildir: Maildir::from(mdirs.path().join("Subdir")),
             name: "Subdir".into(),
+            selectable: true,
         },
         MaildirsEntry {
             maildirpp: false,
+            hierarchy_separator: '/',
             maildir: Maildir::from(mdirs.path().join("Subdir/Subdir")),
             name: "Subdir/Subdir".into(),
+            selectable: true,
         },
     ]);
 
-    assert_eq!(mdirs.iter().collect::<HashSet<_>>(), expected_mdirs);
+    assert_eq!(
+        mdirs.iter().map(Result::unwrap).collect::<HashSet<_>>(),
+        expected_mdirs
+    );
 
     mdirs.remove("Subdir/Subdir").unwrap();
 
     let expected_mdirs = HashSet::from_iter([MaildirsEntry {
         maildirpp: false,
+        hierarchy_separator: '/',
         maildir: Maildir::from(mdirs.path().join("Subdir")),
         name: "Subdir".into(),
+        selectable: true,
     }]);
 
-    assert_eq!(mdirs.iter().collect::<HashSet<_>>(), expected_mdirs);
+    assert_eq!(
+        mdirs.iter().map(Result::unwrap).collect::<HashSet<_>>(),
+        expected_mdirs
+    );
 
     mdirs.remove("Subdir").unwrap();
 
-    assert_eq!(mdirs.iter().collect::<HashSet<_>>(), HashSet::default());
+    assert_eq!(
+        mdirs.iter().map(Result::unwrap).collect::<HashSet<_>>(),
+        HashSet::default()
+    );
+}
+
+#[test]
+fn rename() {
+    let mdirs = Maildirs::new(tempdir().unwrap().path()).with_maildirpp(false);
+    mdirs.create("A").unwrap();
+    mdirs.create("A/B").unwrap();
+    mdirs.create("C").unwrap();
+    fs::write(mdirs.path().join("subscriptions"), "A\nA/B\nAB\nC\n").unwrap();
+
+    let mdir = mdirs.rename("A", "D/E").unwrap();
+    assert!(mdir.exists());
+    assert_eq!(mdir.path(), mdirs.path().join("D").join("E"));
+    assert!(mdirs.find("A").is_none());
+    assert!(mdirs.find("D/E/B").is_some());
+
+    let subscriptions = fs::read_to_string(mdirs.path().join("subscriptions")).unwrap();
+    assert_eq!(subscriptions, "D/E\nD/E/B\nAB\nC\n");
+
+    // two levels of nested folders are moved along
+    mdirs.create("D/E/B/F").unwrap();
+    mdirs.rename("D", "G").unwrap();
+    assert!(mdirs.find("G/E/B/F").is_some());
+    assert!(mdirs.find("D/E/B/F").is_none());
+
+    let err = mdirs.rename("G", "C").unwrap_err();
+    assert!(matches!(err, Error::AlreadyExistsError(path) if path == mdirs.path().join("C")));
+    assert!(mdirs.find("G/E").is_some());
+}
+
+#[test]
+fn create_reserved() {
+    let mdirs = Maildirs::new(tempdir().unwrap().path()).with_maildirpp(false);
+
+    for name in ["cur", "new", "tmp", "Subdir/cur", "cur/Subdir"] {
+        let err = mdirs.create(name).unwrap_err();
+        assert!(matches!(err, Error::InvalidFolderError(n, _) if n == name));
+        assert!(mdirs.get(name).is_err());
+    }
+
+    assert!(mdirs.create("current").is_ok());
+
+    // Maildir++ folders are prefixed by a dot, so they cannot collide
+    let mdirs = mdirs.with_maildirpp(true);
+    let mdir = mdirs.create("cur").unwrap();
+    assert_eq!(mdir.path(), mdirs.path().join(".cur"));
+}
+
+#[test]
+fn create_root() {
+    let mdirs = Maildirs::new(tempdir().unwrap().path().join("root"));
+    assert!(!mdirs.exists());
+    assert_eq!(mdirs.iter().map(Result::unwrap).count(), 0);
+
+    mdirs.create_root().unwrap();
+    assert!(mdirs.exists());
+    assert_eq!(mdirs.iter().map(Result::unwrap).count(), 0);
+
+    // creating an existing root is a no-op
+    mdirs.create("Subdir").unwrap();
+    mdirs.create_root().unwrap();
+    assert_eq!(mdirs.iter().map(Result::unwrap).count(), 1);
+}
+
+#[test]
+fn counts() {
+    let mdirs = Maildirs::new(tempdir().unwrap().into_path());
+    assert_eq!(mdirs.total_count().unwrap(), 0);
+
+    let a = mdirs.create("a").unwrap();
+    let b = mdirs.create("a/b").unwrap();
+    mdirs.create("c").unwrap();
+    mdirs.create("container/d").unwrap();
+    a.write_new(b"data").unwrap();
+    a.write_cur(b"data", None).unwrap();
+    b.write_new(b"data").unwrap();
+
+    assert_eq!(mdirs.total_count().unwrap(), 3);
+    assert_eq!(mdirs.total_size_on_disk().unwrap(), 12);
+
+    let expected_counts = HashMap::from_iter([
+        (String::from("a"), 2),
+        (String::from("a/b"), 1),
+        (String::from("c"), 0),
+        (String::from("container/d"), 0),
+    ]);
+    assert_eq!(mdirs.counts().unwrap(), expected_counts);
+
+    // maildirs that cannot be counted are not skipped
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        fs::set_permissions(b.cur(), fs::Permissions::from_mode(0o000)).unwrap();
+        let privileged = fs::read_dir(b.cur()).is_ok();
+
+        if !privileged {
+            assert!(mdirs.total_count().is_err());
+            assert!(mdirs.counts().is_err());
+        }
+
+        fs::set_permissions(b.cur(), fs::Permissions::from_mode(0o755)).unwrap();
+    }
+}
+
+#[test]
+fn entries() {
+    let mdirs = Maildirs::new(tempdir().unwrap().into_path());
+    assert_eq!(mdirs.entries().count(), 0);
+
+    let a = mdirs.create("a").unwrap();
+    let b = mdirs.create("a/b").unwrap();
+    let c = mdirs.create("container/c").unwrap();
+    let a_entry = a.write_new(b"data").unwrap();
+    let b_entry = b.write_cur(b"data", None).unwrap();
+    let c_entry = c.write_new(b"data").unwrap();
+
+    let entries: HashMap<_, _> = mdirs.entries().map(Result::unwrap).collect();
+    let expected_entries = HashMap::from_iter([
+        (String::from("a"), a_entry.clone()),
+        (String::from("a/b"), b_entry),
+        (String::from("container/c"), c_entry),
+    ]);
+    assert_eq!(entries, expected_entries);
+
+    let mdirs = mdirs.with_max_depth(Some(1));
+    let entries: HashMap<_, _> = mdirs.entries().map(Result::unwrap).collect();
+    assert_eq!(entries, HashMap::from_iter([(String::from("a"), a_entry)]));
+}
+
+#[test]
+fn get_inbox() {
+    let mdirs = Maildirs::new(tempdir().unwrap().into_path());
+    mdirs.create("INBOX").unwrap();
+    mdirs.create("sent").unwrap();
+    mdirs.create("Sent").unwrap();
+
+    // without a configured inbox, INBOX is a regular folder
+    assert!(mdirs.get("inbox").is_err());
+
+    let mdirs = mdirs.with_inbox(Some("INBOX".into()));
+    assert_eq!(mdirs.get("inbox").unwrap(), mdirs.get("INBOX").unwrap());
+    assert_eq!(
+        mdirs.get("InBox").unwrap().path(),
+        mdirs.path().join("INBOX")
+    );
+
+    // other names remain case-sensitive
+    assert_ne!(mdirs.get("sent").unwrap(), mdirs.get("Sent").unwrap());
+    assert!(mdirs.get("SENT").is_err());
+}
+
+#[cfg(unix)]
+#[test]
+fn iter_errors() {
+    use std::os::unix::fs::{symlink, PermissionsExt};
+
+    let mdirs = Maildirs::new(tempdir().unwrap().into_path());
+    mdirs.create("a").unwrap();
+    let b = mdirs.create("b").unwrap();
+
+    fs::set_permissions(b.path(), fs::Permissions::from_mode(0o000)).unwrap();
+    let privileged = fs::read_dir(b.path()).is_ok();
+
+    if !privileged {
+        assert!(mdirs.iter().any(|entry| entry.is_err()));
+        assert!(mdirs.total_count().is_err());
+    }
+
+    fs::set_permissions(b.path(), fs::Permissions::from_mode(0o755)).unwrap();
+    assert!(mdirs.iter().all(|entry| entry.is_ok()));
+
+    // broken symlinks are errors as well, when followed
+    symlink(mdirs.path().join("missing"), mdirs.path().join("broken")).unwrap();
+    assert!(mdirs.iter().all(|entry| entry.is_ok()));
+
+    let mdirs = mdirs.with_follow_links(true);
+    let (entries, errors): (Vec<_>, Vec<_>) = mdirs.iter().partition(Result::is_ok);
+    assert_eq!(entries.len(), 2);
+    assert_eq!(errors.len(), 1);
+    assert!(mdirs.entries().any(|entry| entry.is_err()));
+
+    // a missing root is not an error
+    let mdirs = Maildirs::new(mdirs.path().join("missing"));
+    assert_eq!(mdirs.iter().count(), 0);
+}
+
+#[cfg(unix)]
+#[test]
+fn iter_symlinks() {
+    use std::os::unix::fs::symlink;
+
+    let root = tempdir().unwrap().into_path();
+    let mdirs = Maildirs::new(root.join("mdirs"));
+    let a = mdirs.create("a").unwrap();
+    mdirs.create("a/b").unwrap();
+
+    // a cycle, and a link escaping the root
+    symlink(a.path(), a.path().join("b").join("loop")).unwrap();
+    let outside = Maildirs::new(root.join("outside")).create("c").unwrap();
+    symlink(outside.path(), mdirs.path().join("link")).unwrap();
+
+    let names = |mdirs: &Maildirs| {
+        let mut names: Vec<String> = mdirs.iter().map(|entry| entry.unwrap().name).collect();
+        names.sort();
+        names
+    };
+
+    assert_eq!(names(&mdirs), vec!["a", "a/b"]);
+
+    let mdirs = mdirs.with_follow_links(true);
+    assert_eq!(names(&mdirs), vec!["a", "a/b", "link"]);
+    assert_eq!(mdirs.total_count().unwrap(), 0);
+}
+
+#[test]
+fn iter_sorted() {
+    let mdirs = Maildirs::new(tempdir().unwrap().into_path());
+
+    for name in ["a", "B", "A/B", "A-B", "A", "A/B/C", "A/A"] {
+        mdirs.create(name).unwrap();
+    }
+
+    let names: Vec<String> = mdirs
+        .iter_sorted()
+        .unwrap()
+        .into_iter()
+        .map(|entry| entry.name)
+        .collect();
+
+    assert_eq!(names, vec!["A", "A/A", "A/B", "A/B/C", "A-B", "B", "a"]);
+}
+
+#[test]
+fn iter_root() {
+    let mdirs = Maildirs::new(tempdir().unwrap().into_path());
+    Maildir::from(mdirs.path()).create_all().unwrap();
+    mdirs.create("a").unwrap();
+    mdirs.create("a/b").unwrap();
+
+    let names = |mdirs: &Maildirs| -> Vec<String> {
+        let entries = mdirs.iter_sorted().unwrap();
+        assert!(entries.iter().all(|entry| entry.selectable));
+        entries.into_iter().map(|entry| entry.name).collect()
+    };
+
+    // the root is listed under an empty name by default
+    assert_eq!(names(&mdirs), vec!["", "a", "a/b"]);
+    assert_eq!(mdirs.get("").unwrap().path(), mdirs.path());
+
+    let mdirs = mdirs.with_root_name(Some("INBOX".into()));
+    assert_eq!(names(&mdirs), vec!["INBOX", "a", "a/b"]);
+    assert_eq!(mdirs.get("INBOX").unwrap().path(), mdirs.path());
+    assert_eq!(mdirs.get("a/b").unwrap().path(), mdirs.path().join("a/b"));
+}
+
+#[test]
+fn create_child() {
+    let mdirs = Maildirs::new(tempdir().unwrap().path());
+    let parent = mdirs.create("Parent").unwrap();
+
+    let child = parent.create_child("Child").unwrap();
+    assert!(child.exists());
+    assert_eq!(child.path(), mdirs.path().join("Parent").join("Child"));
+    assert!(!child.path().join("maildirfolder").exists());
+
+    assert!(matches!(
+        parent.create_child("cur"),
+        Err(Error::InvalidFolderError(..))
+    ));
+    assert!(matches!(
+        parent.create_child("Child/Nested"),
+        Err(Error::InvalidFolderError(..))
+    ));
+}
+
+#[test]
+fn hierarchy_separator() {
+    let mdirs = Maildirs::new(tempdir().unwrap().into_path()).with_hierarchy_separator('.');
+
+    let c = mdirs.create("A.B.C").unwrap();
+    assert_eq!(c.path(), mdirs.path().join("A").join("B").join("C"));
+    assert_eq!(mdirs.get("A.B.C").unwrap(), c);
+
+    let names: HashSet<String> = mdirs.iter().map(|entry| entry.unwrap().name).collect();
+    assert_eq!(
+        names,
+        HashSet::from_iter(["A".to_owned(), "A.B".to_owned(), "A.B.C".to_owned()])
+    );
+
+    assert!(matches!(
+        mdirs.create("A.cur"),
+        Err(Error::InvalidFolderError(..))
+    ));
+    assert!(matches!(
+        mdirs.create("A/B"),
+        Err(Error::InvalidFolderError(..))
+    ));
+}
+
+#[test]
+fn create_all() {
+    let mdirs = Maildirs::new(tempdir().unwrap().into_path());
+    let existing = mdirs.create("Sent").unwrap();
+    existing.write_new(b"data").unwrap();
+
+    let created = mdirs
+        .create_all(&["Sent", "Work", "Work/Projects"])
+        .unwrap();
+    assert_eq!(created.len(), 3);
+    assert_eq!(created[0], existing);
+    assert_eq!(existing.count().unwrap(), 1);
+    assert!(created.iter().all(Maildir::exists));
+
+    let err = mdirs.create_all(&["Valid", "Invalid/cur"]).unwrap_err();
+    assert!(matches!(&err, Error::CreateMaildirError(_, name) if name == "Invalid/cur"));
+    assert!(mdirs.find("Valid").is_some());
+
+    let standard = mdirs.create_standard().unwrap();
+    let names: Vec<&str> = standard.iter().map(|mdir| mdir.name().unwrap()).collect();
+    assert_eq!(
+        names,
+        vec!["INBOX", "Sent", "Drafts", "Trash", "Archive", "Junk"]
+    );
 }
@@ -0,0 +1,59 @@
+use std::time::{Duration, UNIX_EPOCH};
+
+use maildirs::{Error, Flag, Maildirs};
+use tempfile::tempdir;
+
+#[test]
+fn export_mbox_with_envelope() {
+    let mdirs = Maildirs::new(tempdir().unwrap().into_path());
+    let mdir = mdirs.create("mdir").unwrap();
+
+    let contents = b"From: Alice <alice@localhost>\n\nFrom the start\n>From quoted\nbye";
+    let entry = mdir
+        .deliver_with_envelope(contents, [Flag::Seen], "bounces@localhost")
+        .unwrap();
+    assert_eq!(entry.envelope_sender().unwrap(), Some("bounces@localhost"));
+    assert!(!entry.id().unwrap().contains("bounces"));
+    assert_eq!(entry.flags().unwrap(), [Flag::Seen].into());
+
+    // the envelope sender takes precedence over the From header
+    let from_line = entry.mbox_from_line().unwrap();
+    assert!(from_line.starts_with("From bounces@localhost "));
+
+    let mbox = String::from_utf8(entry.to_mbox().unwrap()).unwrap();
+    let expected_mbox = format!(
+        "{from_line}\nFrom: Alice <alice@localhost>\n\n>From the start\n>>From quoted\nbye\n\n"
+    );
+    assert_eq!(mbox, expected_mbox);
+
+    let res = mdir.deliver_with_envelope(contents, None, "a b@localhost");
+    assert!(matches!(res, Err(Error::InvalidEnvelopeSenderError(_))));
+}
+
+#[test]
+fn export_mbox_without_envelope() {
+    let mdirs = Maildirs::new(tempdir().unwrap().into_path());
+    let mdir = mdirs.create("mdir").unwrap();
+
+    let entry = mdir
+        .write_cur_with_id(b"From: Alice <alice@localhost>\n\nHello", None, "0.id")
+        .unwrap();
+    assert_eq!(entry.envelope_sender().unwrap(), None);
+    assert_eq!(entry.received_date().unwrap(), UNIX_EPOCH);
+    assert_eq!(
+        entry.mbox_from_line().unwrap(),
+        "From alice@localhost Thu Jan  1 00:00:00 1970"
+    );
+
+    let entry = mdir
+        .write_cur_with_id(b"Subject: no sender\n\nHello", None, "951782400.id")
+        .unwrap();
+    assert_eq!(
+        entry.received_date().unwrap(),
+        UNIX_EPOCH + Duration::from_secs(951782400)
+    );
+    assert_eq!(
+        entry.mbox_from_line().unwrap(),
+        "From MAILER-DAEMON Tue Feb 29 00:00:00 2000"
+    );
+}
@@ -0,0 +1,90 @@
+use std::collections::HashSet;
+
+use maildirs::{Flag, Maildirs, OverlaySource};
+use tempfile::tempdir;
+
+#[test]
+fn read_overlay() {
+    let mdirs = Maildirs::new(tempdir().unwrap().into_path());
+    let primary = mdirs.create("primary").unwrap();
+    let shared = mdirs.create("shared").unwrap();
+    primary.write_cur(b"primary", [Flag::Seen]).unwrap();
+    shared.write_new(b"shared").unwrap();
+    shared.write_cur(b"shared", [Flag::Flagged]).unwrap();
+
+    let overlay = primary.with_overlay(shared);
+    let entries: Vec<_> = overlay.read().unwrap().map(Result::unwrap).collect();
+    assert_eq!(entries.len(), 3);
+
+    let sources: Vec<_> = entries.iter().map(|entry| entry.source()).collect();
+    assert_eq!(
+        sources
+            .iter()
+            .filter(|s| **s == OverlaySource::Primary)
+            .count(),
+        1
+    );
+    assert_eq!(
+        sources
+            .iter()
+            .filter(|s| **s == OverlaySource::Overlay)
+            .count(),
+        2
+    );
+
+    for entry in entries {
+        let contents = entry.entry().read().unwrap();
+        match entry.source() {
+            OverlaySource::Primary => assert_eq!(contents, b"primary"),
+            OverlaySource::Overlay => assert_eq!(contents, b"shared"),
+        }
+    }
+}
+
+#[test]
+fn write_overlay() {
+    let mdirs = Maildirs::new(tempdir().unwrap().into_path());
+    let primary = mdirs.create("primary").unwrap();
+    let shared = mdirs.create("shared").unwrap();
+    let shared_entry = shared.write_cur(b"shared", [Flag::Flagged]).unwrap();
+
+    let overlay = primary.with_overlay(shared.clone());
+    overlay.write_new(b"primary").unwrap();
+    overlay.write_cur(b"primary", [Flag::Seen]).unwrap();
+    assert_eq!(primary.read().unwrap().count(), 2);
+    assert_eq!(shared.read().unwrap().count(), 1);
+
+    let mut entry = overlay
+        .read()
+        .unwrap()
+        .map(Result::unwrap)
+        .find(|entry| entry.source() == OverlaySource::Overlay)
+        .unwrap();
+    overlay.update_flags(&mut entry, [Flag::Seen]).unwrap();
+    assert_eq!(entry.flags(), &HashSet::from_iter([Flag::Seen]));
+
+    // the read-only overlay entry is left untouched
+    assert!(shared_entry.path().exists());
+    let expected_flags = HashSet::from_iter([Flag::Flagged]);
+    assert_eq!(shared_entry.flags().unwrap(), expected_flags);
+
+    let entry = overlay
+        .read()
+        .unwrap()
+        .map(Result::unwrap)
+        .find(|entry| entry.source() == OverlaySource::Overlay)
+        .unwrap();
+    assert_eq!(entry.flags(), &HashSet::from_iter([Flag::Seen]));
+
+    let mut entry = overlay
+        .read()
+        .unwrap()
+        .map(Result::unwrap)
+        .find(|entry| {
+            entry.flags().contains(&Flag::Seen) && entry.source() == OverlaySource::Primary
+        })
+        .unwrap();
+    overlay.update_flags(&mut entry, [Flag::Draft]).unwrap();
+    let expected_flags = HashSet::from_iter([Flag::Draft]);
+    assert_eq!(entry.entry().flags().unwrap(), expected_flags);
+}
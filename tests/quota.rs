@@ -0,0 +1,72 @@
+use std::fs;
+
+use maildirs::{Error, MaildirBuilder, Maildirs};
+use tempfile::tempdir;
+
+#[test]
+fn read_quota() {
+    let mdirs = Maildirs::new(tempdir().unwrap().into_path());
+    let mdir = mdirs.create("mdir").unwrap();
+    assert_eq!(mdir.quota().unwrap(), None);
+
+    let path = mdir.path().join("maildirsize");
+    fs::write(&path, "10485760S,1000C\n1024 2\n-512 -1\n").unwrap();
+
+    let quota = mdir.quota().unwrap().unwrap();
+    assert_eq!(quota.size_limit(), Some(10485760));
+    assert_eq!(quota.count_limit(), Some(1000));
+    assert_eq!(quota.size(), 512);
+    assert_eq!(quota.count(), 1);
+    assert!(!quota.is_exceeded());
+
+    fs::write(&path, "1C\n1024 2\n").unwrap();
+    let quota = mdir.quota().unwrap().unwrap();
+    assert_eq!(quota.size_limit(), None);
+    assert!(quota.is_exceeded());
+
+    fs::write(path, "invalid\n").unwrap();
+    assert!(matches!(mdir.quota(), Err(Error::ParseQuotaError(_))));
+}
+
+#[test]
+fn recalculate_quota() {
+    let mdirs = Maildirs::new(tempdir().unwrap().into_path());
+    let mdir = mdirs.create("mdir").unwrap();
+    mdir.write_new(b"new").unwrap();
+    mdir.write_cur(b"cur!", None).unwrap();
+
+    let path = mdir.path().join("maildirsize");
+    fs::write(&path, "100S\n999 999\n").unwrap();
+
+    mdir.recalculate_quota().unwrap();
+    assert_eq!(fs::read_to_string(&path).unwrap(), "100S\n7 2\n");
+
+    let quota = mdir.quota().unwrap().unwrap();
+    assert_eq!(quota.size_limit(), Some(100));
+    assert_eq!(quota.size(), 7);
+    assert_eq!(quota.count(), 2);
+}
+
+#[test]
+fn track_quota() {
+    let root = tempdir().unwrap().into_path();
+    let mdir = MaildirBuilder::new().with_quota_tracking(true).build(root);
+    mdir.create_all().unwrap();
+
+    // nothing is tracked without a maildirsize file
+    mdir.write_new(b"data").unwrap();
+    assert_eq!(mdir.quota().unwrap(), None);
+
+    mdir.recalculate_quota().unwrap();
+    mdir.write_new(b"new").unwrap();
+    mdir.write_cur(b"cur!", None).unwrap();
+
+    let quota = mdir.quota().unwrap().unwrap();
+    assert_eq!(quota.size(), 11);
+    assert_eq!(quota.count(), 3);
+
+    // disabled tracking leaves the file untouched
+    let mdir = mdir.with_quota_tracking(false);
+    mdir.write_new(b"data").unwrap();
+    assert_eq!(mdir.quota().unwrap().unwrap().count(), 3);
+}
@@ -0,0 +1,47 @@
+#![cfg(feature = "tokio")]
+
+use std::{collections::HashSet, fs};
+
+use maildirs::{Flag, Maildirs};
+use tempfile::tempdir;
+
+#[tokio::test]
+async fn write_and_read_async() {
+    let mdirs = Maildirs::new(tempdir().unwrap().into_path());
+    let mdir = mdirs.create("mdir").unwrap();
+
+    let entry = mdir.write_new_async(b"new").await.unwrap();
+    assert!(entry.path().starts_with(mdir.new()));
+    assert_eq!(entry.read_async().await.unwrap(), b"new");
+
+    let entry = mdir.write_cur_async(b"cur", [Flag::Seen]).await.unwrap();
+    assert!(entry.path().starts_with(mdir.cur()));
+    assert_eq!(entry.read_async().await.unwrap(), b"cur");
+    assert_eq!(entry.flags().unwrap(), HashSet::from_iter([Flag::Seen]));
+
+    assert_eq!(mdir.read().unwrap().count(), 2);
+    assert_eq!(fs::read_dir(mdir.tmp()).unwrap().count(), 0);
+}
+
+#[tokio::test]
+async fn clean_tmp_on_async_write_failure() {
+    let mdirs = Maildirs::new(tempdir().unwrap().into_path());
+    let mdir = mdirs.create("mdir").unwrap();
+
+    // the final rename fails once the tmp file has been written
+    fs::remove_dir(mdir.new()).unwrap();
+
+    assert!(mdir.write_new_async(b"new").await.is_err());
+    assert_eq!(fs::read_dir(mdir.tmp()).unwrap().count(), 0);
+}
+
+#[tokio::test]
+async fn write_durably_async() {
+    let mdirs = Maildirs::new(tempdir().unwrap().into_path());
+    let mdir = mdirs.create("mdir").unwrap().with_durable(true);
+
+    let entry = mdir.write_new_async(b"new").await.unwrap();
+    assert_eq!(entry.read_async().await.unwrap(), b"new");
+    let entry = mdir.write_cur_async(b"cur", [Flag::Seen]).await.unwrap();
+    assert_eq!(entry.read_async().await.unwrap(), b"cur");
+}
@@ -0,0 +1,86 @@
+use std::{collections::HashSet, fs, thread};
+
+use maildirs::{Error, Maildirs};
+use tempfile::tempdir;
+
+#[test]
+fn assign_uids() {
+    let mdirs = Maildirs::new(tempdir().unwrap().into_path());
+    let mdir = mdirs.create("mdir").unwrap();
+    let a = mdir.write_new(b"a").unwrap();
+    let b = mdir.write_cur(b"b", None).unwrap();
+
+    let uidlist = mdir.uidlist();
+    assert_eq!(uidlist.uid_of(a.id().unwrap()).unwrap(), None);
+    assert!(!uidlist.path().exists());
+
+    assert_eq!(uidlist.assign_uid(&a).unwrap(), 1);
+    assert_eq!(uidlist.assign_uid(&b).unwrap(), 2);
+    assert_eq!(uidlist.assign_uid(&a).unwrap(), 1);
+    assert_eq!(uidlist.next_uid().unwrap(), 3);
+
+    // uids survive a reload and stay stable after removal
+    let uidvalidity = uidlist.uidvalidity().unwrap();
+    a.remove().unwrap();
+    let uidlist = mdir.uidlist();
+    assert_eq!(uidlist.uidvalidity().unwrap(), uidvalidity);
+    assert_eq!(uidlist.uid_of(a.id().unwrap()).unwrap(), Some(1));
+    assert_eq!(uidlist.uid_of(b.id().unwrap()).unwrap(), Some(2));
+
+    let c = mdir.write_new(b"c").unwrap();
+    assert_eq!(uidlist.assign_uid(&c).unwrap(), 3);
+}
+
+#[test]
+fn assign_uids_concurrently() {
+    let mdirs = Maildirs::new(tempdir().unwrap().into_path());
+    let mdir = mdirs.create("mdir").unwrap();
+    let entries: Vec<_> = (0..100).map(|_| mdir.write_new(b"").unwrap()).collect();
+
+    // both writers assign all entries, in opposite orders
+    let uids: Vec<Vec<u32>> = thread::scope(|scope| {
+        let forward = scope.spawn(|| {
+            let uidlist = mdir.uidlist();
+            entries
+                .iter()
+                .map(|entry| uidlist.assign_uid(entry).unwrap())
+                .collect()
+        });
+        let backward = scope.spawn(|| {
+            let uidlist = mdir.uidlist();
+            let mut uids: Vec<u32> = entries
+                .iter()
+                .rev()
+                .map(|entry| uidlist.assign_uid(entry).unwrap())
+                .collect();
+            uids.reverse();
+            uids
+        });
+
+        vec![forward.join().unwrap(), backward.join().unwrap()]
+    });
+
+    // no update is lost, and both writers agree on every uid
+    assert_eq!(uids[0], uids[1]);
+    assert_eq!(HashSet::<u32>::from_iter(uids[0].clone()).len(), 100);
+
+    let uidlist = mdir.uidlist();
+    assert_eq!(uidlist.next_uid().unwrap(), 101);
+
+    for (entry, uid) in entries.iter().zip(&uids[0]) {
+        assert_eq!(uidlist.uid_of(entry.id().unwrap()).unwrap(), Some(*uid));
+    }
+}
+
+#[test]
+fn parse_invalid_uidlist() {
+    let mdirs = Maildirs::new(tempdir().unwrap().into_path());
+    let mdir = mdirs.create("mdir").unwrap();
+    let uidlist = mdir.uidlist();
+    fs::write(uidlist.path(), "invalid\n").unwrap();
+
+    assert!(matches!(
+        uidlist.uidvalidity(),
+        Err(Error::ParseUidlistError(_))
+    ));
+}
@@ -0,0 +1,46 @@
+use maildirs::{
+    validate::{validate_folder, validate_id},
+    Error,
+};
+
+#[test]
+fn validate_ids() {
+    for id in ["1234.abcd.localhost", "my-id", "my id"] {
+        assert!(validate_id(id).is_ok());
+    }
+
+    for id in [
+        "", ".hidden", "a/b", "a:b", "a;b", "a\0b", "a\nb", " a", "a ",
+    ] {
+        assert!(matches!(validate_id(id), Err(Error::InvalidIdError(_))));
+    }
+}
+
+#[test]
+fn validate_folders() {
+    for folder in ["Inbox", "Sent Items", ".hidden", "a.b"] {
+        assert!(validate_folder(folder).is_ok());
+    }
+
+    for folder in ["", "a/b", "a:b", "a;b", "a\0b", "a\tb", " a", "a "] {
+        assert!(matches!(
+            validate_folder(folder),
+            Err(Error::InvalidFolderError(..))
+        ));
+    }
+}
+
+#[cfg(windows)]
+#[test]
+fn validate_windows_reserved_names() {
+    for name in ["CON", "con", "Aux.txt", "COM1", "lpt9.eml"] {
+        assert!(matches!(validate_id(name), Err(Error::InvalidIdError(_))));
+        assert!(matches!(
+            validate_folder(name),
+            Err(Error::InvalidFolderError(..))
+        ));
+    }
+
+    assert!(validate_id("CONTACT").is_ok());
+    assert!(validate_folder("Console").is_ok());
+}
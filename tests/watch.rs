@@ -0,0 +1,37 @@
+#![cfg(feature = "watch")]
+
+use std::{sync::mpsc, thread, time::Duration};
+
+use maildirs::{Flag, MaildirEvent, Maildirs};
+use tempfile::tempdir;
+
+#[test]
+fn watch_maildir() {
+    let mdirs = Maildirs::new(tempdir().unwrap().into_path());
+    let mdir = mdirs.create("mdir").unwrap();
+
+    let watcher = mdir.watch().unwrap();
+    let (sender, events) = mpsc::channel();
+
+    thread::spawn(move || {
+        for event in watcher {
+            if sender.send(event.unwrap()).is_err() {
+                break;
+            }
+        }
+    });
+
+    let next_event = || events.recv_timeout(Duration::from_secs(5)).unwrap();
+
+    let mut entry = mdir.write_new(b"data").unwrap();
+    assert_eq!(next_event(), MaildirEvent::NewMessage(entry.clone()));
+
+    entry.move_to_cur([Flag::Seen]).unwrap();
+    assert_eq!(next_event(), MaildirEvent::FlagsChanged(entry.clone()));
+
+    entry.insert_flag(Flag::Flagged).unwrap();
+    assert_eq!(next_event(), MaildirEvent::FlagsChanged(entry.clone()));
+
+    entry.remove().unwrap();
+    assert_eq!(next_event(), MaildirEvent::Removed(entry));
+}